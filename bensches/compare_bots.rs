@@ -1 +1,27 @@
+// Purpose: Plays two configurations of the bot against each other, see `engine::match_runner`.
+//
+// cargo bench --bench compare_bots -- [--games <n>] [--concurrency <n>] [--tc <seconds>+<increment>]
+//     [--nodes <n>] [--depth <plies>] [--openings <file.pgn|file.epd>] [--plies <n>]
+//     [--pgn <out.pgn>] [--sprt <elo0>,<elo1>] [--seed <n>]
+//     [--first <option>=<value>...] [--second <option>=<value>...]
+// The options of a contestant are name, threads, hash, evalfile, syzygy and elo, ex:
+//     cargo bench --bench compare_bots -- --games 200 --tc 5+0.05 --first name=nnue evalfile=net.nnue
+// A contestant with an evalfile evaluates with that network, one with an elo plays at that level.
+// This is the same as `chess_bot match`, built with optimizations without asking for them.
 
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    // cargo passes --bench to benchmarks without a harness
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| arg != "--bench")
+        .collect();
+    match chess_bot::cli::play_match(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
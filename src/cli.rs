@@ -0,0 +1,487 @@
+// Purpose: The subcommands of the command line, everything but the GUI.
+// `chess_bot --help` lists them, every subcommand gives its own usage when its arguments
+// are wrong. They print to stdout and return their errors, main reports them on stderr
+// and exits with a failure.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::core::{
+    game::Game,
+    perft,
+    san::{line_to_san, to_san},
+};
+use crate::engine::{
+    bench,
+    book::{Book, BookBuilder},
+    match_runner::{self, Contestant, MatchConfig, Sprt, SprtVerdict},
+    nnue::Network,
+    search::{SearchInfo, SearchLimits, MATE, MATE_BOUND},
+    selfplay::{self, SelfPlayConfig},
+    strength::Strength,
+    tuning::{self, Tuner},
+    Engine,
+};
+
+pub const USAGE: &str = "\
+usage: chess_bot [<command>] [<arguments>]
+
+commands:
+  play [--fen <fen>] [--white human|bot] [--black human|bot]
+       [--clock <minutes>+<seconds>] [--clock-mode fischer|bronstein|delay]
+       [--animation <ms>]
+                      play in a window, the default command
+  uci                 talk UCI to a chess GUI
+  xboard              talk XBoard (CECP) to a chess GUI
+  analyse <fen> [--depth <plies>] [--lines <n>] [--evalfile <file>]
+                      search a position and print what the engine thinks
+  perft <depth> [--fen <fen>] [--divide]
+                      count the positions reachable in <depth> plies
+  bench [<depth>]     search a fixed set of positions, prints the nodes and the speed
+  match [<options>]   play two configurations of the bot against each other
+  book <out.bin> <games.pgn>... [--depth <plies>]
+                      make a polyglot opening book from PGN files
+  tune <positions>... [--out <eval_params.rs>] [--iterations <n>]
+                      tune the classical evaluation on positions with results
+  selfplay <out.txt> [<options>]
+                      let the bot play itself to make positions to tune on
+  help                show this message
+
+commands with required arguments show their options when run without them";
+
+/// analyse <fen> [--depth <plies>] [--lines <n>] [--evalfile <file>]
+/// Searches the position and prints every iteration with its principal variation
+pub fn analyse(args: &[String]) -> Result<(), String> {
+    let mut limits = SearchLimits {
+        depth: Some(12),
+        ..Default::default()
+    };
+    let mut lines = 1;
+    let mut network = None;
+    let mut fen = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().map(|v| v.as_str()).unwrap_or_default();
+        match arg.as_str() {
+            "--depth" => limits.depth = value().parse().ok().or(limits.depth),
+            "--lines" => lines = value().parse().unwrap_or(lines),
+            "--evalfile" => network = Some(value().to_string()),
+            _ => fen.push(arg.as_str()),
+        }
+    }
+    if fen.is_empty() {
+        return Err(
+            "usage: chess_bot analyse <fen> [--depth <plies>] [--lines <n>] [--evalfile <file>]"
+                .to_string(),
+        );
+    }
+    let game = Game::from_fen(&fen.join(" "))
+        .ok_or_else(|| format!("invalid position {}", fen.join(" ")))?;
+
+    let mut engine = Engine::default();
+    if let Some(path) = network {
+        engine
+            .load_network(&path)
+            .map_err(|error| format!("cannot read network {}: {}", path, error))?;
+        engine.set_use_nnue(true);
+    }
+    engine.set_multi_pv(lines);
+    let result = engine.search(&game, &[], &limits, &mut |info| print_line(&game, info));
+    match result.best_move {
+        Some(best_move) => println!("best move {}", to_san(&game.board, game.turn, &best_move)),
+        None => println!("no legal moves"),
+    }
+    Ok(())
+}
+
+/// One iteration of the search for `analyse`, ex: "depth 8  +0.35  12345 nodes  e4 e5 Nf3"
+fn print_line(game: &Game, info: &SearchInfo) {
+    // from white's point of view, like most GUIs show it: "#3" when white mates in 3
+    // and "#-3" when black does
+    let white_score = if game.turn { info.score } else { -info.score };
+    let score = if white_score >= MATE_BOUND {
+        format!("#{}", (MATE - white_score + 1) / 2)
+    } else if white_score <= -MATE_BOUND {
+        format!("#-{}", (MATE + white_score + 1) / 2)
+    } else {
+        format!("{:+.2}", white_score as f64 / 100.0)
+    };
+    let pv = line_to_san(&game.board, game.turn, &info.pv);
+    let line = if info.multi_pv > 1 {
+        format!(" ({})", info.multi_pv)
+    } else {
+        String::new()
+    };
+    println!(
+        "depth {:>2}{}  {:>6}  {:>9} nodes  {}",
+        info.depth,
+        line,
+        score,
+        info.nodes,
+        pv.join(" ")
+    );
+}
+
+/// perft <depth> [--fen <fen>] [--divide]
+/// Counts the positions reachable in `depth` plies, with --divide per legal move
+pub fn perft(args: &[String]) -> Result<(), String> {
+    let mut depth = None;
+    let mut fen = None;
+    let mut divide = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--fen" => fen = iter.next(),
+            "--divide" => divide = true,
+            _ => depth = arg.parse::<u32>().ok(),
+        }
+    }
+    let depth = depth.ok_or("usage: chess_bot perft <depth> [--fen <fen>] [--divide]")?;
+    let game = match fen {
+        Some(fen) => Game::from_fen(fen).ok_or_else(|| format!("invalid position {}", fen))?,
+        None => Game::default(),
+    };
+
+    let start = Instant::now();
+    let nodes = if divide {
+        let moves = perft::divide(&game.board, game.turn, depth);
+        for (r#move, nodes) in &moves {
+            println!("{:?}: {}", r#move, nodes);
+        }
+        moves.iter().map(|(_, nodes)| nodes).sum()
+    } else {
+        perft::perft(&game.board, game.turn, depth)
+    };
+    let time = start.elapsed();
+    println!(
+        "perft {}: {} nodes in {:.2}s, {} nps",
+        depth,
+        nodes,
+        time.as_secs_f64(),
+        (nodes as f64 / time.as_secs_f64().max(1e-3)) as u64
+    );
+    Ok(())
+}
+
+/// bench [<depth>]
+/// Searches the bench positions, the total nodes are a signature of the search
+pub fn bench(args: &[String]) -> Result<(), String> {
+    let depth = match args.first() {
+        Some(depth) => depth
+            .parse()
+            .map_err(|_| "usage: chess_bot bench [<depth>]")?,
+        None => bench::DEFAULT_DEPTH,
+    };
+    let result = bench::run(depth, &mut |fen, nodes| println!("{:>9}  {}", nodes, fen));
+    println!("{} nodes {} nps", result.nodes, result.nps());
+    Ok(())
+}
+
+/// book <out.bin> <games.pgn>... [--depth <plies>]
+/// Creates a polyglot opening book from PGN files
+pub fn make_book(args: &[String]) -> Result<(), String> {
+    let mut depth = 20;
+    let mut files = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--depth" => depth = iter.next().and_then(|d| d.parse().ok()).unwrap_or(depth),
+            _ => files.push(arg),
+        }
+    }
+    let (output, pgn_files) = files
+        .split_first()
+        .ok_or("usage: chess_bot book <out.bin> <games.pgn>... [--depth <plies>]")?;
+    let mut builder = BookBuilder::new(depth);
+    for path in pgn_files {
+        builder
+            .add_pgn_file(path)
+            .map_err(|error| format!("cannot read {}: {}", path, error))?;
+    }
+    let book = builder.build();
+    book.save(output)
+        .map_err(|error| format!("cannot write {}: {}", output, error))?;
+    println!(
+        "{} games, {} entries written to {}",
+        builder.games(),
+        book.len(),
+        output
+    );
+    Ok(())
+}
+
+/// tune <positions> [--out <eval_params.rs>] [--iterations <n>]
+/// Tunes the weights of the classical evaluation on quiet positions labelled with results
+pub fn tune(args: &[String]) -> Result<(), String> {
+    let mut iterations = 2000;
+    let mut output = None;
+    let mut files = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--iterations" => {
+                iterations = iter
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(iterations)
+            }
+            "--out" => output = iter.next(),
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        return Err(
+            "usage: chess_bot tune <positions>... [--out <eval_params.rs>] [--iterations <n>]"
+                .to_string(),
+        );
+    }
+    let mut tuner = Tuner::new();
+    for path in files {
+        tuner
+            .add_file(path)
+            .map_err(|error| format!("cannot read {}: {}", path, error))?;
+    }
+    if tuner.is_empty() {
+        return Err("no positions to tune on".to_string());
+    }
+    let weights = tuning::current_weights();
+    let k = tuner.fit_k(&weights);
+    eprintln!(
+        "{} positions ({} lines skipped), k {:.3}, error {:.6}",
+        tuner.len(),
+        tuner.skipped(),
+        k,
+        tuner.error(&weights)
+    );
+    let weights = tuner.tune(&weights, iterations, &mut |iteration, error| {
+        eprintln!("iteration {} error {:.6}", iteration, error)
+    });
+    let source = tuning::to_rust_source(&weights);
+    match output {
+        Some(path) => {
+            std::fs::write(path, source)
+                .map_err(|error| format!("cannot write {}: {}", path, error))?;
+            eprintln!("tuned weights written to {}", path);
+        }
+        None => print!("{}", source),
+    }
+    Ok(())
+}
+
+/// selfplay <out.txt> [--games <n>] [--concurrency <n>] [--nodes <n>] [--depth <plies>]
+///     [--seed <n>] [--random-plies <n>] [--book <book.bin>] [--syzygy <path>]
+/// Plays the bot against itself and writes the positions with their scores and results
+pub fn self_play(args: &[String]) -> Result<(), String> {
+    let mut config = SelfPlayConfig::default();
+    let mut output = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().map(|v| v.as_str()).unwrap_or_default();
+        match arg.as_str() {
+            "--games" => config.games = value().parse().unwrap_or(config.games),
+            "--concurrency" => config.concurrency = value().parse().unwrap_or(config.concurrency),
+            "--nodes" => config.limits.nodes = value().parse().ok(),
+            "--depth" => config.limits.depth = value().parse().ok(),
+            "--seed" => config.seed = value().parse().unwrap_or(config.seed),
+            "--random-plies" => {
+                config.random_plies = value().parse().unwrap_or(config.random_plies)
+            }
+            "--syzygy" => config.syzygy_path = value().to_string(),
+            "--book" => {
+                let path = value();
+                let book = Book::open(path)
+                    .map_err(|error| format!("cannot read book {}: {}", path, error))?;
+                config.book = Some(book);
+            }
+            _ => output = Some(arg),
+        }
+    }
+    let output = output.ok_or(
+        "usage: chess_bot selfplay <out.txt> [--games <n>] [--concurrency <n>] [--nodes <n>] \
+         [--depth <plies>] [--seed <n>] [--random-plies <n>] [--book <book.bin>] [--syzygy <path>]",
+    )?;
+    let file = std::fs::File::create(output)
+        .map_err(|error| format!("cannot write {}: {}", output, error))?;
+    let mut out = std::io::BufWriter::new(file);
+    let result = selfplay::generate(&config, &mut out, &mut |stats| {
+        eprint!(
+            "\rgame {}/{}, {} positions, +{} ={} -{}",
+            stats.games,
+            config.games,
+            stats.positions,
+            stats.white_wins,
+            stats.draws,
+            stats.black_wins
+        )
+    });
+    eprintln!();
+    result.map_err(|error| format!("cannot write {}: {}", output, error))?;
+    Ok(())
+}
+
+/// match [--games <n>] [--concurrency <n>] [--tc <seconds>+<increment>] [--nodes <n>]
+///     [--depth <plies>] [--openings <file.pgn|file.epd>] [--plies <n>] [--pgn <out.pgn>]
+///     [--sprt <elo0>,<elo1>] [--seed <n>] [--first <option>=<value>...]
+///     [--second <option>=<value>...]
+/// Plays two configurations of the bot against each other, see `match_runner`.
+/// The options of a contestant are name, threads, hash, evalfile, syzygy and elo.
+pub fn play_match(args: &[String]) -> Result<(), String> {
+    let mut config = MatchConfig::new(Contestant::new("first"), Contestant::new("second"));
+    let mut pgn_path = None;
+    let mut openings_path = None;
+    let mut plies = 8;
+    // the contestant the next name=value options belong to
+    let mut contestant = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if let (Some(first), Some((key, value))) = (contestant, arg.split_once('=')) {
+            let contestant = if first {
+                &mut config.first
+            } else {
+                &mut config.second
+            };
+            set_contestant_option(contestant, key, value)?;
+            continue;
+        }
+        contestant = None;
+        let mut value = || iter.next().map(|v| v.as_str()).unwrap_or_default();
+        match arg.as_str() {
+            "--first" => contestant = Some(true),
+            "--second" => contestant = Some(false),
+            "--games" => config.games = value().parse().unwrap_or(config.games),
+            "--concurrency" => config.concurrency = value().parse().unwrap_or(config.concurrency),
+            "--nodes" => config.limits.nodes = value().parse().ok(),
+            "--depth" => config.limits.depth = value().parse().ok(),
+            "--seed" => config.seed = value().parse().unwrap_or(config.seed),
+            "--pgn" => pgn_path = Some(value().to_string()),
+            "--openings" => openings_path = Some(value().to_string()),
+            "--plies" => plies = value().parse().unwrap_or(plies),
+            "--tc" => {
+                let tc = value();
+                let (time, increment) = tc.split_once('+').unwrap_or((tc, "0"));
+                match (time.parse::<f64>(), increment.parse::<f64>()) {
+                    (Ok(time), Ok(increment)) => {
+                        config.clock = Some((
+                            Duration::from_secs_f64(time),
+                            Duration::from_secs_f64(increment),
+                        ))
+                    }
+                    _ if tc == "none" => config.clock = None,
+                    _ => return Err(format!("cannot read time control {}", tc)),
+                }
+            }
+            "--sprt" => {
+                let bounds: Vec<f64> = value().split(',').filter_map(|v| v.parse().ok()).collect();
+                if let [elo0, elo1] = bounds[..] {
+                    config.sprt = Some(Sprt {
+                        elo0,
+                        elo1,
+                        ..Default::default()
+                    });
+                }
+            }
+            _ => {
+                return Err("usage: chess_bot match [--games <n>] [--concurrency <n>] \
+                     [--tc <seconds>+<increment>|none] [--nodes <n>] [--depth <plies>] \
+                     [--openings <file.pgn|file.epd>] [--plies <n>] [--pgn <out.pgn>] \
+                     [--sprt <elo0>,<elo1>] [--seed <n>] [--first <option>=<value>...] \
+                     [--second <option>=<value>...]"
+                    .to_string());
+            }
+        }
+    }
+    config.random_plies = plies;
+    if let Some(path) = openings_path {
+        config.openings = match_runner::load_openings(&path, plies)
+            .map_err(|error| format!("cannot read openings {}: {}", path, error))?;
+    }
+    let mut pgn = pgn_path
+        .as_deref()
+        .map(File::create)
+        .transpose()
+        .map_err(|error| format!("cannot write pgn: {}", error))?
+        .map(BufWriter::new);
+    // the match goes on when the PGN can't be written, it fails at the end
+    let mut pgn_error = None;
+
+    println!(
+        "{} vs {}, {} games",
+        config.first.name, config.second.name, config.games
+    );
+    let (stats, verdict) = match_runner::run_match(&config, &mut |game, stats| {
+        if let Some(file) = &mut pgn {
+            if let Err(error) = match_runner::write_pgn(file, &config, game) {
+                pgn_error = Some(format!("cannot write pgn: {}", error));
+                pgn = None;
+            }
+        }
+        let (elo, margin) = stats.elo();
+        print!(
+            "game {}: +{} ={} -{}, elo {:.1} +- {:.1}",
+            stats.games(),
+            stats.wins,
+            stats.draws,
+            stats.losses,
+            elo,
+            margin
+        );
+        if let Some(sprt) = &config.sprt {
+            let (lower, upper) = sprt.bounds();
+            print!(", llr {:.2} ({:.2}, {:.2})", sprt.llr(stats), lower, upper);
+        }
+        println!();
+    });
+    if let Some(pgn) = &mut pgn {
+        if let Err(error) = pgn.flush() {
+            pgn_error = Some(format!("cannot write pgn: {}", error));
+        }
+    }
+
+    let (elo, margin) = stats.elo();
+    println!(
+        "{} vs {}: +{} ={} -{} ({:.1}%), elo {:.1} +- {:.1}",
+        config.first.name,
+        config.second.name,
+        stats.wins,
+        stats.draws,
+        stats.losses,
+        stats.score() * 100.0,
+        elo,
+        margin
+    );
+    if let Some(sprt) = &config.sprt {
+        let verdict = match verdict {
+            SprtVerdict::AcceptH1 => "H1 accepted",
+            SprtVerdict::AcceptH0 => "H0 accepted",
+            SprtVerdict::Continue => "no verdict yet",
+        };
+        println!("sprt [{}, {}]: {}", sprt.elo0, sprt.elo1, verdict);
+    }
+    pgn_error.map_or(Ok(()), Err)
+}
+
+fn set_contestant_option(
+    contestant: &mut Contestant,
+    key: &str,
+    value: &str,
+) -> Result<(), String> {
+    match key {
+        "name" => contestant.name = value.to_string(),
+        "threads" => contestant.options.threads = value.parse().unwrap_or(1),
+        "hash" => contestant.options.hash_size = value.parse().unwrap_or(16),
+        "syzygy" => contestant.syzygy_path = value.to_string(),
+        "elo" => contestant.strength = value.parse().ok().map(Strength::new),
+        "evalfile" => match Network::open(value) {
+            Ok(network) => {
+                contestant.network = Some(Arc::new(network));
+                contestant.options.use_nnue = true;
+            }
+            Err(error) => return Err(format!("cannot read network {}: {}", value, error)),
+        },
+        _ => return Err(format!("unknown option {}", key)),
+    }
+    Ok(())
+}
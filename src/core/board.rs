@@ -1,9 +1,12 @@
 // Purpose: Contains the Board struct and its methods.
 
 use super::*;
+use crate::core::polyglot;
 use crate::core::utils::*;
+use crate::core::zobrist;
 use crate::core::PieceType;
 
+#[derive(Clone)]
 pub struct Board {
     black_pawn: u64,
     black_rook: u64,
@@ -18,7 +21,7 @@ pub struct Board {
     white_queen: u64,
     white_king: u64,
     en_passant: u64,
-    /// 0b0001 = white can castle kingsid
+    /// 0b0001 = white can castle kingside
     /// 0b0010 = white can castle queenside
     /// 0b0100 = black can castle kingside
     /// 0b1000 = black can castle queenside
@@ -76,7 +79,7 @@ impl Board {
                     'Q' => PieceType::WhiteQueen,
                     'K' => PieceType::WhiteKing,
                     _ => {
-                        info!("Unknown character in FEN string: {}", c);
+                        eprintln!("Unknown character in FEN string: {}", c);
                         PieceType::WhiteKing
                     }
                 };
@@ -97,7 +100,7 @@ impl Board {
                 }
                 column += 1;
             } else {
-                info!("Unknown character in FEN string: {}", c);
+                eprintln!("Unknown character in FEN string: {}", c);
             }
         }
         board
@@ -106,7 +109,7 @@ impl Board {
     pub fn exportFEN(&self) -> String {
         let mut FEN = String::new();
         let mut empty = 0;
-        for row in (0..8).rev() {
+        for row in 0..8 {
             for column in 0..8 {
                 let pos = 1 << (column + row * 8);
                 if self.black_pawn & pos != 0 {
@@ -189,7 +192,7 @@ impl Board {
                 FEN.push_str(&empty.to_string());
                 empty = 0;
             }
-            if row > 0 {
+            if row < 7 {
                 FEN.push('/');
             }
         }
@@ -197,6 +200,9 @@ impl Board {
     }
 
     pub fn exportEnPassantSquare(&self) -> String {
+        if self.en_passant == 0 {
+            return String::from("-");
+        }
         translate_bitboard(self.en_passant)
     }
 
@@ -279,13 +285,31 @@ impl Board {
         &mut self,
         r#move: &Move,
     ) -> (PieceType, Option<PieceType>) {
-        self.en_passant = 0;
         let from = 1 << (r#move.column_from + r#move.row_from * 8);
         let piece_orgin = self.get_piece(from).unwrap();
         let to = 1 << (r#move.column_to + r#move.row_to * 8);
-        let piece_dest = self.get_piece(to);
+        let mut piece_dest = self.get_piece(to);
+
+        // en passant, a pawn moving diagonally onto an empty square
+        let is_pawn = piece_orgin == PieceType::BlackPawn || piece_orgin == PieceType::WhitePawn;
+        if is_pawn && piece_dest.is_none() && r#move.column_from != r#move.column_to && self.en_passant & to != 0 {
+            let captured = 1 << (r#move.column_to + r#move.row_from * 8);
+            piece_dest = self.get_piece(captured);
+            self.mask(captured);
+        }
+
+        // promotion, to a queen unless the move says otherwise
+        let is_promotion = (piece_orgin == PieceType::BlackPawn && r#move.row_to == 7)
+            || (piece_orgin == PieceType::WhitePawn && r#move.row_to == 0);
+        let piece_placed = if is_promotion {
+            r#move.promotion.unwrap_or(Promotion::Queen).piece(piece_orgin.color())
+        } else {
+            piece_orgin
+        };
+
+        self.en_passant = 0;
         self.mask(from | to);
-        match piece_orgin {
+        match piece_placed {
             PieceType::BlackPawn   => self.black_pawn   |= to,
             PieceType::BlackRook   => self.black_rook   |= to,
             PieceType::BlackKnight => self.black_knight |= to,
@@ -300,7 +324,7 @@ impl Board {
             PieceType::WhiteKing   => self.white_king   |= to,
         }
 
-        if piece_orgin == PieceType::BlackPawn || piece_orgin == PieceType::WhitePawn {
+        if is_pawn {
             if (from << 16) & to != 0 {
                 self.en_passant = from << 8;
             } else if (from >> 16) & to != 0 {
@@ -308,26 +332,6 @@ impl Board {
             }
         }
 
-        if piece_dest.is_none() {
-            if piece_orgin == PieceType::BlackPawn {
-                self.white_pawn &= !to >> 8;
-                return (piece_orgin, self.get_piece(to - 8));
-            } else if piece_orgin == PieceType::WhitePawn {
-                self.black_pawn &= !to << 8;
-                return (piece_orgin, self.get_piece(to + 8));
-            }
-        }
-
-        // promotion
-        if piece_orgin == PieceType::BlackPawn && r#move.row_to == 7 {
-            self.black_pawn &= !to;
-            self.black_queen |= to;
-        }
-        if piece_orgin == PieceType::WhitePawn && r#move.row_to == 0 {
-            self.white_pawn &= !to;
-            self.white_queen |= to;
-        }
-
         // castling
         if piece_orgin == PieceType::BlackKing {
             if r#move.column_from == 4 && r#move.column_to == 6 {
@@ -348,9 +352,76 @@ impl Board {
             }
         }
 
+        // a king or rook leaving its square, or a rook being captured, loses the castling right
+        if from & self.castling_rights_mask() != 0 || to & self.castling_rights_mask() != 0 {
+            for (square, right) in [(63, 0b0001), (56, 0b0010), (7, 0b0100), (0, 0b1000)] {
+                if (from | to) & (1 << square) != 0 {
+                    self.castling_rights &= !right;
+                }
+            }
+            if from & (1 << 60) != 0 {
+                self.castling_rights &= !0b0011;
+            }
+            if from & (1 << 4) != 0 {
+                self.castling_rights &= !0b1100;
+            }
+        }
+
         (piece_orgin, piece_dest)
     }
 
+    /// The squares of the kings and rooks that still have castling rights
+    fn castling_rights_mask(&self) -> u64 {
+        let mut mask = 0;
+        if self.castling_rights & 0b0011 != 0 {
+            mask |= 1 << 60;
+        }
+        if self.castling_rights & 0b1100 != 0 {
+            mask |= 1 << 4;
+        }
+        for (square, right) in [(63, 0b0001), (56, 0b0010), (7, 0b0100), (0, 0b1000)] {
+            if self.castling_rights & right != 0 {
+                mask |= 1 << square;
+            }
+        }
+        mask
+    }
+
+    pub fn castling_rights(&self) -> u8 {
+        self.castling_rights
+    }
+
+    /// Sets the castling rights from the castling field of a FEN string, ex: "KQkq" or "-"
+    pub fn set_castling_rights(&mut self, castling: &str) {
+        self.castling_rights = 0;
+        for c in castling.chars() {
+            match c {
+                'K' => self.castling_rights |= 0b0001,
+                'Q' => self.castling_rights |= 0b0010,
+                'k' => self.castling_rights |= 0b0100,
+                'q' => self.castling_rights |= 0b1000,
+                _ => {}
+            }
+        }
+    }
+
+    pub fn export_castling_rights(&self) -> String {
+        let mut castling = String::new();
+        for (c, right) in [('K', 0b0001), ('Q', 0b0010), ('k', 0b0100), ('q', 0b1000)] {
+            if self.castling_rights & right != 0 {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        castling
+    }
+
+    pub fn en_passant(&self) -> u64 {
+        self.en_passant
+    }
+
     pub fn get_black_pieces(&self) -> u64 {
         self.black_pawn
             | self.black_rook
@@ -373,6 +444,51 @@ impl Board {
         self.get_black_pieces() | self.get_white_pieces()
     }
 
+    #[rustfmt::skip]
+    pub fn get_pieces(&self, piece: PieceType) -> u64 {
+        match piece {
+            PieceType::BlackPawn   => self.black_pawn,
+            PieceType::BlackRook   => self.black_rook,
+            PieceType::BlackKnight => self.black_knight,
+            PieceType::BlackBishop => self.black_bishop,
+            PieceType::BlackQueen  => self.black_queen,
+            PieceType::BlackKing   => self.black_king,
+            PieceType::WhitePawn   => self.white_pawn,
+            PieceType::WhiteRook   => self.white_rook,
+            PieceType::WhiteKnight => self.white_knight,
+            PieceType::WhiteBishop => self.white_bishop,
+            PieceType::WhiteQueen  => self.white_queen,
+            PieceType::WhiteKing   => self.white_king,
+        }
+    }
+
+    /// Zobrist hash of the position, `turn` is true if it is white's turn
+    pub fn zobrist_key(&self, turn: bool) -> u64 {
+        let mut key = 0;
+        for piece in PieceType::ALL {
+            key ^= zobrist::bitboard_key(piece, self.get_pieces(piece));
+        }
+        key ^= zobrist::castling_key(self.castling_rights);
+        if self.en_passant != 0 {
+            key ^= zobrist::en_passant_key(self.en_passant.trailing_zeros() % 8);
+        }
+        if turn {
+            key ^= zobrist::side_key();
+        }
+        key
+    }
+
+    /// Hash of the position as used by Polyglot opening books, see `polyglot::polyglot_key`
+    pub fn polyglot_key(&self, turn: bool) -> u64 {
+        polyglot::polyglot_key(self, turn)
+    }
+
+    /// Zobrist hash of only the pawns, used to index the pawn hash table
+    pub fn pawn_key(&self) -> u64 {
+        zobrist::bitboard_key(PieceType::WhitePawn, self.white_pawn)
+            ^ zobrist::bitboard_key(PieceType::BlackPawn, self.black_pawn)
+    }
+
 
 
     #[rustfmt::skip]
@@ -645,19 +761,19 @@ impl Board {
         }
         // left
         if column > 1 {
-            if row > 0 {
+            if row < 7 {
                 moves |= pos << 6;
             }
-            if row < 7 {
+            if row > 0 {
                 moves |= pos >> 10;
             }
         }
         // right
         if column < 6 {
-            if row > 0 {
+            if row < 7 {
                 moves |= pos << 10;
             }
-            if row < 7 {
+            if row > 0 {
                 moves |= pos >> 6;
             }
         }
@@ -687,19 +803,19 @@ impl Board {
         }
         // left
         if column > 1 {
-            if row > 0 {
+            if row < 7 {
                 moves |= pos << 6;
             }
-            if row < 7 {
+            if row > 0 {
                 moves |= pos >> 10;
             }
         }
         // right
         if column < 6 {
-            if row > 0 {
+            if row < 7 {
                 moves |= pos << 10;
             }
-            if row < 7 {
+            if row > 0 {
                 moves |= pos >> 6;
             }
         }
@@ -886,21 +1002,23 @@ impl Board {
             moves |= pos >> 7;
         }
         // castling right
-        if self.castling_rights & 0b0001 != 0
+        if self.castling_rights & 0b0100 != 0
+            && pos == 1 << 4
             && self.get_all_pieces() & pos << 1 == 0
             && self.get_all_pieces() & pos << 2 == 0
         {
             moves |= pos << 2;
         }
         // castling left
-        if self.castling_rights & 0b0010 != 0
+        if self.castling_rights & 0b1000 != 0
+            && pos == 1 << 4
             && self.get_all_pieces() & pos >> 1 == 0
             && self.get_all_pieces() & pos >> 2 == 0
             && self.get_all_pieces() & pos >> 3 == 0
         {
             moves |= pos >> 2;
         }
-        moves & !self.get_black_pieces() & !self.get_all_moves(true)
+        self.without_attacked_squares(moves & !self.get_black_pieces(), true)
     }
 
     pub fn get_allowed_white_king_moves(&self, column: u32, row: u32) -> u64 {
@@ -939,21 +1057,23 @@ impl Board {
             moves |= pos >> 7;
         }
         // castling right
-        if self.castling_rights & 0b0100 != 0
+        if self.castling_rights & 0b0001 != 0
+            && pos == 1 << 60
             && self.get_all_pieces() & pos << 1 == 0
             && self.get_all_pieces() & pos << 2 == 0
         {
             moves |= pos << 2;
         }
         // castling left
-        if self.castling_rights & 0b1000 != 0
+        if self.castling_rights & 0b0010 != 0
+            && pos == 1 << 60
             && self.get_all_pieces() & pos >> 1 == 0
             && self.get_all_pieces() & pos >> 2 == 0
             && self.get_all_pieces() & pos >> 3 == 0
         {
             moves |= pos >> 2;
         }
-        moves & !self.get_white_pieces() & !self.get_all_moves(false)
+        self.without_attacked_squares(moves & !self.get_white_pieces(), false)
     }
 
     /// returns the points that white is ahead by
@@ -980,4 +1100,365 @@ impl Board {
         }
         moves
     }
+
+    /// The squares a king on the square can step to, without castling
+    fn king_steps(column: u32, row: u32) -> u64 {
+        let mut moves = 0;
+        for (dc, dr) in [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ] {
+            let (c, r) = (column as i32 + dc, row as i32 + dr);
+            if (0..8).contains(&c) && (0..8).contains(&r) {
+                moves |= 1 << (c + r * 8);
+            }
+        }
+        moves
+    }
+
+    /// Removes the squares that are attacked by `color` from the bitboard
+    fn without_attacked_squares(&self, mut squares: u64, color: bool) -> u64 {
+        let mut remaining = squares;
+        while remaining != 0 {
+            let square = remaining.trailing_zeros();
+            remaining &= remaining - 1;
+            if self.is_square_attacked(square, color) {
+                squares &= !(1 << square);
+            }
+        }
+        squares
+    }
+
+    /// Is the square (0..64) attacked by any piece of `color`
+    pub fn is_square_attacked(&self, square: u32, color: bool) -> bool {
+        let (column, row) = (square % 8, square / 8);
+        let pos: u64 = 1 << square;
+        // look from the square as if it holds a piece of the other color,
+        // every attacker of `color` is then a capture of that piece
+        let (pawns, knights, bishops, rooks, queens, king) = if color {
+            (
+                self.white_pawn,
+                self.white_knight,
+                self.white_bishop,
+                self.white_rook,
+                self.white_queen,
+                self.white_king,
+            )
+        } else {
+            (
+                self.black_pawn,
+                self.black_knight,
+                self.black_bishop,
+                self.black_rook,
+                self.black_queen,
+                self.black_king,
+            )
+        };
+        let pawn_attackers = if color {
+            (if column > 0 { pos << 7 } else { 0 }) | (if column < 7 { pos << 9 } else { 0 })
+        } else {
+            (if column > 0 { pos >> 9 } else { 0 }) | (if column < 7 { pos >> 7 } else { 0 })
+        };
+        if pawn_attackers & pawns != 0 {
+            return true;
+        }
+        if Board::king_steps(column, row) & king != 0 {
+            return true;
+        }
+        let (knight_moves, bishop_moves, rook_moves) = if color {
+            (
+                self.get_allowed_black_knight_moves(column, row),
+                self.get_allowed_black_bishop_moves(column, row),
+                self.get_allowed_black_rook_moves(column, row),
+            )
+        } else {
+            (
+                self.get_allowed_white_knight_moves(column, row),
+                self.get_allowed_white_bishop_moves(column, row),
+                self.get_allowed_white_rook_moves(column, row),
+            )
+        };
+        knight_moves & knights != 0
+            || bishop_moves & (bishops | queens) != 0
+            || rook_moves & (rooks | queens) != 0
+    }
+
+    /// Neither side can mate: only kings, or a king and one knight or bishop against a king
+    pub fn is_insufficient_material(&self) -> bool {
+        let heavy = self.white_pawn
+            | self.black_pawn
+            | self.white_rook
+            | self.black_rook
+            | self.white_queen
+            | self.black_queen;
+        let minors = self.white_knight | self.black_knight | self.white_bishop | self.black_bishop;
+        heavy == 0 && minors.count_ones() <= 1
+    }
+
+    /// Could `color` still mate with some series of legal moves, when the other side helps.
+    /// Not with a bare king, a single knight against a bare king, or bishops on squares of one
+    /// color when the other side has nothing to block its king with on the other color.
+    pub fn can_mate(&self, color: bool) -> bool {
+        // a8 is a light square
+        const LIGHT_SQUARES: u64 = 0xAA55_AA55_AA55_AA55;
+        let (own, other) = if color {
+            (self.get_white_pieces(), self.get_black_pieces())
+        } else {
+            (self.get_black_pieces(), self.get_white_pieces())
+        };
+        let heavy = self.white_pawn
+            | self.black_pawn
+            | self.white_rook
+            | self.black_rook
+            | self.white_queen
+            | self.black_queen;
+        if heavy & own != 0 {
+            return true;
+        }
+        let knights = (self.white_knight | self.black_knight) & own;
+        let bishops = (self.white_bishop | self.black_bishop) & own;
+        let other_king = (self.white_king | self.black_king) & other;
+        let other_pieces = other & !other_king;
+        match (knights.count_ones(), bishops) {
+            (0, 0) => false,
+            (0, bishops) if bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0 => {
+                // the blockers have to stand on the squares the bishops don't reach
+                let other_bishops = (self.white_bishop | self.black_bishop) & other;
+                let reach = if bishops & LIGHT_SQUARES != 0 {
+                    LIGHT_SQUARES
+                } else {
+                    !LIGHT_SQUARES
+                };
+                other_pieces & !other_bishops != 0 || other_bishops & !reach != 0
+            }
+            (1, 0) => other_pieces != 0,
+            _ => true,
+        }
+    }
+
+    /// Is the king of `color` in check
+    pub fn is_in_check(&self, color: bool) -> bool {
+        let king = if color {
+            self.white_king
+        } else {
+            self.black_king
+        };
+        king != 0 && self.is_square_attacked(king.trailing_zeros(), !color)
+    }
+
+    /// All legal moves of `color`, moves that leave the own king in check are left out
+    pub fn get_legal_moves(&self, color: bool) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(48);
+        let own_pieces = if color {
+            self.get_white_pieces()
+        } else {
+            self.get_black_pieces()
+        };
+        let mut pieces = own_pieces;
+        while pieces != 0 {
+            let from = pieces.trailing_zeros();
+            pieces &= pieces - 1;
+            let (column, row) = (from % 8, from / 8);
+            let mut targets = self.get_allowed_moves(column, row, color);
+            while targets != 0 {
+                let to = targets.trailing_zeros();
+                targets &= targets - 1;
+                let r#move = Move::new((column, row), (to % 8, to / 8));
+                if !self.is_legal(&r#move, color) {
+                    continue;
+                }
+                let is_pawn = (self.white_pawn | self.black_pawn) & (1 << from) != 0;
+                if is_pawn && (r#move.row_to == 0 || r#move.row_to == 7) {
+                    moves.extend(Promotion::ALL.map(|promotion| r#move.with_promotion(promotion)));
+                } else {
+                    moves.push(r#move);
+                }
+            }
+        }
+        moves
+    }
+
+    /// Does the pseudo legal move keep the own king out of check
+    fn is_legal(&self, r#move: &Move, color: bool) -> bool {
+        let from = r#move.column_from + r#move.row_from * 8;
+        let king = if color {
+            self.white_king
+        } else {
+            self.black_king
+        };
+        if king & (1 << from) != 0 && r#move.column_from.abs_diff(r#move.column_to) == 2 {
+            // castling out of or through check
+            let middle = (r#move.column_from + r#move.column_to) / 2 + r#move.row_from * 8;
+            if self.is_square_attacked(from, !color) || self.is_square_attacked(middle, !color) {
+                return false;
+            }
+        }
+        let mut board = self.clone();
+        board.move_piece(r#move);
+        !board.is_in_check(color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::game::Game;
+
+    fn game(fen: &str) -> Game {
+        Game::new(fen)
+    }
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for notation in moves {
+            game.make_move(&Move::from_algebraic_notation(notation).unwrap());
+        }
+    }
+
+    fn legal(game: &Game) -> Vec<String> {
+        let mut moves: Vec<String> = game
+            .board
+            .get_legal_moves(game.turn)
+            .iter()
+            .map(Move::to_algebraic_notation)
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn square_notation_rows() {
+        // row 0 is the 8th rank
+        assert_eq!(translate_coordinates(0, 7), "a1");
+        assert_eq!(translate_coordinates(7, 0), "h8");
+        assert_eq!(translate_single_fen("e2"), Ok((4, 6)));
+        let r#move = Move::from_algebraic_notation("c1f4").unwrap();
+        assert!(r#move == Move::new((2, 7), (5, 4)));
+    }
+
+    #[test]
+    fn start_position_moves() {
+        let mut game = game("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(legal(&game).len(), 20);
+        play(&mut game, &["e2e4"]);
+        assert_eq!(legal(&game).len(), 20);
+    }
+
+    #[test]
+    fn knight_moves_do_not_wrap() {
+        let game = game("4k3/8/8/7N/N7/8/8/4K3 w - - 0 1");
+        let knight_moves: Vec<String> = legal(&game)
+            .into_iter()
+            .filter(|m| !m.starts_with("e1"))
+            .collect();
+        assert_eq!(
+            knight_moves,
+            ["a4b2", "a4b6", "a4c3", "a4c5", "h5f4", "h5f6", "h5g3", "h5g7"]
+        );
+    }
+
+    #[test]
+    fn en_passant_removes_the_pawn() {
+        let mut game = game("4k3/5p2/8/4P3/8/8/8/4K3 b - - 0 1");
+        play(&mut game, &["f7f5"]);
+        assert!(legal(&game).contains(&String::from("e5f6")));
+        play(&mut game, &["e5f6"]);
+        assert_eq!(game.board.get_piece_pos(5, 3), None);
+        assert_eq!(game.board.get_piece_pos(5, 2), Some(PieceType::WhitePawn));
+        // only right after the double push
+        let mut game = Game::new("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1");
+        play(&mut game, &["d7d5", "e1d1", "e8d8"]);
+        assert!(!legal(&game).contains(&String::from("e5d6")));
+    }
+
+    #[test]
+    fn castling_moves_the_rook_and_updates_the_rights() {
+        let mut game = game("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert!(legal(&game).contains(&String::from("e1g1")));
+        assert!(legal(&game).contains(&String::from("e1c1")));
+        play(&mut game, &["e1g1"]);
+        assert_eq!(game.board.get_piece_pos(5, 7), Some(PieceType::WhiteRook));
+        assert_eq!(game.board.export_castling_rights(), "kq");
+        // capturing the rook on a1 takes away white's last right
+        let mut game = Game::new("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1");
+        play(&mut game, &["a8a1"]);
+        assert_eq!(game.board.export_castling_rights(), "Kk");
+    }
+
+    #[test]
+    fn no_castling_through_check() {
+        let game = game("r3k2r/8/8/8/8/8/5r2/R3K2R w KQkq - 0 1");
+        assert!(!game.board.is_in_check(true));
+        assert!(!legal(&game).contains(&String::from("e1g1")));
+        assert!(legal(&game).contains(&String::from("e1c1")));
+    }
+
+    #[test]
+    fn promotion_by_a_push() {
+        let mut game = game("4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        play(&mut game, &["a7a8"]);
+        assert_eq!(game.board.get_piece_pos(0, 0), Some(PieceType::WhiteQueen));
+    }
+
+    #[test]
+    fn underpromotions() {
+        let game = game("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        let promotions: Vec<String> = legal(&game)
+            .into_iter()
+            .filter(|m| m.starts_with("a7"))
+            .collect();
+        assert_eq!(
+            promotions,
+            ["a7a8b", "a7a8n", "a7a8q", "a7a8r", "a7b8b", "a7b8n", "a7b8q", "a7b8r"]
+        );
+        let mut game = Game::new("4k3/8/8/8/8/8/4K1p1/8 b - - 0 1");
+        play(&mut game, &["g2g1n"]);
+        assert_eq!(game.board.get_piece_pos(6, 7), Some(PieceType::BlackKnight));
+        assert!(game.board.is_in_check(true));
+    }
+
+    #[test]
+    fn promotion_notation() {
+        let r#move = Move::from_algebraic_notation("e7e8n").unwrap();
+        assert_eq!(r#move.promotion, Some(Promotion::Knight));
+        assert_eq!(r#move.to_algebraic_notation(), "e7e8n");
+        assert_eq!(
+            Move::from_algebraic_notation("e2e4").unwrap().promotion,
+            None
+        );
+        assert!(Move::from_algebraic_notation("e7e8k").is_err());
+        assert!(Move::from_algebraic_notation("e7e8qq").is_err());
+    }
+
+    #[test]
+    fn can_mate_per_side() {
+        let can_mate = |fen| {
+            let board = Game::new(fen).board;
+            (board.can_mate(true), board.can_mate(false))
+        };
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), (false, false));
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"), (true, false));
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/N3K3 w - - 0 1"), (false, false));
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/NN2K3 w - - 0 1"), (true, false));
+        assert_eq!(can_mate("4k3/7p/8/8/8/8/8/N3K3 w - - 0 1"), (true, true));
+        // bishops on one color of square only mate with blockers on the other color
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1"), (false, false));
+        assert_eq!(can_mate("4k3/8/8/8/8/8/8/BB2K3 w - - 0 1"), (true, false));
+        assert_eq!(can_mate("4kb2/8/8/8/8/8/8/B3K3 w - - 0 1"), (false, false));
+        assert_eq!(can_mate("3kb3/8/8/8/8/8/8/B3K3 w - - 0 1"), (true, true));
+        assert_eq!(can_mate("4kr2/8/8/8/8/8/8/B3K3 w - - 0 1"), (true, true));
+    }
+
+    #[test]
+    fn check_and_checkmate() {
+        let game = game("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert!(game.board.is_in_check(true));
+        assert!(game.board.is_square_attacked(60, false));
+        assert!(legal(&game).is_empty());
+    }
 }
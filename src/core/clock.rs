@@ -0,0 +1,249 @@
+// Purpose: A chess clock with a base time and an increment or delay for every move.
+//
+// Fischer adds the increment after every move. Bronstein adds back the time the move took,
+// but never more than the delay. With a simple delay the clock only starts to count down
+// after the delay has passed.
+
+use std::time::{Duration, Instant};
+
+use super::game::{Game, GameResult};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockMode {
+    Fischer,
+    Bronstein,
+    Delay,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockSettings {
+    pub base: Duration,
+    /// the increment, or the delay for Bronstein and simple delay
+    pub increment: Duration,
+    pub mode: ClockMode,
+}
+
+impl ClockSettings {
+    /// Reads a time control like "5+3": minutes of base time and seconds of increment
+    pub fn parse(text: &str, mode: ClockMode) -> Option<ClockSettings> {
+        let (base, increment) = text.split_once('+').unwrap_or((text, "0"));
+        let base = base.parse::<f64>().ok().filter(|b| *b > 0.0)?;
+        let increment = increment.parse::<f64>().ok().filter(|i| *i >= 0.0)?;
+        Some(ClockSettings {
+            base: Duration::from_secs_f64(base * 60.0),
+            increment: Duration::from_secs_f64(increment),
+            mode,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Clock {
+    settings: ClockSettings,
+    /// time left of white and black at the start of their turn
+    white: Duration,
+    black: Duration,
+    /// the side whose clock runs and since when
+    running: Option<(bool, Instant)>,
+}
+
+impl Clock {
+    /// A clock that isn't running yet
+    pub fn new(settings: ClockSettings) -> Clock {
+        Clock {
+            settings,
+            white: settings.base,
+            black: settings.base,
+            running: None,
+        }
+    }
+
+    pub fn settings(&self) -> &ClockSettings {
+        &self.settings
+    }
+
+    fn left_mut(&mut self, color: bool) -> &mut Duration {
+        if color {
+            &mut self.white
+        } else {
+            &mut self.black
+        }
+    }
+
+    /// The time of a turn that comes off the clock
+    fn counted(&self, elapsed: Duration) -> Duration {
+        match self.settings.mode {
+            ClockMode::Delay => elapsed.saturating_sub(self.settings.increment),
+            ClockMode::Fischer | ClockMode::Bronstein => elapsed,
+        }
+    }
+
+    /// The time `color` has left right now
+    pub fn remaining(&self, color: bool) -> Duration {
+        let left = if color { self.white } else { self.black };
+        match self.running {
+            Some((side, since)) if side == color => {
+                left.saturating_sub(self.counted(since.elapsed()))
+            }
+            _ => left,
+        }
+    }
+
+    /// The side whose clock is running
+    pub fn running(&self) -> Option<bool> {
+        self.running.map(|(side, _)| side)
+    }
+
+    /// Starts the clock of `color`, stopping the other one
+    pub fn start(&mut self, color: bool) {
+        self.stop();
+        self.running = Some((color, Instant::now()));
+    }
+
+    /// Stops the clock without adding anything
+    pub fn stop(&mut self) {
+        if let Some((side, _)) = self.running {
+            *self.left_mut(side) = self.remaining(side);
+            self.running = None;
+        }
+    }
+
+    /// `color` made its move: its clock gets its increment and the other clock starts
+    pub fn press(&mut self, color: bool) {
+        if let Some((side, since)) = self.running {
+            if side == color {
+                let used = since.elapsed();
+                let left = self.remaining(color);
+                let bonus = match self.settings.mode {
+                    _ if left.is_zero() => Duration::ZERO,
+                    ClockMode::Fischer => self.settings.increment,
+                    ClockMode::Bronstein => used.min(self.settings.increment),
+                    ClockMode::Delay => Duration::ZERO,
+                };
+                *self.left_mut(color) = left + bonus;
+            }
+        }
+        self.running = Some((!color, Instant::now()));
+    }
+
+    /// The side that ran out of time
+    pub fn flagged(&self) -> Option<bool> {
+        [true, false]
+            .into_iter()
+            .find(|color| self.remaining(*color).is_zero())
+    }
+
+    /// The result when a side ran out of time in the last position of the game: it lost,
+    /// unless the other side can't mate anymore
+    pub fn timeout_result(&self, game: &Game) -> Option<GameResult> {
+        let flagged = self.flagged()?;
+        Some(if !game.board.can_mate(!flagged) {
+            GameResult::Draw
+        } else if flagged {
+            GameResult::BlackWins
+        } else {
+            GameResult::WhiteWins
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(seconds: u64, increment: u64, mode: ClockMode) -> Clock {
+        let mut clock = Clock::new(ClockSettings {
+            base: Duration::from_secs(seconds),
+            increment: Duration::from_secs(increment),
+            mode,
+        });
+        clock.start(true);
+        clock
+    }
+
+    /// Lets the running clock run for `seconds` as if they have passed
+    fn run(clock: &mut Clock, seconds: u64) {
+        if let Some((side, _)) = clock.running {
+            clock.running = Some((side, Instant::now() - Duration::from_secs(seconds)));
+        }
+    }
+
+    fn assert_left(clock: &Clock, color: bool, seconds: f64) {
+        let left = clock.remaining(color).as_secs_f64();
+        assert!(
+            (left - seconds).abs() < 0.1,
+            "{} left instead of {}",
+            left,
+            seconds
+        );
+    }
+
+    #[test]
+    fn fischer_adds_the_increment() {
+        let mut clock = clock(60, 2, ClockMode::Fischer);
+        run(&mut clock, 3);
+        assert_left(&clock, true, 57.0);
+        clock.press(true);
+        assert_eq!(clock.running(), Some(false));
+        assert_left(&clock, true, 59.0);
+        assert_left(&clock, false, 60.0);
+    }
+
+    #[test]
+    fn bronstein_adds_back_at_most_the_delay() {
+        let mut clock = clock(60, 2, ClockMode::Bronstein);
+        run(&mut clock, 3);
+        clock.press(true);
+        assert_left(&clock, true, 59.0);
+        run(&mut clock, 1);
+        clock.press(false);
+        assert_left(&clock, false, 60.0);
+    }
+
+    #[test]
+    fn delay_counts_down_after_the_delay() {
+        let mut clock = clock(60, 2, ClockMode::Delay);
+        run(&mut clock, 1);
+        assert_left(&clock, true, 60.0);
+        clock.press(true);
+        assert_left(&clock, true, 60.0);
+        run(&mut clock, 5);
+        assert_left(&clock, false, 57.0);
+        clock.press(false);
+        assert_left(&clock, false, 57.0);
+    }
+
+    #[test]
+    fn stop_keeps_the_time() {
+        let mut clock = clock(60, 0, ClockMode::Fischer);
+        run(&mut clock, 10);
+        clock.stop();
+        assert_eq!(clock.running(), None);
+        assert_left(&clock, true, 50.0);
+    }
+
+    #[test]
+    fn timeout_result() {
+        let mut clock = clock(1, 5, ClockMode::Fischer);
+        let game = |fen| Game::from_fen(fen).unwrap();
+        let rook = game("4k3/8/8/8/8/8/8/R3K3 b - - 0 1");
+        assert_eq!(clock.timeout_result(&rook), None);
+        run(&mut clock, 2);
+        assert_eq!(clock.flagged(), Some(true));
+        // no increment for a side that already ran out of time
+        clock.press(true);
+        assert_eq!(clock.flagged(), Some(true));
+        // white ran out of time, what counts is what black has
+        assert_eq!(clock.timeout_result(&rook), Some(GameResult::Draw));
+        let black_rook = game("r3k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            clock.timeout_result(&black_rook),
+            Some(GameResult::BlackWins)
+        );
+        // a knight can mate when white's own pawn blocks its king
+        let knight = game("4k3/8/8/8/8/8/8/n3K2P w - - 0 1");
+        assert_eq!(clock.timeout_result(&knight), Some(GameResult::BlackWins));
+        let bare_knight = game("4k3/8/8/8/8/8/8/n3K3 w - - 0 1");
+        assert_eq!(clock.timeout_result(&bare_knight), Some(GameResult::Draw));
+    }
+}
@@ -2,6 +2,7 @@ use std::fmt::Debug;
 
 use super::{board::Board, utils::*, Move, PieceType};
 
+#[derive(Clone)]
 pub struct Game {
     pub board: Board,
     /// if white's turn, true, else false
@@ -14,6 +15,72 @@ pub struct Game {
     pub halfmove_clock: u32,
 }
 
+/// How a game ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    /// The result as written in PGN, ex: "1-0"
+    pub fn to_pgn(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+
+    /// The points white scores, 1 for a win and 0.5 for a draw
+    pub fn white_score(&self) -> f64 {
+        match self {
+            GameResult::WhiteWins => 1.0,
+            GameResult::BlackWins => 0.0,
+            GameResult::Draw => 0.5,
+        }
+    }
+
+    /// The side that won, None for a draw
+    pub fn winner(&self) -> Option<bool> {
+        match self {
+            GameResult::WhiteWins => Some(true),
+            GameResult::BlackWins => Some(false),
+            GameResult::Draw => None,
+        }
+    }
+}
+
+/// Why a game ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    Timeout,
+    Resignation,
+    Agreement,
+}
+
+impl Termination {
+    /// The reason in words, ex: "checkmate" or "the 50 move rule"
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::Repetition => "repetition",
+            Termination::FiftyMoves => "the 50 move rule",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::Timeout => "timeout",
+            Termination::Resignation => "resignation",
+            Termination::Agreement => "agreement",
+        }
+    }
+}
+
 impl Debug for Game {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Game")
@@ -44,7 +111,7 @@ impl Game {
         assert!(FEN[1] == "w" || FEN[1] == "b");
         let turn = FEN[1] == "w";
         // castling rights
-        let _castling_rights = FEN[2];
+        let castling_rights = FEN[2];
         // en passant square
 
         let mut en_passant_square = 0;
@@ -55,7 +122,8 @@ impl Game {
         let halfmove_clock = FEN[4].parse::<u32>().unwrap();
         // fullmove number
         let fullmove = FEN[5].parse::<u32>().unwrap();
-        let board = Board::new(FEN[0], en_passant_square);
+        let mut board = Board::new(FEN[0], en_passant_square);
+        board.set_castling_rights(castling_rights);
         let points = board.points();
 
         Game {
@@ -67,25 +135,79 @@ impl Game {
         }
     }
 
+    /// Reads a FEN, None when it isn't valid. The move counters may be left out, like in EPD.
+    pub fn from_fen(fen: &str) -> Option<Game> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let [placement, turn, castling, en_passant, counters @ ..] = fields.as_slice() else {
+            return None;
+        };
+        let rows: Vec<&str> = placement.split('/').collect();
+        let valid_placement = rows.len() == 8
+            && rows.iter().all(|row| {
+                let mut squares = 0;
+                for c in row.chars() {
+                    match c {
+                        '1'..='8' => squares += c.to_digit(10).unwrap(),
+                        c if PieceType::from_char(c).is_some() => squares += 1,
+                        _ => return false,
+                    }
+                }
+                squares == 8
+            });
+        // exactly one king on each side, without one there is nothing to checkmate
+        let valid_kings = ['K', 'k']
+            .iter()
+            .all(|king| placement.chars().filter(|c| c == king).count() == 1);
+        let valid_castling =
+            *castling == "-" || castling.chars().all(|c| matches!(c, 'K' | 'Q' | 'k' | 'q'));
+        let valid_en_passant = *en_passant == "-" || translate_single_fen(en_passant).is_ok();
+        if !valid_placement
+            || !valid_kings
+            || !matches!(*turn, "w" | "b")
+            || !valid_castling
+            || !valid_en_passant
+        {
+            return None;
+        }
+        let halfmove_clock = counters.first().and_then(|c| c.parse::<u32>().ok());
+        let fullmove = counters.get(1).and_then(|c| c.parse::<u32>().ok());
+        // the moves are counted from 1
+        if fullmove == Some(0) {
+            return None;
+        }
+        Some(Game::new(&format!(
+            "{} {} {} {} {} {}",
+            placement,
+            turn,
+            castling,
+            en_passant,
+            halfmove_clock.unwrap_or(0),
+            fullmove.unwrap_or(1)
+        )))
+    }
+
     pub fn turn(&self) -> bool {
         self.turn
     }
 
     pub fn make_move(&mut self, r#move: &Move) {
-        println!("{:?}", self);
-        if let (piece, Some(piece_captured)) = self.board.move_piece(r#move) {
+        let (piece, piece_captured) = self.board.move_piece(r#move);
+        if let Some(piece_captured) = piece_captured {
             self.points += if self.turn {
                 piece_captured.points() as i32
             } else {
                 -(piece_captured.points() as i32)
             };
-            match piece {
-                PieceType::WhitePawn | PieceType::BlackPawn => {
-                    self.halfmove_clock = 0;
-                }
-                _ => {
-                    self.halfmove_clock += 1;
-                }
+        }
+        match piece {
+            PieceType::WhitePawn | PieceType::BlackPawn => {
+                self.halfmove_clock = 0;
+            }
+            _ if piece_captured.is_some() => {
+                self.halfmove_clock = 0;
+            }
+            _ => {
+                self.halfmove_clock += 1;
             }
         }
         self.turn = !self.turn;
@@ -94,6 +216,37 @@ impl Game {
         }
     }
 
+    /// The result when the game is over by the rules: checkmate, stalemate, the 50 move
+    /// rule, threefold repetition or too little material to mate.
+    /// `history` holds the keys of the positions played before the current one.
+    pub fn result(&self, history: &[u64]) -> Option<GameResult> {
+        self.end(history).map(|(result, _)| result)
+    }
+
+    /// The result when the game is over by the rules and why, see `result`
+    pub fn end(&self, history: &[u64]) -> Option<(GameResult, Termination)> {
+        if self.board.get_legal_moves(self.turn).is_empty() {
+            return Some(if !self.board.is_in_check(self.turn) {
+                (GameResult::Draw, Termination::Stalemate)
+            } else if self.turn {
+                (GameResult::BlackWins, Termination::Checkmate)
+            } else {
+                (GameResult::WhiteWins, Termination::Checkmate)
+            });
+        }
+        let key = self.board.zobrist_key(self.turn);
+        let termination = if self.halfmove_clock >= 100 {
+            Termination::FiftyMoves
+        } else if history.iter().filter(|k| **k == key).count() >= 2 {
+            Termination::Repetition
+        } else if self.board.is_insufficient_material() {
+            Termination::InsufficientMaterial
+        } else {
+            return None;
+        };
+        Some((GameResult::Draw, termination))
+    }
+
     pub fn exportPosition(&self) -> String {
         let mut FEN = String::new();
         // board
@@ -103,7 +256,7 @@ impl Game {
         FEN += if self.turn { "w" } else { "b" };
         // castling rights
         FEN += " ";
-        FEN += "KQkq";
+        FEN += &self.board.export_castling_rights();
         // en passant square
         FEN += " ";
         FEN += &self.board.exportEnPassantSquare();
@@ -116,3 +269,38 @@ impl Game {
         FEN
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_fen_reads_valid_positions() {
+        let game =
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(game.exportPosition(), Game::default().exportPosition());
+        // EPD leaves the counters out
+        let game = Game::from_fen("8/8/8/4k3/8/8/4P3/4K3 b - -").unwrap();
+        assert!(!game.turn);
+        assert_eq!((game.halfmove_clock, game.fullmove), (0, 1));
+    }
+
+    #[test]
+    fn from_fen_rejects_invalid_positions() {
+        for fen in [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9 0 1",
+            // no white king, two black kings
+            "4k3/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3kK3 w - - 0 1",
+            // the moves are counted from 1
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+        ] {
+            assert!(Game::from_fen(fen).is_none(), "{}", fen);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+// Purpose: The record of a game: every move in SAN and every position, to look back through it.
+//
+// The record has a current position, the one on the board. Going back through the game only
+// moves the current position, the moves after it stay until a different move is played from it.
+
+use super::{
+    game::{Game, GameResult, Termination},
+    pgn::{write_pgn, PgnHeader},
+    san::to_san,
+    Move,
+};
+
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    /// positions[i] is the position after i moves, positions[0] is the start
+    positions: Vec<Game>,
+    /// keys[i] is the zobrist key of positions[i]
+    keys: Vec<u64>,
+    moves: Vec<Move>,
+    san: Vec<String>,
+    /// index of the current position
+    current: usize,
+}
+
+impl Default for GameRecord {
+    fn default() -> Self {
+        Self::new(Game::default())
+    }
+}
+
+impl GameRecord {
+    pub fn new(start: Game) -> GameRecord {
+        GameRecord {
+            keys: vec![start.board.zobrist_key(start.turn)],
+            positions: vec![start],
+            moves: Vec::new(),
+            san: Vec::new(),
+            current: 0,
+        }
+    }
+
+    pub fn start(&self) -> &Game {
+        &self.positions[0]
+    }
+
+    /// The current position
+    pub fn game(&self) -> &Game {
+        &self.positions[self.current]
+    }
+
+    /// Keys of the positions before the current one, for repetitions
+    pub fn history(&self) -> &[u64] {
+        &self.keys[..self.current]
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    pub fn san(&self) -> &[String] {
+        &self.san
+    }
+
+    /// Amount of moves before the current position
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.current == self.moves.len()
+    }
+
+    /// The last position, after all moves, wherever the current position is
+    pub fn last_position(&self) -> &Game {
+        &self.positions[self.moves.len()]
+    }
+
+    /// The end of the game by the rules, judged on the last position
+    pub fn end(&self) -> Option<(GameResult, Termination)> {
+        self.last_position().end(&self.keys[..self.moves.len()])
+    }
+
+    /// The move that led to the current position
+    pub fn last_move(&self) -> Option<Move> {
+        self.current.checked_sub(1).map(|i| self.moves[i])
+    }
+
+    /// Shows the position after `ply` moves, or the last one when there are fewer
+    pub fn go_to(&mut self, ply: usize) {
+        self.current = ply.min(self.moves.len());
+    }
+
+    pub fn back(&mut self) {
+        self.go_to(self.current.saturating_sub(1));
+    }
+
+    pub fn forward(&mut self) {
+        self.go_to(self.current + 1);
+    }
+
+    /// Plays a move from the current position. When that isn't the last position the moves
+    /// after it are thrown away, unless the move is the one that was played there already.
+    pub fn play(&mut self, r#move: Move) {
+        if self.moves.get(self.current) == Some(&r#move) {
+            self.current += 1;
+            return;
+        }
+        self.positions.truncate(self.current + 1);
+        self.keys.truncate(self.current + 1);
+        self.moves.truncate(self.current);
+        self.san.truncate(self.current);
+
+        let mut game = self.game().clone();
+        self.san.push(to_san(&game.board, game.turn, &r#move));
+        game.make_move(&r#move);
+        self.keys.push(game.board.zobrist_key(game.turn));
+        self.positions.push(game);
+        self.moves.push(r#move);
+        self.current += 1;
+    }
+
+    /// The move number of a move, and whether white played it
+    pub fn move_number(&self, index: usize) -> (u32, bool) {
+        let position = &self.positions[index];
+        (position.fullmove, position.turn)
+    }
+
+    /// The whole game in PGN, also the moves after the current position.
+    /// `end` is the result and why the game ended, None while it is still going on.
+    pub fn to_pgn(
+        &self,
+        white: &str,
+        black: &str,
+        end: Option<(GameResult, Termination)>,
+    ) -> String {
+        let extra = match end {
+            Some((_, Termination::Timeout)) => vec![("Termination", "time forfeit".to_string())],
+            Some(_) => vec![("Termination", "normal".to_string())],
+            None => Vec::new(),
+        };
+        let header = PgnHeader {
+            event: "Casual game",
+            round: "-".to_string(),
+            white,
+            black,
+            result: end.map_or("*", |(result, _)| result.to_pgn()),
+            extra,
+        };
+        let mut pgn = Vec::new();
+        write_pgn(&mut pgn, &header, self.start(), &self.moves)
+            .expect("writing to a Vec doesn't fail");
+        String::from_utf8(pgn).expect("PGN is written as UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(record: &mut GameRecord, notation: &str) {
+        record.play(Move::from_algebraic_notation(notation).unwrap());
+    }
+
+    fn record(moves: &[&str]) -> GameRecord {
+        let mut record = GameRecord::default();
+        for notation in moves {
+            play(&mut record, notation);
+        }
+        record
+    }
+
+    #[test]
+    fn plays_moves() {
+        let record = record(&["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(record.san(), ["e4", "e5", "Nf3"]);
+        assert_eq!(record.current(), 3);
+        assert!(record.is_at_end());
+        assert!(!record.game().turn);
+        assert_eq!(record.history().len(), 3);
+        assert_eq!(record.move_number(2), (2, true));
+        assert_eq!(
+            record.last_move(),
+            Move::from_algebraic_notation("g1f3").ok()
+        );
+    }
+
+    #[test]
+    fn going_back_keeps_the_moves() {
+        let mut record = record(&["e2e4", "e7e5", "g1f3"]);
+        record.back();
+        record.back();
+        assert_eq!(record.current(), 1);
+        assert_eq!(record.moves().len(), 3);
+        assert_eq!(record.history(), &record.keys[..1]);
+        record.forward();
+        assert_eq!(record.current(), 2);
+        record.go_to(10);
+        assert!(record.is_at_end());
+        record.go_to(0);
+        assert_eq!(record.last_move(), None);
+        record.back();
+        assert_eq!(record.current(), 0);
+    }
+
+    #[test]
+    fn the_end_is_judged_on_the_last_position() {
+        let mut record = record(&["f2f3", "e7e5", "g2g4", "d8h4"]);
+        let mate = Some((GameResult::BlackWins, Termination::Checkmate));
+        assert_eq!(record.end(), mate);
+        record.go_to(1);
+        assert_eq!(record.end(), mate);
+        assert_eq!(
+            record.last_position().exportPosition(),
+            record.positions[4].exportPosition()
+        );
+        assert_eq!(self::record(&["e2e4"]).end(), None);
+    }
+
+    #[test]
+    fn the_same_move_follows_the_game() {
+        let mut record = record(&["e2e4", "e7e5", "g1f3"]);
+        record.go_to(1);
+        play(&mut record, "e7e5");
+        assert_eq!(record.current(), 2);
+        assert_eq!(record.san(), ["e4", "e5", "Nf3"]);
+    }
+
+    #[test]
+    fn another_move_starts_a_new_line() {
+        let mut record = record(&["e2e4", "e7e5", "g1f3"]);
+        record.go_to(1);
+        play(&mut record, "c7c5");
+        assert_eq!(record.san(), ["e4", "c5"]);
+        assert!(record.is_at_end());
+        assert_eq!(record.keys.len(), 3);
+        let mut game = Game::default();
+        for notation in ["e2e4", "c7c5"] {
+            game.make_move(&Move::from_algebraic_notation(notation).unwrap());
+        }
+        assert_eq!(record.game().exportPosition(), game.exportPosition());
+        assert_eq!(record.keys[2], game.board.zobrist_key(game.turn));
+    }
+
+    #[test]
+    fn pgn_of_a_position() {
+        let start = Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").unwrap();
+        let mut record = GameRecord::new(start);
+        play(&mut record, "e8d7");
+        play(&mut record, "e2e4");
+        let pgn = record.to_pgn("me", "bot", None);
+        assert!(
+            pgn.contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]"),
+            "{}",
+            pgn
+        );
+        assert!(pgn.contains("40... Kd7 41. e4 *"), "{}", pgn);
+        assert!(!pgn.contains("Termination"));
+        let pgn = record.to_pgn("me", "bot", Some((GameResult::Draw, Termination::Timeout)));
+        assert!(pgn.contains("[Termination \"time forfeit\"]"));
+    }
+}
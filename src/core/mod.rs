@@ -18,6 +18,27 @@ pub enum PieceType {
     WhiteRook,
 }
 impl PieceType {
+    /// All piece types, in the same order as `PieceType::index`
+    pub const ALL: [PieceType; 12] = [
+        PieceType::BlackBishop,
+        PieceType::BlackKing,
+        PieceType::BlackKnight,
+        PieceType::BlackPawn,
+        PieceType::BlackQueen,
+        PieceType::BlackRook,
+        PieceType::WhiteBishop,
+        PieceType::WhiteKing,
+        PieceType::WhiteKnight,
+        PieceType::WhitePawn,
+        PieceType::WhiteQueen,
+        PieceType::WhiteRook,
+    ];
+
+    /// Index of the piece type in 0..12, used to look up per piece tables
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
     pub fn from_char(c: char) -> Option<PieceType> {
         match c {
             'p' => Some(PieceType::BlackPawn),
@@ -74,12 +95,64 @@ impl PieceType {
     }
 }
 
+/// The piece a pawn promotes to, its color is the color of the pawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Promotion {
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+}
+impl Promotion {
+    /// All promotions, the queen first
+    pub const ALL: [Promotion; 4] = [
+        Promotion::Queen,
+        Promotion::Rook,
+        Promotion::Bishop,
+        Promotion::Knight,
+    ];
+
+    /// Reads 'q', 'r', 'b' or 'n', in either case
+    pub fn from_char(c: char) -> Option<Promotion> {
+        match c.to_ascii_lowercase() {
+            'q' => Some(Promotion::Queen),
+            'r' => Some(Promotion::Rook),
+            'b' => Some(Promotion::Bishop),
+            'n' => Some(Promotion::Knight),
+            _ => None,
+        }
+    }
+    /// The lowercase letter, as in UCI notation
+    pub fn to_char(&self) -> char {
+        match self {
+            Promotion::Queen => 'q',
+            Promotion::Rook => 'r',
+            Promotion::Bishop => 'b',
+            Promotion::Knight => 'n',
+        }
+    }
+    pub fn piece(&self, color: bool) -> PieceType {
+        match (self, color) {
+            (Promotion::Queen, true) => PieceType::WhiteQueen,
+            (Promotion::Rook, true) => PieceType::WhiteRook,
+            (Promotion::Bishop, true) => PieceType::WhiteBishop,
+            (Promotion::Knight, true) => PieceType::WhiteKnight,
+            (Promotion::Queen, false) => PieceType::BlackQueen,
+            (Promotion::Rook, false) => PieceType::BlackRook,
+            (Promotion::Bishop, false) => PieceType::BlackBishop,
+            (Promotion::Knight, false) => PieceType::BlackKnight,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Move {
     pub column_from: u32,
     pub row_from: u32,
     pub column_to: u32,
     pub row_to: u32,
+    /// the piece a pawn reaching the last row becomes, a queen when it is None
+    pub promotion: Option<Promotion>,
 }
 impl Move {
     pub fn new(from: (u32, u32), to: (u32, u32)) -> Move {
@@ -88,17 +161,26 @@ impl Move {
             row_from: from.1,
             column_to: to.0,
             row_to: to.1,
+            promotion: None,
+        }
+    }
+
+    pub fn with_promotion(self, promotion: Promotion) -> Move {
+        Move {
+            promotion: Some(promotion),
+            ..self
         }
     }
 
-    /// Converts a algebraic notation string to a Move
-    /// ex: "a2a4" -> Move { column_from: 0, row_from: 1, column_to: 0, row_to: 3 }
-    /// ex: "c1f4" -> Move { column_from: 2, row_from: 0, column_to: 5, row_to: 3 }
+    /// Converts a algebraic notation string to a Move, row 0 is the 8th rank
+    /// ex: "a2a4" -> Move { column_from: 0, row_from: 6, column_to: 0, row_to: 4 }
+    /// ex: "c1f4" -> Move { column_from: 2, row_from: 7, column_to: 5, row_to: 4 }
+    /// ex: "e7e8n" -> Move { column_from: 4, row_from: 1, column_to: 4, row_to: 0, promotion: Knight }
     pub fn from_algebraic_notation(notation: &str) -> Result<Move, String> {
         let mut chars = notation.chars();
         let column_from = match chars.next() {
             Some(c) => {
-                let c = c as u32 - 97;
+                let c = (c as u32).wrapping_sub(97);
                 if c > 7 {
                     return Err(String::from("Invalid algebraic notation"));
                 }
@@ -108,17 +190,17 @@ impl Move {
         };
         let row_from = match chars.next() {
             Some(c) => {
-                let c = c as u32 - 49;
+                let c = (c as u32).wrapping_sub(49);
                 if c > 7 {
                     return Err(String::from("Invalid algebraic notation"));
                 }
-                c
+                7 - c
             }
             None => return Err(String::from("Invalid algebraic notation")),
         };
         let column_to = match chars.next() {
             Some(c) => {
-                let c = c as u32 - 97;
+                let c = (c as u32).wrapping_sub(97);
                 if c > 7 {
                     return Err(String::from("Invalid algebraic notation"));
                 }
@@ -128,14 +210,21 @@ impl Move {
         };
         let row_to = match chars.next() {
             Some(c) => {
-                let c = c as u32 - 49;
+                let c = (c as u32).wrapping_sub(49);
                 if c > 7 {
                     return Err(String::from("Invalid algebraic notation"));
                 }
-                c
+                7 - c
             }
             None => return Err(String::from("Invalid algebraic notation")),
         };
+        let promotion = match chars.next() {
+            Some(c) => match Promotion::from_char(c) {
+                Some(promotion) => Some(promotion),
+                None => return Err(String::from("Invalid algebraic notation")),
+            },
+            None => None,
+        };
         if chars.next().is_some() {
             return Err(String::from("Invalid algebraic notation"));
         }
@@ -144,6 +233,7 @@ impl Move {
             row_from,
             column_to,
             row_to,
+            promotion,
         })
     }
 
@@ -152,7 +242,11 @@ impl Move {
         let column_to = (self.column_to + 97) as u8 as char;
         let row_from = 8 - self.row_from;
         let row_to = 8 - self.row_to;
-        format!("{}{}{}{}", column_from, row_from, column_to, row_to)
+        let mut notation = format!("{}{}{}{}", column_from, row_from, column_to, row_to);
+        if let Some(promotion) = self.promotion {
+            notation.push(promotion.to_char());
+        }
+        notation
     }
 }
 
@@ -162,7 +256,13 @@ impl Debug for Move {
     }
 }
 
-use macroquad::logging::info;
 pub mod board;
+pub mod clock;
 pub mod game;
+pub mod game_record;
+pub mod perft;
+pub mod pgn;
+pub mod polyglot;
+pub mod san;
 pub mod utils;
+pub mod zobrist;
@@ -0,0 +1,105 @@
+// Purpose: Perft, counts the positions reachable in a number of plies to check the move generator.
+
+use super::{board::Board, Move};
+
+/// The amount of move sequences of `depth` plies from this position
+pub fn perft(board: &Board, turn: bool, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = board.get_legal_moves(turn);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves
+        .iter()
+        .map(|r#move| {
+            let mut child = board.clone();
+            child.move_piece(r#move);
+            perft(&child, !turn, depth - 1)
+        })
+        .sum()
+}
+
+/// Perft split over the legal moves, to find which move the generator gets wrong
+pub fn divide(board: &Board, turn: bool, depth: u32) -> Vec<(Move, u64)> {
+    board
+        .get_legal_moves(turn)
+        .into_iter()
+        .map(|r#move| {
+            let mut child = board.clone();
+            child.move_piece(&r#move);
+            (r#move, perft(&child, !turn, depth.saturating_sub(1)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::game::Game;
+
+    /// The counts of perft 1, 2, ... from a position
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let game = Game::from_fen(fen).unwrap();
+        for (depth, nodes) in (1..).zip(expected) {
+            assert_eq!(
+                perft(&game.board, game.turn, depth),
+                *nodes,
+                "{} depth {}",
+                fen,
+                depth
+            );
+        }
+    }
+
+    #[test]
+    fn start_position() {
+        assert_perft(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197281],
+        );
+    }
+
+    #[test]
+    fn kiwipete() {
+        assert_perft(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862],
+        );
+    }
+
+    #[test]
+    fn position_3() {
+        assert_perft(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238],
+        );
+    }
+
+    #[test]
+    fn position_4() {
+        assert_perft(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467],
+        );
+    }
+
+    #[test]
+    fn position_5() {
+        assert_perft(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379],
+        );
+    }
+
+    #[test]
+    fn divide_adds_up_to_perft() {
+        let game = Game::default();
+        let total: u64 = divide(&game.board, game.turn, 3)
+            .iter()
+            .map(|(_, n)| n)
+            .sum();
+        assert_eq!(total, perft(&game.board, game.turn, 3));
+    }
+}
@@ -0,0 +1,307 @@
+// Purpose: Reads and writes games in PGN, the text format chess games are shared in.
+//
+// Only the main line is kept, comments, variations and annotations are skipped.
+// The moves stay in SAN, see `san::parse_san` to turn them into moves.
+
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::{
+    game::Game,
+    san::{parse_san, to_san},
+    Move,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct PgnGame {
+    /// the tag pairs, ex: ("White", "Carlsen")
+    pub tags: Vec<(String, String)>,
+    /// the moves of the main line in SAN
+    pub moves: Vec<String>,
+    /// "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position the game starts from, the FEN tag or the normal start position.
+    /// None when the FEN tag isn't a valid position.
+    pub fn start_position(&self) -> Option<Game> {
+        match self.tag("FEN") {
+            Some(fen) => Game::from_fen(fen),
+            None => Some(Game::default()),
+        }
+    }
+
+    /// Plays the moves from the start position, stops at the first move that isn't legal.
+    /// None when the start position isn't valid.
+    pub fn replay(&self) -> Option<(Game, Vec<Move>)> {
+        let mut game = self.start_position()?;
+        let mut moves = Vec::new();
+        for san in &self.moves {
+            match parse_san(&game.board, game.turn, san) {
+                Some(r#move) => {
+                    game.make_move(&r#move);
+                    moves.push(r#move);
+                }
+                None => break,
+            }
+        }
+        Some((game, moves))
+    }
+}
+
+/// Reads all games of a PGN text
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut chars = text.chars().peekable();
+    let mut token = String::new();
+    // depth of the variation we are in, 0 is the main line
+    let mut variation = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                }
+            }
+            ';' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => variation += 1,
+            ')' => variation = (variation - 1).max(0),
+            '[' if variation == 0 => {
+                // a tag after the moves starts a new game without a result
+                if !game.moves.is_empty() {
+                    games.push(std::mem::take(&mut game));
+                }
+                let tag: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                if let Some((name, value)) = tag.trim().split_once(' ') {
+                    let value = value.trim().trim_matches('"').replace("\\\"", "\"");
+                    game.tags.push((name.to_string(), value));
+                }
+            }
+            _ if c.is_whitespace() => {}
+            _ => {
+                token.push(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{};()[".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                if variation == 0 && read_token(&token, &mut game) {
+                    games.push(std::mem::take(&mut game));
+                }
+                token.clear();
+            }
+        }
+    }
+    if !game.moves.is_empty() || !game.tags.is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+/// Adds a token of the movetext to the game, returns true when it is the result that ends the game
+fn read_token(token: &str, game: &mut PgnGame) -> bool {
+    if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+        game.result = token.to_string();
+        return true;
+    }
+    // move numbers can be glued to the move, ex: "12.e4" or "12...e5"
+    let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if !san.is_empty() && !san.starts_with('$') {
+        game.moves.push(san.to_string());
+    }
+    false
+}
+
+/// The tags of a game to write, the ones of the Seven Tag Roster that aren't always the same
+pub struct PgnHeader<'a> {
+    pub event: &'a str,
+    pub round: String,
+    pub white: &'a str,
+    pub black: &'a str,
+    /// "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: &'a str,
+    /// tags written after the others, ex: ("Termination", "normal")
+    pub extra: Vec<(&'static str, String)>,
+}
+
+/// Writes a game played today from `start`, with a FEN tag when that isn't the normal start
+pub fn write_pgn(
+    out: &mut dyn Write,
+    header: &PgnHeader,
+    start: &Game,
+    moves: &[Move],
+) -> io::Result<()> {
+    let mut tags = vec![
+        ("Event", header.event.to_string()),
+        ("Site", "?".to_string()),
+        ("Date", today()),
+        ("Round", header.round.clone()),
+        ("White", header.white.to_string()),
+        ("Black", header.black.to_string()),
+        ("Result", header.result.to_string()),
+    ];
+    let fen = start.exportPosition();
+    if fen != Game::default().exportPosition() {
+        tags.push(("SetUp", "1".to_string()));
+        tags.push(("FEN", fen));
+    }
+    for (name, value) in tags.iter().chain(&header.extra) {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(out, "[{} \"{}\"]", name, value)?;
+    }
+    writeln!(out)?;
+
+    let mut position = start.clone();
+    let mut line = String::new();
+    for (i, r#move) in moves.iter().enumerate() {
+        let mut text = String::new();
+        if position.turn || i == 0 {
+            text.push_str(&format!("{}.", position.fullmove));
+            if !position.turn {
+                text.push_str("..");
+            }
+            text.push(' ');
+        }
+        text.push_str(&to_san(&position.board, position.turn, r#move));
+        position.make_move(r#move);
+        // PGN lines should stay below 80 characters
+        if line.len() + text.len() + 1 > 79 {
+            writeln!(out, "{}", line.trim_end())?;
+            line.clear();
+        }
+        line.push_str(&text);
+        line.push(' ');
+    }
+    line.push_str(header.result);
+    writeln!(out, "{}", line)?;
+    writeln!(out)
+}
+
+/// ex: "2024.03.17", in UTC
+fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let (year, month, day) = date_from_days((seconds / 86400) as i64);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// The year, month and day of a day counted from 1970-01-01, from Howard Hinnant's
+/// `civil_from_days`
+fn date_from_days(days: i64) -> (i64, u32, u32) {
+    // eras of 400 years starting on March 1st of the year 0
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // months starting from March
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: &str = r#"[Event "Test"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 e5 {a comment} 2. Nf3 (2. f4 exf4) Nc6 3. Bb5 $1 a6 1-0
+
+[Event "Bad FEN"]
+[FEN "not a position"]
+[Result "*"]
+
+1. e4 *
+"#;
+
+    #[test]
+    fn reads_tags_and_main_line() {
+        let games = parse_pgn(PGN);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("White"), Some("A"));
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6"]);
+        let (game, moves) = games[0].replay().unwrap();
+        assert_eq!(moves.len(), 6);
+        assert_eq!(game.fullmove, 4);
+    }
+
+    #[test]
+    fn invalid_fen_tag_is_no_start_position() {
+        let games = parse_pgn(PGN);
+        assert!(games[1].start_position().is_none());
+        assert!(games[1].replay().is_none());
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date_from_days(0), (1970, 1, 1));
+        assert_eq!(date_from_days(11_016), (2000, 2, 29));
+        assert_eq!(date_from_days(19_782), (2024, 2, 29));
+        assert_eq!(date_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn written_games_read_back() {
+        let start = Game::from_fen("4k3/8/8/8/8/8/4P3/R3K3 b Q - 0 30").unwrap();
+        let moves: Vec<Move> = ["e8d7", "e1c1", "d7c6", "e2e4"]
+            .iter()
+            .map(|notation| Move::from_algebraic_notation(notation).unwrap())
+            .collect();
+        let header = PgnHeader {
+            event: "A \"quoted\" event",
+            round: "3".to_string(),
+            white: "White",
+            black: "Black",
+            result: "*",
+            extra: vec![("Termination", "unterminated".to_string())],
+        };
+        let mut out = Vec::new();
+        write_pgn(&mut out, &header, &start, &moves).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(
+            text.contains("\n30... Kd7 31. O-O-O+ Kc6 32. e4 *\n"),
+            "{}",
+            text
+        );
+
+        let games = parse_pgn(&text);
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.tag("Event"), Some("A \"quoted\" event"));
+        let date = game.tag("Date").unwrap();
+        assert!(date.len() == 10 && date.chars().filter(|c| *c == '.').count() == 2);
+        assert_eq!(game.tag("Termination"), Some("unterminated"));
+        assert_eq!(game.result, "*");
+        let (_, replayed) = game.replay().unwrap();
+        assert_eq!(replayed, moves);
+    }
+}
@@ -0,0 +1,287 @@
+// Purpose: Standard algebraic notation (SAN), the notation of PGN files, ex: "Nf3", "exd5", "O-O".
+
+use crate::core::{board::Board, Move, PieceType, Promotion};
+
+/// Finds the legal move written in SAN, None when there is no such move or it is ambiguous.
+/// A pawn reaching the last row needs the promotion piece, ex: "e8=Q" or "e8Q".
+pub fn parse_san(board: &Board, turn: bool, san: &str) -> Option<Move> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let legal_moves = board.get_legal_moves(turn);
+    let row = if turn { 7 } else { 0 };
+    let castling_column = match san {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None,
+    };
+    if let Some(column) = castling_column {
+        let king = if turn {
+            PieceType::WhiteKing
+        } else {
+            PieceType::BlackKing
+        };
+        return legal_moves.into_iter().find(|m| {
+            board.get_piece_pos(m.column_from, m.row_from) == Some(king)
+                && (m.column_from, m.row_from) == (4, row)
+                && (m.column_to, m.row_to) == (column, row)
+        });
+    }
+
+    // split off the promotion, "e8=Q" or "e8Q"
+    let (san, promotion) = match san.strip_suffix(['Q', 'R', 'B', 'N']) {
+        Some(rest) => (
+            rest.strip_suffix('=').unwrap_or(rest),
+            san.chars().last().and_then(Promotion::from_char),
+        ),
+        None => (san, None),
+    };
+    let (letter, rest) = match san.chars().next()? {
+        c @ ('K' | 'Q' | 'R' | 'B' | 'N') => (c, &san[1..]),
+        _ => ('P', san),
+    };
+    let piece = PieceType::from_char(if turn {
+        letter
+    } else {
+        letter.to_ascii_lowercase()
+    })?;
+    let rest: Vec<char> = rest.chars().filter(|c| *c != 'x' && *c != '-').collect();
+    if rest.len() < 2 {
+        return None;
+    }
+    let (disambiguation, destination) = rest.split_at(rest.len() - 2);
+    let (column_to, row_to) = square(destination[0], destination[1])?;
+    let mut column_from = None;
+    let mut row_from = None;
+    for c in disambiguation {
+        match c {
+            'a'..='h' => column_from = Some(*c as u32 - 'a' as u32),
+            '1'..='8' => row_from = Some(7 - (*c as u32 - '1' as u32)),
+            _ => return None,
+        }
+    }
+
+    let mut candidates = legal_moves.into_iter().filter(|m| {
+        (m.column_to, m.row_to) == (column_to, row_to)
+            && board.get_piece_pos(m.column_from, m.row_from) == Some(piece)
+            && column_from.is_none_or(|c| c == m.column_from)
+            && row_from.is_none_or(|r| r == m.row_from)
+            && m.promotion == promotion
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(r#move), None) => Some(r#move),
+        _ => None,
+    }
+}
+
+/// Writes a legal move in SAN, ex: "Nbd7", "exd5", "O-O", "e8=Q+"
+pub fn to_san(board: &Board, turn: bool, r#move: &Move) -> String {
+    let Some(piece) = board.get_piece_pos(r#move.column_from, r#move.row_from) else {
+        return r#move.to_algebraic_notation();
+    };
+    let is_king = matches!(piece, PieceType::WhiteKing | PieceType::BlackKing);
+    let is_pawn = matches!(piece, PieceType::WhitePawn | PieceType::BlackPawn);
+    let mut san = String::new();
+    if is_king && r#move.column_from == 4 && r#move.column_to == 6 {
+        san.push_str("O-O");
+    } else if is_king && r#move.column_from == 4 && r#move.column_to == 2 {
+        san.push_str("O-O-O");
+    } else {
+        let capture = board
+            .get_piece_pos(r#move.column_to, r#move.row_to)
+            .is_some()
+            || (is_pawn && r#move.column_from != r#move.column_to);
+        if is_pawn {
+            if capture {
+                san.push(column_char(r#move.column_from));
+            }
+        } else {
+            san.push(piece.to_char().to_ascii_uppercase());
+            // other pieces of the same type that can go to the same square
+            let others: Vec<Move> = board
+                .get_legal_moves(turn)
+                .into_iter()
+                .filter(|m| {
+                    (m.column_to, m.row_to) == (r#move.column_to, r#move.row_to)
+                        && (m.column_from, m.row_from) != (r#move.column_from, r#move.row_from)
+                        && board.get_piece_pos(m.column_from, m.row_from) == Some(piece)
+                })
+                .collect();
+            if !others.is_empty() {
+                if others.iter().all(|m| m.column_from != r#move.column_from) {
+                    san.push(column_char(r#move.column_from));
+                } else if others.iter().all(|m| m.row_from != r#move.row_from) {
+                    san.push(row_char(r#move.row_from));
+                } else {
+                    san.push(column_char(r#move.column_from));
+                    san.push(row_char(r#move.row_from));
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push(column_char(r#move.column_to));
+        san.push(row_char(r#move.row_to));
+        if is_pawn && r#move.row_to == if turn { 0 } else { 7 } {
+            let promotion = r#move.promotion.unwrap_or(Promotion::Queen);
+            san.push('=');
+            san.push(promotion.to_char().to_ascii_uppercase());
+        }
+    }
+
+    let mut child = board.clone();
+    child.move_piece(r#move);
+    if child.is_in_check(!turn) {
+        san.push(if child.get_legal_moves(!turn).is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    san
+}
+
+fn column_char(column: u32) -> char {
+    (b'a' + column as u8) as char
+}
+
+/// The rank of a row, row 0 is the 8th rank
+fn row_char(row: u32) -> char {
+    (b'8' - row as u8) as char
+}
+
+/// The column and row of a square like ('e', '4'), row 0 is the 8th rank
+fn square(column: char, row: char) -> Option<(u32, u32)> {
+    if !('a'..='h').contains(&column) || !('1'..='8').contains(&row) {
+        return None;
+    }
+    Some((column as u32 - 'a' as u32, 7 - (row as u32 - '1' as u32)))
+}
+
+/// Writes a line of moves in SAN, like a principal variation
+pub fn line_to_san(board: &Board, turn: bool, moves: &[Move]) -> Vec<String> {
+    let mut board = board.clone();
+    let mut turn = turn;
+    let mut line = Vec::new();
+    for r#move in moves {
+        line.push(to_san(&board, turn, r#move));
+        board.move_piece(r#move);
+        turn = !turn;
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::game::Game;
+
+    fn san(fen: &str, notation: &str) -> String {
+        let game = Game::from_fen(fen).unwrap();
+        let r#move = Move::from_algebraic_notation(notation).unwrap();
+        to_san(&game.board, game.turn, &r#move)
+    }
+
+    #[test]
+    fn writes_san() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san(start, "g1f3"), "Nf3");
+        assert_eq!(san("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), "exd5");
+        // en passant is a capture too
+        assert_eq!(san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+        assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1"), "O-O");
+        assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8"), "O-O-O");
+        assert_eq!(san("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e7e8q"), "e8=Q");
+        assert_eq!(san("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8+");
+        assert_eq!(san("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+    }
+
+    #[test]
+    fn disambiguates() {
+        // knights on b1 and f1 both reach d2
+        assert_eq!(san("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "b1d2"), "Nbd2");
+        // rooks on a1 and a5 both reach a3
+        assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+        // queens on a1, a3 and c1 all reach b2
+        assert_eq!(san("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "a1b2"), "Qa1b2");
+    }
+
+    #[test]
+    fn reads_back_every_move() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            for r#move in game.board.get_legal_moves(game.turn) {
+                let written = to_san(&game.board, game.turn, &r#move);
+                assert_eq!(
+                    parse_san(&game.board, game.turn, &written),
+                    Some(r#move),
+                    "{} in {}",
+                    written,
+                    fen
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reads_other_spellings() {
+        {
+            let game = Game::from_fen("r3k2r/8/8/8/8/8/4P3/R3K2R w KQkq - 0 1").unwrap();
+            let parse = |san| parse_san(&game.board, game.turn, san);
+            assert_eq!(parse("0-0"), parse("O-O"));
+            assert_eq!(parse("O-O+"), parse("O-O"));
+            assert_eq!(parse("e4!?"), Move::from_algebraic_notation("e2e4").ok());
+            assert_eq!(parse("Rh1-h8"), parse("Rxh8"));
+            // the king is in the way of the rook on h1
+            assert_eq!(parse("Rd1"), Move::from_algebraic_notation("a1d1").ok());
+            assert_eq!(parse("Nf3"), None);
+            assert_eq!(parse("z9"), None);
+        }
+        {
+            // there are two rooks that can go to a3
+            let game = Game::from_fen("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1").unwrap();
+            let parse = |san| parse_san(&game.board, game.turn, san);
+            assert_eq!(parse("Ra3"), None);
+            assert_eq!(parse("R5a3"), Move::from_algebraic_notation("a5a3").ok());
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_promotions() {
+        let game = Game::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let parse = |san| parse_san(&game.board, game.turn, san);
+        assert_eq!(parse("a8=Q"), Move::from_algebraic_notation("a7a8q").ok());
+        assert_eq!(parse("a8=N"), Move::from_algebraic_notation("a7a8n").ok());
+        assert_eq!(parse("a8R"), Move::from_algebraic_notation("a7a8r").ok());
+        assert_eq!(
+            parse("axb8=B+"),
+            Move::from_algebraic_notation("a7b8b").ok()
+        );
+        // the promotion piece can't be left out
+        assert_eq!(parse("a8"), None);
+        assert_eq!(parse("a8=K"), None);
+        let fen = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san(fen, "a7a8n"), "a8=N");
+        assert_eq!(san(fen, "a7a8r"), "a8=R");
+        assert_eq!(san(fen, "a7b8b"), "axb8=B");
+    }
+
+    #[test]
+    fn writes_a_line() {
+        let game = Game::default();
+        let line: Vec<Move> = ["e2e4", "e7e5", "g1f3"]
+            .iter()
+            .map(|notation| Move::from_algebraic_notation(notation).unwrap())
+            .collect();
+        assert_eq!(
+            line_to_san(&game.board, game.turn, &line),
+            ["e4", "e5", "Nf3"]
+        );
+    }
+}
@@ -28,14 +28,14 @@ pub fn translate_single_fen(pos: &str) -> Result<(u32, u32), String> {
         _ => return Err("Invalid column".to_string()),
     };
     let row = match row {
-        '1' => 0,
-        '2' => 1,
-        '3' => 2,
-        '4' => 3,
-        '5' => 4,
-        '6' => 5,
-        '7' => 6,
-        '8' => 7,
+        '1' => 7,
+        '2' => 6,
+        '3' => 5,
+        '4' => 4,
+        '5' => 3,
+        '6' => 2,
+        '7' => 1,
+        '8' => 0,
         _ => return Err("Invalid row".to_string()),
     };
     Ok((column, row))
@@ -72,14 +72,14 @@ pub fn translate_coordinates(column: u32, row: u32) -> String {
         _ => panic!("Invalid column"),
     };
     FEN += match row {
-        0 => "1",
-        1 => "2",
-        2 => "3",
-        3 => "4",
-        4 => "5",
-        5 => "6",
-        6 => "7",
-        7 => "8",
+        7 => "1",
+        6 => "2",
+        5 => "3",
+        4 => "4",
+        3 => "5",
+        2 => "6",
+        1 => "7",
+        0 => "8",
         _ => panic!("Invalid row"),
     };
     FEN
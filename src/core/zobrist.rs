@@ -0,0 +1,117 @@
+// Purpose: Zobrist keys used to hash positions.
+//
+// The keys are generated at compile time from a fixed seed, so hashes are
+// stable between runs and between builds.
+
+use crate::core::PieceType;
+
+const PIECE_KEYS: usize = 0;
+const SIDE_KEY: usize = PIECE_KEYS + 12 * 64;
+const CASTLING_KEYS: usize = SIDE_KEY + 1;
+const EN_PASSANT_KEYS: usize = CASTLING_KEYS + 16;
+const KEY_COUNT: usize = EN_PASSANT_KEYS + 8;
+
+const KEYS: [u64; KEY_COUNT] = generate_keys(0x2545_F491_4F6C_DD1D);
+
+/// xorshift64* so the keys can be generated in a const context
+const fn generate_keys(seed: u64) -> [u64; KEY_COUNT] {
+    let mut keys = [0; KEY_COUNT];
+    let mut state = seed;
+    let mut i = 0;
+    while i < KEY_COUNT {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        keys[i] = state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        i += 1;
+    }
+    keys
+}
+
+/// Key for a piece standing on a square (0..64, `column + row * 8`)
+pub fn piece_key(piece: PieceType, square: u32) -> u64 {
+    KEYS[PIECE_KEYS + piece.index() * 64 + square as usize]
+}
+
+/// Key that is xored in when it is white's turn
+pub fn side_key() -> u64 {
+    KEYS[SIDE_KEY]
+}
+
+/// Key for a set of castling rights, see `Board::castling_rights`
+pub fn castling_key(castling_rights: u8) -> u64 {
+    KEYS[CASTLING_KEYS + (castling_rights & 0b1111) as usize]
+}
+
+/// Key for the column of the en passant square
+pub fn en_passant_key(column: u32) -> u64 {
+    KEYS[EN_PASSANT_KEYS + column as usize]
+}
+
+/// Xor of the keys of every piece in the bitboard
+pub fn bitboard_key(piece: PieceType, mut bitboard: u64) -> u64 {
+    let mut key = 0;
+    while bitboard != 0 {
+        key ^= piece_key(piece, bitboard.trailing_zeros());
+        bitboard &= bitboard - 1;
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{game::Game, Move};
+
+    fn key_after(fen: &str, moves: &[&str]) -> u64 {
+        let mut game = Game::from_fen(fen).unwrap();
+        for notation in moves {
+            game.make_move(&Move::from_algebraic_notation(notation).unwrap());
+        }
+        game.board.zobrist_key(game.turn)
+    }
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn keys_are_distinct() {
+        let mut keys = KEYS.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), KEY_COUNT);
+        assert!(!keys.contains(&0));
+    }
+
+    #[test]
+    fn transpositions_have_the_same_key() {
+        assert_eq!(
+            key_after(START, &["g1f3", "g8f6", "b1c3"]),
+            key_after(START, &["b1c3", "g8f6", "g1f3"])
+        );
+        assert_eq!(
+            key_after(START, &["g1f3", "g8f6", "f3g1", "f6g8"]),
+            key_after(START, &[])
+        );
+    }
+
+    #[test]
+    fn side_castling_and_en_passant_change_the_key() {
+        let position = "r3k2r/8/8/8/8/8/8/R3K2R";
+        let white = key_after(&format!("{} w KQkq - 0 1", position), &[]);
+        assert_ne!(white, key_after(&format!("{} b KQkq - 0 1", position), &[]));
+        assert_ne!(white, key_after(&format!("{} w Kkq - 0 1", position), &[]));
+        assert_ne!(white, key_after(&format!("{} w - - 0 1", position), &[]));
+        // the rook going and coming back loses the right to castle that side
+        assert_eq!(
+            key_after(
+                &format!("{} w KQkq - 0 1", position),
+                &["h1h2", "h8h7", "h2h1", "h7h8"]
+            ),
+            key_after(&format!("{} w Qq - 0 1", position), &[])
+        );
+        assert_ne!(
+            key_after("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1", &[]),
+            key_after("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1", &[])
+        );
+    }
+}
@@ -0,0 +1,109 @@
+// Purpose: Analyses the position on the board on a background thread, for the GUI.
+//
+// The search runs without limits until the position changes. Every finished iteration is put
+// where the frame loop can read it, so the frame loop never waits for the engine.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::core::game::Game;
+
+use super::search::{SearchInfo, SearchLimits};
+use super::Engine;
+
+pub struct Analysis {
+    engine: Arc<Mutex<Engine>>,
+    stop: Arc<AtomicBool>,
+    search: Option<JoinHandle<()>>,
+    /// key of the position the search is about, None when nothing is analysed
+    key: Option<u64>,
+    /// the last finished iteration of the search
+    info: Arc<Mutex<Option<SearchInfo>>>,
+}
+
+impl Default for Analysis {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analysis {
+    pub fn new() -> Analysis {
+        let engine = Engine::default();
+        Analysis {
+            stop: engine.stop_handle(),
+            engine: Arc::new(Mutex::new(engine)),
+            search: None,
+            key: None,
+            info: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Call every frame with the position on the board, a new position starts a new search.
+    /// `history` holds the keys of the positions played before the current one.
+    pub fn update(&mut self, game: &Game, history: &[u64]) {
+        let key = game.board.zobrist_key(game.turn);
+        if self.key == Some(key) {
+            return;
+        }
+        self.stop();
+        self.key = Some(key);
+        if game.board.get_legal_moves(game.turn).is_empty() {
+            return;
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+        let engine = self.engine.clone();
+        let info = self.info.clone();
+        let game = game.clone();
+        let history = history.to_vec();
+        self.search = Some(std::thread::spawn(move || {
+            let result = engine.lock().unwrap().search(
+                &game,
+                &history,
+                &SearchLimits::default(),
+                &mut |new_info| {
+                    if new_info.multi_pv == 1 {
+                        *info.lock().unwrap() = Some(new_info.clone());
+                    }
+                },
+            );
+            // tablebase moves are played without an iteration to report
+            let mut info = info.lock().unwrap();
+            if info.is_none() {
+                *info = Some(SearchInfo {
+                    depth: result.depth,
+                    multi_pv: 1,
+                    score: result.score,
+                    nodes: result.nodes,
+                    time: Duration::ZERO,
+                    hashfull: 0,
+                    pv: result.pv,
+                });
+            }
+        }));
+    }
+
+    /// The last finished iteration, None before the first one or when the game is over
+    pub fn info(&self) -> Option<SearchInfo> {
+        self.info.lock().unwrap().clone()
+    }
+
+    /// Stops the search and forgets what it found
+    pub fn stop(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            search.join().unwrap();
+        }
+        self.key = None;
+        *self.info.lock().unwrap() = None;
+    }
+}
+
+impl Drop for Analysis {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,62 @@
+// Purpose: Searches a fixed set of positions to a fixed depth, to measure the speed of the engine
+// and to check that a change doesn't change the search: the same code always searches the
+// same amount of nodes.
+
+use std::time::{Duration, Instant};
+
+use crate::core::game::Game;
+
+use super::search::SearchLimits;
+use super::Engine;
+
+/// depth of the bench when none is given
+pub const DEFAULT_DEPTH: u32 = 6;
+
+/// openings, middlegames and endgames, a few of them with tactics
+pub const POSITIONS: [&str; 12] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/2pp4/3P4/2PBPN2/PP1N1PPP/R1BQ1RK1 w - - 0 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "2rq1rk1/pp1bppbp/2np1np1/8/3NP3/1BN1BP2/PPPQ2PP/2KR3R b - - 0 11",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "8/8/8/5k2/8/3K4/4P3/8 w - - 0 1",
+    "3r2k1/p4ppp/8/8/8/8/P4PPP/3R2K1 w - - 0 1",
+];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BenchResult {
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+impl BenchResult {
+    pub fn nps(&self) -> u64 {
+        (self.nodes as f64 / self.time.as_secs_f64().max(1e-3)) as u64
+    }
+}
+
+/// Searches every position of the bench with one thread and a new game each time.
+/// `progress` is called after every position with its FEN and the nodes of its search.
+pub fn run(depth: u32, progress: &mut dyn FnMut(&str, u64)) -> BenchResult {
+    let mut engine = Engine::default();
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..Default::default()
+    };
+    let mut total = BenchResult::default();
+    for fen in POSITIONS {
+        let game = Game::from_fen(fen).expect("bench positions are valid");
+        engine.new_game();
+        let start = Instant::now();
+        let result = engine.search(&game, &[], &limits, &mut |_| {});
+        total.time += start.elapsed();
+        total.nodes += result.nodes;
+        progress(fen, result.nodes);
+    }
+    total
+}
@@ -0,0 +1,342 @@
+// Purpose: Opening books in the Polyglot format, so the bot plays varied openings without searching.
+//
+// A Polyglot book is a list of 16 byte entries sorted by key, every number is big endian:
+//   key    u64  polyglot hash of the position, see `Board::polyglot_key`
+//   move   u16  bits 0-5 to square, 6-11 from square, 12-14 promotion piece
+//   weight u16  how good the move is, relative to the other moves of the position
+//   learn  u32  unused
+// Squares count from a1 = 0 to h8 = 63 and castling is written as the king taking its own rook.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::{
+    board::Board,
+    game::Game,
+    pgn::{parse_pgn, PgnGame},
+    san::parse_san,
+    Move, PieceType, Promotion,
+};
+
+use super::rng::Rng;
+
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    /// the move in the polyglot encoding, see `encode_move`
+    pub r#move: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BookSelection {
+    /// pick a move with a chance relative to its weight, for variety
+    #[default]
+    Weighted,
+    /// always the move with the highest weight
+    Best,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+    /// sorted by key
+    entries: Vec<BookEntry>,
+    selection: BookSelection,
+    /// stop using the book after this many plies from the start of the game
+    max_depth: Option<u32>,
+}
+
+impl Book {
+    /// Reads a polyglot `.bin` book
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Book> {
+        let bytes = fs::read(path)?;
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the size of a polyglot book is a multiple of 16 bytes",
+            ));
+        }
+        Ok(Book::from_bytes(&bytes))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Book {
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| BookEntry {
+                key: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                r#move: u16::from_be_bytes(chunk[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(chunk[10..12].try_into().unwrap()),
+                learn: u32::from_be_bytes(chunk[12..16].try_into().unwrap()),
+            })
+            .collect();
+        // books should be sorted already, but the lookup depends on it
+        entries.sort_by_key(|entry| entry.key);
+        Book {
+            entries,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.r#move.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Writes the book as a polyglot `.bin` file
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn selection(&self) -> BookSelection {
+        self.selection
+    }
+
+    pub fn set_selection(&mut self, selection: BookSelection) {
+        self.selection = selection;
+    }
+
+    pub fn max_depth(&self) -> Option<u32> {
+        self.max_depth
+    }
+
+    /// The book is only used during the first `max_depth` plies of a game, None for no limit
+    pub fn set_max_depth(&mut self, max_depth: Option<u32>) {
+        self.max_depth = max_depth;
+    }
+
+    /// The legal book moves of a position with their weights, best first
+    pub fn moves(&self, board: &Board, turn: bool) -> Vec<(Move, u16)> {
+        let key = board.polyglot_key(turn);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let legal_moves = board.get_legal_moves(turn);
+        let mut moves: Vec<(Move, u16)> = self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((decode_move(board, entry.r#move)?, entry.weight)))
+            .filter(|(r#move, _)| legal_moves.contains(r#move))
+            .collect();
+        moves.sort_by_key(|(_, weight)| std::cmp::Reverse(*weight));
+        moves
+    }
+
+    /// Picks a book move for the game, None when out of book or past the max depth
+    pub fn probe(&self, game: &Game, rng: &mut Rng) -> Option<Move> {
+        let ply = game.fullmove.saturating_sub(1) * 2 + !game.turn as u32;
+        if self.max_depth.is_some_and(|max_depth| ply >= max_depth) {
+            return None;
+        }
+        let moves = self.moves(&game.board, game.turn);
+        match self.selection {
+            BookSelection::Best => moves.first().map(|(r#move, _)| *r#move),
+            BookSelection::Weighted => {
+                let total: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+                if total == 0 {
+                    return moves.first().map(|(r#move, _)| *r#move);
+                }
+                let mut pick = rng.below(total);
+                for (r#move, weight) in moves {
+                    if pick < weight as u64 {
+                        return Some(r#move);
+                    }
+                    pick -= weight as u64;
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Polyglot encoding of a move, castling is written as the king taking its own rook
+pub fn encode_move(board: &Board, r#move: &Move) -> u16 {
+    let piece = board.get_piece_pos(r#move.column_from, r#move.row_from);
+    let is_king = matches!(piece, Some(PieceType::WhiteKing | PieceType::BlackKing));
+    let mut column_to = r#move.column_to;
+    if is_king && r#move.column_from.abs_diff(r#move.column_to) == 2 {
+        column_to = if column_to > r#move.column_from { 7 } else { 0 };
+    }
+    let promotion = match r#move.promotion {
+        None => 0,
+        Some(Promotion::Knight) => 1,
+        Some(Promotion::Bishop) => 2,
+        Some(Promotion::Rook) => 3,
+        Some(Promotion::Queen) => 4,
+    };
+    let to = column_to + (7 - r#move.row_to) * 8;
+    let from = r#move.column_from + (7 - r#move.row_from) * 8;
+    (promotion << 12 | from << 6 | to) as u16
+}
+
+/// The move of a polyglot encoded move, None for an unknown promotion piece
+pub fn decode_move(board: &Board, r#move: u16) -> Option<Move> {
+    let r#move = r#move as u32;
+    let (column_to, row_to) = (r#move & 7, 7 - (r#move >> 3 & 7));
+    let (column_from, row_from) = (r#move >> 6 & 7, 7 - (r#move >> 9 & 7));
+    let promotion = match r#move >> 12 & 7 {
+        0 => None,
+        1 => Some(Promotion::Knight),
+        2 => Some(Promotion::Bishop),
+        3 => Some(Promotion::Rook),
+        4 => Some(Promotion::Queen),
+        _ => return None,
+    };
+    let piece = board.get_piece_pos(column_from, row_from);
+    let is_king = matches!(piece, Some(PieceType::WhiteKing | PieceType::BlackKing));
+    let column_to = match (is_king, column_from, column_to) {
+        (true, 4, 7) if row_from == row_to => 6,
+        (true, 4, 0) if row_from == row_to => 2,
+        _ => column_to,
+    };
+    Some(Move {
+        promotion,
+        ..Move::new((column_from, row_from), (column_to, row_to))
+    })
+}
+
+/// Creates a book from PGN games.
+///
+/// Every move gets 2 points for a win of the side that played it, 1 for a draw
+/// and 0 for a loss. Moves that never scored are left out of the book.
+pub struct BookBuilder {
+    /// only the first `max_depth` plies of every game are added
+    max_depth: u32,
+    /// points per position key and encoded move
+    points: HashMap<(u64, u16), u64>,
+    games: usize,
+}
+
+impl BookBuilder {
+    pub fn new(max_depth: u32) -> BookBuilder {
+        BookBuilder {
+            max_depth,
+            points: HashMap::new(),
+            games: 0,
+        }
+    }
+
+    /// Amount of games added so far
+    pub fn games(&self) -> usize {
+        self.games
+    }
+
+    /// Adds the moves of a game, false when its FEN tag isn't valid and the game is skipped
+    pub fn add_game(&mut self, pgn: &PgnGame) -> bool {
+        let Some(mut game) = pgn.start_position() else {
+            return false;
+        };
+        // (white, black) points
+        let (white, black) = match pgn.result.as_str() {
+            "1-0" => (2, 0),
+            "0-1" => (0, 2),
+            _ => (1, 1),
+        };
+        for san in pgn.moves.iter().take(self.max_depth as usize) {
+            let r#move = match parse_san(&game.board, game.turn, san) {
+                Some(r#move) => r#move,
+                None => break,
+            };
+            let key = game.board.polyglot_key(game.turn);
+            let encoded = encode_move(&game.board, &r#move);
+            *self.points.entry((key, encoded)).or_default() +=
+                if game.turn { white } else { black };
+            game.make_move(&r#move);
+        }
+        self.games += 1;
+        true
+    }
+
+    /// Adds all games of a PGN text, returns the amount of games added
+    pub fn add_pgn(&mut self, text: &str) -> usize {
+        parse_pgn(text)
+            .iter()
+            .filter(|game| self.add_game(game))
+            .count()
+    }
+
+    /// Adds all games of a PGN file, returns the amount of games added
+    pub fn add_pgn_file(&mut self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let bytes = fs::read(path)?;
+        Ok(self.add_pgn(&String::from_utf8_lossy(&bytes)))
+    }
+
+    pub fn build(&self) -> Book {
+        let max_points = self.points.values().copied().max().unwrap_or(0);
+        // the weights have to fit in 16 bits
+        let scale = (max_points as f64 / u16::MAX as f64).max(1.0);
+        let mut entries: Vec<BookEntry> = self
+            .points
+            .iter()
+            .filter(|(_, points)| **points > 0)
+            .map(|(&(key, r#move), &points)| BookEntry {
+                key,
+                r#move,
+                weight: ((points as f64 / scale) as u16).max(1),
+                learn: 0,
+            })
+            .collect();
+        entries.sort_by_key(|entry| (entry.key, std::cmp::Reverse(entry.weight), entry.r#move));
+        Book {
+            entries,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: &str = "[Result \"1/2-1/2\"]\n\n1. e4 e5 2. Nf3 1/2-1/2\n\n\
+                       [FEN \"not a position\"]\n[Result \"*\"]\n\n1. e4 *\n";
+
+    #[test]
+    fn builds_a_book_from_pgn() {
+        let mut builder = BookBuilder::new(10);
+        // the game with the bad FEN tag is skipped
+        assert_eq!(builder.add_pgn(PGN), 1);
+        let book = Book::from_bytes(&builder.build().to_bytes());
+        let mut game = Game::default();
+        let e4 = Move::from_algebraic_notation("e2e4").unwrap();
+        assert_eq!(
+            book.moves(&game.board, game.turn).first().map(|m| m.0),
+            Some(e4)
+        );
+        game.make_move(&e4);
+        let e5 = Move::from_algebraic_notation("e7e5").unwrap();
+        assert_eq!(
+            book.moves(&game.board, game.turn).first().map(|m| m.0),
+            Some(e5)
+        );
+    }
+
+    #[test]
+    fn probe_with_fullmove_zero() {
+        let mut builder = BookBuilder::new(10);
+        builder.add_pgn(PGN);
+        let mut book = builder.build();
+        book.set_max_depth(Some(4));
+        let game = Game {
+            fullmove: 0,
+            ..Game::default()
+        };
+        assert!(book.probe(&game, &mut Rng::new(1)).is_some());
+    }
+}
@@ -0,0 +1,193 @@
+// Purpose: Lets the bot play in a frame loop, like the GUI's.
+//
+// The engine searches on a background thread so the frame loop never waits for
+// it. After its move the bot ponders: it keeps searching the position after the
+// reply it expects, and when that reply is played the search simply goes on.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::core::{game::Game, Move};
+
+use super::book::Book;
+use super::search::{SearchLimits, SearchResult};
+use super::strength::Strength;
+use super::Engine;
+
+pub struct BotPlayer {
+    /// the color the bot plays, true for white
+    color: bool,
+    limits: SearchLimits,
+    pondering_enabled: bool,
+    engine: Arc<Mutex<Engine>>,
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+    search: Option<JoinHandle<SearchResult>>,
+    /// the running search is a ponder search
+    is_pondering: bool,
+    /// key of the position the ponder search expects to see on our turn
+    ponder_key: u64,
+    /// key of the position the running search is about
+    search_key: u64,
+    /// the rest of the line the last search expects after the bot's move
+    hint: Vec<Move>,
+    /// key of the position after the bot's move
+    hint_key: u64,
+    /// the score of the last search, from the bot's point of view
+    last_score: Option<i32>,
+}
+
+impl BotPlayer {
+    pub fn new(color: bool, limits: SearchLimits) -> BotPlayer {
+        let engine = Engine::default();
+        BotPlayer {
+            color,
+            limits,
+            pondering_enabled: false,
+            stop: engine.stop_handle(),
+            ponder: engine.ponder_handle(),
+            engine: Arc::new(Mutex::new(engine)),
+            search: None,
+            is_pondering: false,
+            ponder_key: 0,
+            search_key: 0,
+            hint: Vec::new(),
+            hint_key: 0,
+            last_score: None,
+        }
+    }
+
+    pub fn color(&self) -> bool {
+        self.color
+    }
+
+    pub fn pondering_enabled(&self) -> bool {
+        self.pondering_enabled
+    }
+
+    pub fn set_pondering(&mut self, enabled: bool) {
+        self.pondering_enabled = enabled;
+        if !enabled && self.is_pondering {
+            self.stop_search();
+        }
+    }
+
+    /// The limits of the next search, like the time left on its clock
+    pub fn set_limits(&mut self, limits: SearchLimits) {
+        self.limits = limits;
+    }
+
+    /// Sets the opening book the bot plays from, None to always search
+    pub fn set_book(&mut self, book: Option<Book>) {
+        self.stop_search();
+        self.engine.lock().unwrap().set_book(book);
+    }
+
+    /// Plays at the level of this rating, None for full strength
+    pub fn set_strength(&mut self, strength: Option<Strength>) {
+        self.stop_search();
+        self.engine.lock().unwrap().set_strength(strength);
+    }
+
+    /// Is the bot thinking about its own move, pondering doesn't count
+    pub fn is_thinking(&self) -> bool {
+        self.search.is_some() && !self.is_pondering
+    }
+
+    /// The reply the bot expects in this position and the move it threatens after it,
+    /// as long as the game is still in the position its last move led to
+    pub fn hint(&self, game: &Game) -> Option<(Move, Option<Move>)> {
+        if game.board.zobrist_key(game.turn) != self.hint_key {
+            return None;
+        }
+        let best_move = *self.hint.first()?;
+        Some((best_move, self.hint.get(1).copied()))
+    }
+
+    /// The bot agrees to a draw when its last search didn't think it was ahead
+    pub fn accepts_draw(&self) -> bool {
+        self.last_score.is_some_and(|score| score <= 0)
+    }
+
+    /// Call every frame, returns the move of the bot once it has found one.
+    /// `history` holds the keys of the positions played before the current one.
+    pub fn update(&mut self, game: &Game, history: &[u64]) -> Option<Move> {
+        if game.turn != self.color {
+            return None;
+        }
+        if self.is_pondering {
+            if game.board.zobrist_key(game.turn) == self.ponder_key {
+                // ponderhit, the search keeps everything it has done so far
+                self.ponder.store(false, Ordering::Relaxed);
+                self.is_pondering = false;
+            } else {
+                self.stop_search();
+            }
+        } else if self.search.is_some() && game.board.zobrist_key(game.turn) != self.search_key {
+            // the game went on from another position, like a move taken back
+            self.stop_search();
+        }
+        if self.search.is_none() {
+            if game.board.get_legal_moves(game.turn).is_empty() {
+                return None;
+            }
+            self.start_search(game, history, false);
+        }
+        if !self.search.as_ref().unwrap().is_finished() {
+            return None;
+        }
+
+        let result = self.search.take().unwrap().join().unwrap();
+        self.last_score = Some(result.score);
+        if let [best_move, line @ ..] = result.pv.as_slice() {
+            let mut hint_game = game.clone();
+            hint_game.make_move(best_move);
+            self.hint_key = hint_game.board.zobrist_key(hint_game.turn);
+            self.hint = line.to_vec();
+        }
+        if let (true, [best_move, reply, ..]) = (self.pondering_enabled, result.pv.as_slice()) {
+            let mut ponder_game = game.clone();
+            let mut ponder_history = history.to_vec();
+            for r#move in [best_move, reply] {
+                ponder_history.push(ponder_game.board.zobrist_key(ponder_game.turn));
+                ponder_game.make_move(r#move);
+            }
+            self.ponder_key = ponder_game.board.zobrist_key(ponder_game.turn);
+            self.start_search(&ponder_game, &ponder_history, true);
+        }
+        result.best_move
+    }
+
+    fn start_search(&mut self, game: &Game, history: &[u64], ponder: bool) {
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponder.store(ponder, Ordering::Relaxed);
+        self.is_pondering = ponder;
+        self.search_key = game.board.zobrist_key(game.turn);
+        let engine = self.engine.clone();
+        let game = game.clone();
+        let history = history.to_vec();
+        let limits = self.limits;
+        self.search = Some(std::thread::spawn(move || {
+            engine
+                .lock()
+                .unwrap()
+                .search(&game, &history, &limits, &mut |_| {})
+        }));
+    }
+
+    /// Stops the running search and throws its result away
+    pub fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            search.join().unwrap();
+        }
+        self.is_pondering = false;
+    }
+}
+
+impl Drop for BotPlayer {
+    fn drop(&mut self) {
+        self.stop_search();
+    }
+}
@@ -0,0 +1,10 @@
+// Purpose: Weights of the classical evaluation, in centipawns.
+// The tuner writes this file, see `tuning` and `chess_bot tune`.
+
+/// value of a pawn, knight, bishop, rook and queen
+pub const PIECE_VALUES: [i32; 5] = [100, 300, 300, 500, 900];
+pub const DOUBLED_PAWN: i32 = -12;
+pub const ISOLATED_PAWN: i32 = -15;
+pub const BACKWARD_PAWN: i32 = -10;
+/// bonus for a passed pawn, indexed by the rank as seen from the pawn's own side
+pub const PASSED_PAWN: [i32; 8] = [0, 10, 17, 30, 55, 90, 140, 0];
@@ -0,0 +1,100 @@
+// Purpose: Static evaluation of a position.
+
+use std::sync::Arc;
+
+use crate::core::{board::Board, PieceType};
+
+use super::eval_params::PIECE_VALUES;
+use super::nnue::{Network, NnueState};
+use super::pawn_hash::PawnHashTable;
+
+/// Evaluates positions, owns the caches that make evaluation cheap
+#[derive(Default)]
+pub struct Evaluator {
+    pawn_table: PawnHashTable,
+    /// evaluate with the network instead of the classical evaluation
+    nnue: Option<NnueState>,
+}
+
+impl Evaluator {
+    pub fn new() -> Evaluator {
+        Evaluator::default()
+    }
+
+    /// Evaluates with this network from now on, None for the classical evaluation
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        let unchanged = match (&self.nnue, &network) {
+            (Some(nnue), Some(network)) => Arc::ptr_eq(nnue.network(), network),
+            (None, None) => true,
+            _ => false,
+        };
+        if !unchanged {
+            self.nnue = network.map(NnueState::new);
+        }
+    }
+
+    pub fn uses_nnue(&self) -> bool {
+        self.nnue.is_some()
+    }
+
+    /// Starts a search at this position, `push` and `pop` follow the moves from here
+    pub fn reset(&mut self, board: &Board) {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.reset(board);
+        }
+    }
+
+    /// The search made a move from `parent`, the current position, to `child`
+    pub fn push(&mut self, parent: &Board, child: &Board) {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push(parent, child);
+        }
+    }
+
+    /// The search took back the last move
+    pub fn pop(&mut self) {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.pop();
+        }
+    }
+
+    /// Returns the score of the board in centipawns from the point of view of the side to move.
+    /// With a network the board has to be the current position of `reset`, `push` and `pop`.
+    pub fn evaluate(&mut self, board: &Board, turn: bool) -> i32 {
+        if let Some(nnue) = &self.nnue {
+            return nnue.evaluate(turn);
+        }
+        let score = material(board) + self.pawn_table.probe(board).score;
+        if turn {
+            score
+        } else {
+            -score
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pawn_table.clear();
+    }
+}
+
+/// The material balance in centipawns from white's point of view
+pub fn material(board: &Board) -> i32 {
+    let mut score = 0;
+    for piece in PieceType::ALL {
+        let count = board.get_pieces(piece).count_ones() as i32;
+        score += count * piece_value(piece) * (piece.color() as i32 * 2 - 1);
+    }
+    score
+}
+
+/// The value of a piece in the evaluation, 0 for kings
+pub fn piece_value(piece: PieceType) -> i32 {
+    match piece {
+        PieceType::BlackPawn | PieceType::WhitePawn => PIECE_VALUES[0],
+        PieceType::BlackKnight | PieceType::WhiteKnight => PIECE_VALUES[1],
+        PieceType::BlackBishop | PieceType::WhiteBishop => PIECE_VALUES[2],
+        PieceType::BlackRook | PieceType::WhiteRook => PIECE_VALUES[3],
+        PieceType::BlackQueen | PieceType::WhiteQueen => PIECE_VALUES[4],
+        PieceType::BlackKing | PieceType::WhiteKing => 0,
+    }
+}
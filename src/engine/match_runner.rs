@@ -0,0 +1,569 @@
+// Purpose: Plays two engine configurations against each other and tells which one is stronger.
+//
+// Every opening is played twice so both sides get to play it with white. Several games can
+// be played at the same time, every game has its own pair of engines. After every game the
+// score is turned into an Elo difference with a 95% error bar, and a sequential probability
+// ratio test (SPRT) can stop the match as soon as the result is clear.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use crate::core::{
+    game::{Game, GameResult},
+    pgn::{self, parse_pgn, PgnHeader},
+    Move,
+};
+
+use super::nnue::Network;
+use super::rng::Rng;
+use super::search::SearchLimits;
+use super::selfplay::{tablebase_result, Adjudication, Adjudicator};
+use super::strength::Strength;
+use super::time_manager::TimeControl;
+use super::{Engine, EngineOptions};
+
+/// One side of the match
+#[derive(Clone)]
+pub struct Contestant {
+    pub name: String,
+    pub options: EngineOptions,
+    /// the network used when `options.use_nnue` is set
+    pub network: Option<Arc<Network>>,
+    /// directories of the Syzygy tablebases, empty for none
+    pub syzygy_path: String,
+    /// play weaker on purpose, to calibrate the levels
+    pub strength: Option<Strength>,
+}
+
+impl Contestant {
+    pub fn new(name: &str) -> Contestant {
+        Contestant {
+            name: name.to_string(),
+            options: EngineOptions::default(),
+            network: None,
+            syzygy_path: String::new(),
+            strength: None,
+        }
+    }
+
+    fn engine(&self) -> Engine {
+        let mut engine = Engine::new(self.options.clone());
+        engine.set_network(self.network.clone());
+        engine.set_syzygy_path(&self.syzygy_path);
+        engine.set_strength(self.strength);
+        engine
+    }
+}
+
+/// A position the games start from, with the moves that lead to it from `fen`
+#[derive(Clone, Debug)]
+pub struct Opening {
+    pub fen: Option<String>,
+    pub moves: Vec<Move>,
+}
+
+impl Opening {
+    /// None when `fen` isn't a valid position
+    pub fn start_position(&self) -> Option<Game> {
+        match &self.fen {
+            Some(fen) => Game::from_fen(fen),
+            None => Some(Game::default()),
+        }
+    }
+}
+
+/// Reads an opening suite: a PGN file of which the first `plies` of every game are used,
+/// or a file with a FEN or EPD position on every line
+pub fn load_openings(path: impl AsRef<Path>, plies: usize) -> io::Result<Vec<Opening>> {
+    let is_pgn = path
+        .as_ref()
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pgn"));
+    let text = std::fs::read_to_string(path)?;
+    let openings = if is_pgn {
+        // games with a FEN tag that isn't valid are skipped
+        parse_pgn(&text)
+            .iter()
+            .filter_map(|game| {
+                let (_, mut moves) = game.replay()?;
+                moves.truncate(plies);
+                Some(Opening {
+                    fen: game.tag("FEN").map(|fen| fen.to_string()),
+                    moves,
+                })
+            })
+            .collect()
+    } else {
+        text.lines()
+            .filter_map(|line| {
+                let fen: Vec<&str> = line.split_whitespace().take(6).collect();
+                let game = Game::from_fen(&fen.join(" "))?;
+                Some(Opening {
+                    fen: Some(game.exportPosition()),
+                    moves: Vec::new(),
+                })
+            })
+            .collect()
+    };
+    Ok(openings)
+}
+
+/// A random opening of `plies` moves, for matches without an opening suite
+pub fn random_opening(rng: &mut Rng, plies: usize) -> Opening {
+    loop {
+        let mut game = Game::default();
+        let mut moves = Vec::new();
+        while moves.len() < plies {
+            let legal_moves = game.board.get_legal_moves(game.turn);
+            if legal_moves.is_empty() {
+                break;
+            }
+            let r#move = legal_moves[rng.below(legal_moves.len() as u64) as usize];
+            game.make_move(&r#move);
+            moves.push(r#move);
+        }
+        if moves.len() == plies && !game.board.get_legal_moves(game.turn).is_empty() {
+            return Opening { fen: None, moves };
+        }
+    }
+}
+
+/// Sequential probability ratio test: is the first contestant `elo1` stronger (H1)
+/// or only `elo0` (H0), with at most `alpha` false positives and `beta` false negatives
+#[derive(Clone, Copy, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtVerdict {
+    /// the first contestant is `elo1` stronger
+    AcceptH1,
+    /// the first contestant is not `elo1` stronger
+    AcceptH0,
+    Continue,
+}
+
+impl Sprt {
+    /// The bounds the log likelihood ratio has to cross, lower and upper
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// The log likelihood ratio of H1 against H0, with a normal approximation of the scores
+    pub fn llr(&self, stats: &MatchStats) -> f64 {
+        let games = stats.games() as f64;
+        let variance = stats.variance();
+        if games == 0.0 || variance <= 0.0 {
+            return 0.0;
+        }
+        let (score0, score1) = (expected_score(self.elo0), expected_score(self.elo1));
+        let score = stats.score();
+        games * ((score - score0).powi(2) - (score - score1).powi(2)) / (2.0 * variance)
+    }
+
+    pub fn verdict(&self, stats: &MatchStats) -> SprtVerdict {
+        let llr = self.llr(stats);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtVerdict::AcceptH1
+        } else if llr <= lower {
+            SprtVerdict::AcceptH0
+        } else {
+            SprtVerdict::Continue
+        }
+    }
+}
+
+/// The score a side is expected to make against an opponent `elo` weaker
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Wins, draws and losses from the point of view of the first contestant
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MatchStats {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl MatchStats {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The average points per game of the first contestant, even before the first game
+    pub fn score(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /// The variance of the points of a single game
+    fn variance(&self) -> f64 {
+        let score = self.score();
+        let games = self.games().max(1) as f64;
+        (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / games
+    }
+
+    /// The Elo difference between the contestants and its 95% error margin
+    pub fn elo(&self) -> (f64, f64) {
+        let elo = |score: f64| {
+            let score = score.clamp(1e-6, 1.0 - 1e-6);
+            -400.0 * (1.0 / score - 1.0).log10()
+        };
+        let margin = 1.96 * (self.variance() / self.games().max(1) as f64).sqrt();
+        let score = self.score();
+        (
+            elo(score),
+            (elo(score + margin) - elo(score - margin)) / 2.0,
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct MatchConfig {
+    pub first: Contestant,
+    pub second: Contestant,
+    /// the games start from these openings, every opening is played with both colours.
+    /// Openings that aren't a valid position are skipped, when none are left every pair of
+    /// games gets a random opening of `random_plies`.
+    pub openings: Vec<Opening>,
+    pub random_plies: usize,
+    /// the most games to play, rounded up to a whole pair
+    pub games: usize,
+    /// games played at the same time
+    pub concurrency: usize,
+    /// the time on the clock of each side at the start of the game and the increment
+    /// after every move, None to only use `limits`
+    pub clock: Option<(Duration, Duration)>,
+    /// limits for every search, on top of the clock
+    pub limits: SearchLimits,
+    pub adjudication: Adjudication,
+    /// stop the match once the test is decided
+    pub sprt: Option<Sprt>,
+    pub seed: u64,
+}
+
+impl MatchConfig {
+    pub fn new(first: Contestant, second: Contestant) -> MatchConfig {
+        MatchConfig {
+            first,
+            second,
+            openings: Vec::new(),
+            random_plies: 8,
+            games: 100,
+            concurrency: 1,
+            clock: Some((Duration::from_secs(10), Duration::from_millis(100))),
+            limits: SearchLimits::default(),
+            adjudication: Adjudication::default(),
+            sprt: None,
+            seed: 0,
+        }
+    }
+}
+
+/// A finished game of the match
+#[derive(Clone, Debug)]
+pub struct MatchGame {
+    /// index of the game in the match, games `2n` and `2n + 1` share their opening
+    pub index: usize,
+    /// the first contestant played white
+    pub first_is_white: bool,
+    pub opening: Opening,
+    /// the moves after the opening
+    pub moves: Vec<Move>,
+    pub result: GameResult,
+    /// why the game ended, for the Termination tag of the PGN
+    pub termination: &'static str,
+}
+
+/// Plays the match, `on_game` is called after every game in the order they finish.
+/// Returns the statistics and the SPRT verdict.
+pub fn run_match(
+    config: &MatchConfig,
+    on_game: &mut dyn FnMut(&MatchGame, &MatchStats),
+) -> (MatchStats, SprtVerdict) {
+    let pairs = config.games.div_ceil(2).max(1);
+    let mut rng = Rng::new(config.seed);
+    // openings that don't start from a valid position are skipped
+    let valid_openings: Vec<&Opening> = config
+        .openings
+        .iter()
+        .filter(|opening| opening.start_position().is_some())
+        .collect();
+    let openings: Vec<Opening> = (0..pairs)
+        .map(|pair| {
+            if valid_openings.is_empty() {
+                random_opening(&mut rng, config.random_plies)
+            } else {
+                valid_openings[pair % valid_openings.len()].clone()
+            }
+        })
+        .collect();
+
+    let next_game = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    let mut stats = MatchStats::default();
+    let mut verdict = SprtVerdict::Continue;
+
+    std::thread::scope(|scope| {
+        for _ in 0..config.concurrency.clamp(1, pairs * 2) {
+            let sender = sender.clone();
+            let (next_game, stop, openings) = (&next_game, &stop, &openings);
+            scope.spawn(move || {
+                let mut first = config.first.engine();
+                let mut second = config.second.engine();
+                while !stop.load(Ordering::Relaxed) {
+                    let index = next_game.fetch_add(1, Ordering::Relaxed);
+                    if index >= pairs * 2 {
+                        break;
+                    }
+                    let opening = &openings[index / 2];
+                    let game = play_game(config, &mut first, &mut second, opening, index);
+                    if sender.send(game).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for game in receiver {
+            match (game.result.winner(), game.first_is_white) {
+                (None, _) => stats.draws += 1,
+                (Some(white), first_is_white) if white == first_is_white => stats.wins += 1,
+                _ => stats.losses += 1,
+            }
+            on_game(&game, &stats);
+            if let Some(sprt) = &config.sprt {
+                // the first decision stands, the games that were still running don't change it
+                if verdict == SprtVerdict::Continue {
+                    verdict = sprt.verdict(&stats);
+                }
+                if verdict != SprtVerdict::Continue {
+                    // the games that are still running are finished but not started anew
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+    (stats, verdict)
+}
+
+/// Plays game `index` of the match, the first contestant has white in the even games
+fn play_game(
+    config: &MatchConfig,
+    first: &mut Engine,
+    second: &mut Engine,
+    opening: &Opening,
+    index: usize,
+) -> MatchGame {
+    let first_is_white = index.is_multiple_of(2);
+    first.new_game();
+    second.new_game();
+    let mut game = opening
+        .start_position()
+        .expect("run_match only plays valid openings");
+    let mut history = Vec::new();
+    for r#move in &opening.moves {
+        history.push(game.board.zobrist_key(game.turn));
+        game.make_move(r#move);
+    }
+
+    // clocks of white and black
+    let mut clocks = config.clock.map(|(time, _)| [time, time]);
+    let increment = config
+        .clock
+        .map_or(Duration::ZERO, |(_, increment)| increment);
+    let mut adjudicator = Adjudicator::new(config.adjudication);
+    let mut moves = Vec::new();
+    let (result, termination) = loop {
+        if let Some(result) = game.result(&history) {
+            break (result, "normal");
+        }
+        let engine = if game.turn == first_is_white {
+            &mut *first
+        } else {
+            &mut *second
+        };
+        if config.adjudication.tablebases {
+            if let Some(result) = tablebase_result(engine, &game) {
+                break (result, "adjudication");
+            }
+        }
+
+        let side = !game.turn as usize;
+        let mut limits = config.limits;
+        if let Some(clocks) = &clocks {
+            limits.time = TimeControl {
+                time: Some(clocks[side]),
+                increment,
+                ..Default::default()
+            };
+        }
+        let start = Instant::now();
+        let search = engine.search(&game, &history, &limits, &mut |_| {});
+        if let Some(clocks) = &mut clocks {
+            let used = start.elapsed();
+            if used > clocks[side] {
+                let result = if game.turn {
+                    GameResult::BlackWins
+                } else {
+                    GameResult::WhiteWins
+                };
+                break (result, "time forfeit");
+            }
+            clocks[side] = clocks[side] - used + increment;
+        }
+
+        let Some(best_move) = search.best_move else {
+            break (GameResult::Draw, "abandoned");
+        };
+        let white_score = if game.turn {
+            search.score
+        } else {
+            -search.score
+        };
+        if let Some(result) = adjudicator.update(white_score) {
+            break (result, "adjudication");
+        }
+        history.push(game.board.zobrist_key(game.turn));
+        game.make_move(&best_move);
+        moves.push(best_move);
+    };
+
+    MatchGame {
+        index,
+        first_is_white,
+        opening: opening.clone(),
+        moves,
+        result,
+        termination,
+    }
+}
+
+/// Writes a game of the match in PGN
+pub fn write_pgn(out: &mut dyn Write, config: &MatchConfig, game: &MatchGame) -> io::Result<()> {
+    let (white, black) = if game.first_is_white {
+        (&config.first.name, &config.second.name)
+    } else {
+        (&config.second.name, &config.first.name)
+    };
+    let Some(start) = game.opening.start_position() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the opening isn't a valid position",
+        ));
+    };
+    let mut extra = Vec::new();
+    if let Some((time, increment)) = config.clock {
+        extra.push((
+            "TimeControl",
+            format!("{}+{}", time.as_secs_f64(), increment.as_secs_f64()),
+        ));
+    }
+    extra.push(("Termination", game.termination.to_string()));
+    let header = PgnHeader {
+        event: &format!("{} vs {}", config.first.name, config.second.name),
+        round: (game.index + 1).to_string(),
+        white,
+        black,
+        result: game.result.to_pgn(),
+        extra,
+    };
+    let moves: Vec<Move> = game
+        .opening
+        .moves
+        .iter()
+        .chain(&game.moves)
+        .copied()
+        .collect();
+    pgn::write_pgn(out, &header, &start, &moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(wins: usize, draws: usize, losses: usize) -> MatchStats {
+        MatchStats {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!(
+            (value - expected).abs() < 1e-3,
+            "{} instead of {}",
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn elo_and_error_margin() {
+        let (elo, margin) = stats(60, 20, 20).elo();
+        assert_close(elo, 147.191);
+        assert_close(margin, 66.015);
+        // the other side of the same match
+        let (elo, margin) = stats(20, 20, 60).elo();
+        assert_close(elo, -147.191);
+        assert_close(margin, 66.015);
+        assert_close(stats(30, 40, 30).elo().0, 0.0);
+        // more games, a smaller margin
+        assert!(stats(300, 400, 300).elo().1 < stats(30, 40, 30).elo().1);
+        assert_eq!(MatchStats::default().elo(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn sprt_log_likelihood_ratio() {
+        let sprt = Sprt {
+            elo1: 10.0,
+            ..Sprt::default()
+        };
+        let (lower, upper) = sprt.bounds();
+        assert_close(lower, -2.944);
+        assert_close(upper, 2.944);
+        assert_close(sprt.llr(&stats(60, 20, 20)), 1.734);
+        assert_close(sprt.llr(&stats(30, 40, 30)), -0.069);
+        // no games or only draws say nothing
+        assert_eq!(sprt.llr(&MatchStats::default()), 0.0);
+        assert_eq!(sprt.llr(&stats(0, 10, 0)), 0.0);
+
+        assert_eq!(sprt.verdict(&stats(60, 20, 20)), SprtVerdict::Continue);
+        assert_eq!(sprt.verdict(&stats(120, 40, 40)), SprtVerdict::AcceptH1);
+        assert_eq!(
+            sprt.verdict(&stats(1500, 2000, 1500)),
+            SprtVerdict::AcceptH0
+        );
+    }
+}
@@ -0,0 +1,383 @@
+/// src/engine/mod.rs
+/// base module for the engine: everything the bot needs to pick a move
+use std::io;
+use std::path::Path;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+use crate::core::{game::Game, Move};
+
+use self::{
+    book::Book,
+    evaluation::Evaluator,
+    nnue::Network,
+    rng::Rng,
+    search::{SearchInfo, SearchLimits, SearchResult, Searcher, SharedState, TB_WIN},
+    strength::Strength,
+    syzygy::Tablebases,
+    time_manager::TimeManager,
+    transposition::{TranspositionTable, DEFAULT_HASH_SIZE},
+};
+
+pub mod analysis;
+pub mod bench;
+pub mod book;
+pub mod bot_player;
+pub mod eval_params;
+pub mod evaluation;
+pub mod match_runner;
+pub mod nnue;
+pub mod pawn_hash;
+pub mod rng;
+pub mod search;
+pub mod selfplay;
+pub mod strength;
+#[cfg(feature = "syzygy")]
+pub mod syzygy;
+#[cfg(not(feature = "syzygy"))]
+#[path = "syzygy_disabled.rs"]
+pub mod syzygy;
+pub mod time_manager;
+pub mod transposition;
+pub mod tuning;
+
+#[derive(Clone, Debug)]
+pub struct EngineOptions {
+    /// amount of search threads, 1 keeps the search deterministic
+    pub threads: usize,
+    /// size of the transposition table in MB
+    pub hash_size: usize,
+    /// amount of best lines the search reports
+    pub multi_pv: usize,
+    /// evaluate with the network instead of the classical evaluation, when one is loaded
+    pub use_nnue: bool,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        EngineOptions {
+            threads: 1,
+            hash_size: DEFAULT_HASH_SIZE,
+            multi_pv: 1,
+            use_nnue: false,
+        }
+    }
+}
+
+pub struct Engine {
+    options: EngineOptions,
+    tt: TranspositionTable,
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+    /// one evaluator per thread, so every thread has its own caches
+    evaluators: Vec<Evaluator>,
+    /// moves of the book are played without searching
+    book: Option<Book>,
+    rng: Rng,
+    tablebases: Tablebases,
+    network: Option<Arc<Network>>,
+    /// play weaker on purpose, None for full strength
+    strength: Option<Strength>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new(EngineOptions::default())
+    }
+}
+
+impl Engine {
+    pub fn new(options: EngineOptions) -> Engine {
+        let mut engine = Engine {
+            tt: TranspositionTable::new(options.hash_size),
+            stop: Arc::new(AtomicBool::new(false)),
+            ponder: Arc::new(AtomicBool::new(false)),
+            evaluators: Vec::new(),
+            book: None,
+            rng: Rng::default(),
+            tablebases: Tablebases::new(),
+            network: None,
+            strength: None,
+            options,
+        };
+        engine.set_threads(engine.options.threads);
+        engine
+    }
+
+    pub fn options(&self) -> &EngineOptions {
+        &self.options
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.options.threads = threads.max(1);
+        self.evaluators
+            .resize_with(self.options.threads, Evaluator::default);
+        self.update_evaluators();
+    }
+
+    pub fn set_hash_size(&mut self, megabytes: usize) {
+        self.options.hash_size = megabytes;
+        self.tt = TranspositionTable::new(megabytes);
+    }
+
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.options.multi_pv = lines.max(1);
+    }
+
+    pub fn book(&self) -> Option<&Book> {
+        self.book.as_ref()
+    }
+
+    pub fn book_mut(&mut self) -> Option<&mut Book> {
+        self.book.as_mut()
+    }
+
+    /// Sets the opening book, None to stop using a book
+    pub fn set_book(&mut self, book: Option<Book>) {
+        self.book = book;
+    }
+
+    /// Seeds the choices between book moves, so games can be replayed
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Uses the Syzygy tablebases in these directories, separated by ':' (';' on Windows).
+    /// Returns the amount of tables found, an empty path turns the tablebases off.
+    pub fn set_syzygy_path(&mut self, path: &str) -> usize {
+        self.tablebases = Tablebases::open(path);
+        self.tablebases.len()
+    }
+
+    pub fn tablebases(&self) -> &Tablebases {
+        &self.tablebases
+    }
+
+    /// Reads the network of a weights file, see `nnue` for the format.
+    /// It is only used with `set_use_nnue`.
+    pub fn load_network(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.set_network(Some(Arc::new(Network::open(path)?)));
+        Ok(())
+    }
+
+    pub fn network(&self) -> Option<&Arc<Network>> {
+        self.network.as_ref()
+    }
+
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.network = network;
+        self.update_evaluators();
+    }
+
+    /// Switches between the network and the classical evaluation
+    pub fn set_use_nnue(&mut self, enabled: bool) {
+        self.options.use_nnue = enabled;
+        self.update_evaluators();
+    }
+
+    /// Does the search evaluate with the network
+    pub fn uses_nnue(&self) -> bool {
+        self.evaluators[0].uses_nnue()
+    }
+
+    fn update_evaluators(&mut self) {
+        let network = self.network.clone().filter(|_| self.options.use_nnue);
+        for evaluator in &mut self.evaluators {
+            evaluator.set_network(network.clone());
+        }
+    }
+
+    pub fn strength(&self) -> Option<Strength> {
+        self.strength
+    }
+
+    /// Plays at the level of this rating, None for full strength. See `strength`.
+    pub fn set_strength(&mut self, strength: Option<Strength>) {
+        self.strength = strength;
+    }
+
+    /// Forgets everything learned in the previous game
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        for evaluator in &mut self.evaluators {
+            evaluator.clear();
+        }
+    }
+
+    /// A flag that stops the running search when it is set, usable from other threads.
+    /// The flag is cleared when the search returns.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// A flag that makes the search ponder when it is set before the search starts.
+    /// While pondering the search ignores its time limits, clearing the flag is a
+    /// ponderhit: the search goes on as a normal search. The flag is cleared when
+    /// the search returns.
+    pub fn ponder_handle(&self) -> Arc<AtomicBool> {
+        self.ponder.clone()
+    }
+
+    /// Plays a book move when there is one and a winning tablebase move when the
+    /// position is in the tablebases, otherwise searches the position of the game
+    /// with all threads and returns the best move.
+    /// `history` holds the keys of the positions played before, to detect repetitions.
+    /// `report` is called by the main thread after every finished iteration.
+    pub fn search(
+        &mut self,
+        game: &Game,
+        history: &[u64],
+        limits: &SearchLimits,
+        report: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchResult {
+        let book_move = match &self.book {
+            Some(book) => book.probe(game, &mut self.rng),
+            None => None,
+        };
+        if let Some(book_move) = book_move {
+            return self.instant_result(book_move, 0);
+        }
+
+        if let Some(strength) = self.strength {
+            return self.search_weakened(strength, game, history, limits, report);
+        }
+
+        // with the tablebases only the moves that keep the best result are searched,
+        // a win is played right away with the move that brings the next zeroing move closest
+        let repeated = history.contains(&game.board.zobrist_key(game.turn));
+        let ranked =
+            self.tablebases
+                .rank_root_moves(&game.board, game.turn, game.halfmove_clock, repeated);
+        let mut root_moves = Vec::new();
+        if let Some(ranked) = ranked {
+            let best_rank = ranked.iter().map(|m| m.rank).max();
+            if let Some(best) = ranked
+                .iter()
+                .filter(|m| m.rank > 0 && Some(m.rank) == best_rank)
+                .min_by_key(|m| m.dtz)
+            {
+                return self.instant_result(best.r#move, TB_WIN - best.dtz);
+            }
+            root_moves = ranked
+                .iter()
+                .filter(|m| Some(m.rank) == best_rank)
+                .map(|m| m.r#move)
+                .collect();
+        }
+        self.search_position(game, history, limits, &root_moves, report)
+    }
+
+    /// Searches a few lines with the limits of the level and picks one of them,
+    /// the tablebases are left out so the bot doesn't play perfect endgames
+    fn search_weakened(
+        &mut self,
+        strength: Strength,
+        game: &Game,
+        history: &[u64],
+        limits: &SearchLimits,
+        report: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchResult {
+        let multi_pv = self.options.multi_pv;
+        self.set_multi_pv(strength.multi_pv().max(multi_pv));
+        let mut result = self.search_position(game, history, &strength.limits(limits), &[], report);
+        self.options.multi_pv = multi_pv;
+        if let Some(i) = strength.pick(&result.lines, &mut self.rng) {
+            let (score, pv) = result.lines[i].clone();
+            result.best_move = pv.first().copied().or(result.best_move);
+            result.score = score;
+            result.pv = pv;
+        }
+        result
+    }
+
+    /// The result of a move found without searching
+    fn instant_result(&mut self, r#move: Move, score: i32) -> SearchResult {
+        // a ponder search may not return before the ponderhit or stop
+        while self.ponder.load(Ordering::Relaxed) && !self.stop.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponder.store(false, Ordering::Relaxed);
+        SearchResult {
+            best_move: Some(r#move),
+            score,
+            pv: vec![r#move],
+            lines: vec![(score, vec![r#move])],
+            ..Default::default()
+        }
+    }
+
+    /// Searches with all threads, `root_moves` limits the moves of the root when not empty
+    fn search_position(
+        &mut self,
+        game: &Game,
+        history: &[u64],
+        limits: &SearchLimits,
+        root_moves: &[Move],
+        report: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchResult {
+        let legal_moves = game.board.get_legal_moves(game.turn);
+        let time = TimeManager::new(&limits.time, legal_moves.len());
+        let shared = SharedState {
+            tt: &self.tt,
+            stop: &self.stop,
+            ponder: &self.ponder,
+            tablebases: &self.tablebases,
+            nodes: AtomicU64::new(0),
+        };
+        let multi_pv = self.options.multi_pv;
+        let (main_evaluator, helper_evaluators) = self.evaluators.split_first_mut().unwrap();
+
+        let mut result = std::thread::scope(|scope| {
+            for (i, evaluator) in helper_evaluators.iter_mut().enumerate() {
+                let shared = &shared;
+                scope.spawn(move || {
+                    let mut helper =
+                        Searcher::new(shared, evaluator, *limits, i + 1, history, None);
+                    helper.set_root_moves(root_moves);
+                    helper.iterative_deepening(&game.board, game.turn, &mut |_| {});
+                });
+            }
+            let mut main = Searcher::new(&shared, main_evaluator, *limits, 0, history, Some(time));
+            main.set_multi_pv(multi_pv);
+            main.set_root_moves(root_moves);
+            let result = main.iterative_deepening(&game.board, game.turn, report);
+            // a ponder search may not return before the ponderhit or stop
+            while shared.ponder.load(Ordering::Relaxed) && !shared.stop.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            // the helpers search until the main thread is done
+            shared.stop.store(true, Ordering::Relaxed);
+            result
+        });
+
+        result.nodes = shared.nodes.load(Ordering::Relaxed);
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponder.store(false, Ordering::Relaxed);
+        if result.best_move.is_none() {
+            // stopped before the first iteration finished
+            result.best_move = legal_moves.first().copied();
+        }
+        result
+    }
+
+    /// Searches the best `lines` moves of the position, for analysis.
+    /// Returns the score from the point of view of the side to move and the
+    /// principal variation of every line, best line first.
+    pub fn analyse(
+        &mut self,
+        game: &Game,
+        history: &[u64],
+        limits: &SearchLimits,
+        lines: usize,
+    ) -> Vec<(i32, Vec<Move>)> {
+        let multi_pv = self.options.multi_pv;
+        self.set_multi_pv(lines);
+        let result = self.search_position(game, history, limits, &[], &mut |_| {});
+        self.options.multi_pv = multi_pv;
+        result.lines
+    }
+}
@@ -0,0 +1,486 @@
+// Purpose: NNUE, a neural network evaluation that is cheap to update after every move.
+//
+// The input of the network are HalfKP features: for both sides every non-king piece
+// is a feature together with the square of that side's own king. A move only turns a
+// few features on or off, so the first layer (the accumulator) is updated with a few
+// additions instead of being computed again, except when the king itself moves.
+//
+// Weights file, every number is little endian:
+//   magic           4 bytes  "DNUE"
+//   version         u32      1
+//   hidden          u32      size of one accumulator, a multiple of 16 up to 4096
+//   feature biases  i16 * hidden
+//   feature weights i16 * 40960 * hidden, the weights of feature 0 first
+//   output weights  i16 * 2 * hidden, the side to move first, then the other side
+//   output bias     i32
+// The feature of a piece seen from a side is king * 640 + (kind * 2 + other side) * 64 + square,
+// with kind 0 to 4 for pawn, knight, bishop, rook and queen, and squares counting from
+// a1 = 0 to h8 = 63 for white and mirrored to a8 = 0 to h1 = 63 for black.
+// The accumulators are clipped to 0..=255 and the output is
+// (output weights . accumulators + output bias) * 400 / (255 * 64) centipawns.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::core::{board::Board, PieceType};
+
+use super::search::{MAX_PLY, TB_WIN};
+
+const MAGIC: &[u8; 4] = b"DNUE";
+const VERSION: u32 = 1;
+pub const FEATURES: usize = 64 * 640;
+/// the largest accumulator we accept, bigger networks would be too slow anyway
+const MAX_HIDDEN: usize = 4096;
+/// accumulators are clipped to 0..=QA
+const QA: i32 = 255;
+/// the output weights are scaled by QB
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+/// scores of the network stay below the tablebase and mate scores
+const MAX_SCORE: i32 = TB_WIN - MAX_PLY as i32 - 1;
+
+pub struct Network {
+    hidden: usize,
+    feature_biases: Vec<i16>,
+    feature_weights: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+    /// use AVX2 instructions, checked once when the network is loaded
+    avx2: bool,
+}
+
+impl Network {
+    /// Reads a weights file, see the top of this file for the format
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Network> {
+        Network::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Network> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        if bytes.len() < 12 || &bytes[0..4] != MAGIC {
+            return Err(invalid("not an NNUE weights file"));
+        }
+        if read_u32(bytes, 4) != VERSION {
+            return Err(invalid("unsupported version of the weights file"));
+        }
+        let hidden = read_u32(bytes, 8) as usize;
+        if hidden == 0 || !hidden.is_multiple_of(16) {
+            return Err(invalid("the hidden size has to be a multiple of 16"));
+        }
+        if hidden > MAX_HIDDEN {
+            return Err(invalid("the hidden size can be at most 4096"));
+        }
+        let weights = hidden + FEATURES * hidden + 2 * hidden;
+        if bytes.len() != 12 + weights * 2 + 4 {
+            return Err(invalid(
+                "the size of the weights file doesn't match its hidden size",
+            ));
+        }
+
+        let mut values = bytes[12..12 + weights * 2]
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]));
+        let mut take = |n: usize| values.by_ref().take(n).collect::<Vec<i16>>();
+        Ok(Network {
+            hidden,
+            feature_biases: take(hidden),
+            feature_weights: take(FEATURES * hidden),
+            output_weights: take(2 * hidden),
+            output_bias: read_u32(bytes, 12 + weights * 2) as i32,
+            avx2: has_avx2(),
+        })
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    /// Computes the accumulator of one side from scratch
+    fn refresh(&self, board: &Board, side: bool, accumulator: &mut [i16]) {
+        accumulator.copy_from_slice(&self.feature_biases);
+        let king = king_square(board, side);
+        for piece in PieceType::ALL {
+            let mut pieces = board.get_pieces(piece);
+            while pieces != 0 {
+                let bit = pieces.trailing_zeros();
+                pieces &= pieces - 1;
+                if let Some(feature) = feature(side, king, piece, bit) {
+                    add(accumulator, self.weights(feature), self.avx2);
+                }
+            }
+        }
+    }
+
+    /// The score in centipawns from the point of view of the side to move
+    fn output(&self, accumulator: &Accumulator, turn: bool) -> i32 {
+        let (us, them) = (
+            &accumulator.sides[turn as usize],
+            &accumulator.sides[!turn as usize],
+        );
+        let (us_weights, them_weights) = self.output_weights.split_at(self.hidden);
+        let sum = dot(us, us_weights, self.avx2).wrapping_add(dot(them, them_weights, self.avx2));
+        let score = (sum as i64 + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64;
+        score.clamp(-MAX_SCORE as i64, MAX_SCORE as i64) as i32
+    }
+}
+
+/// The first layer of the network for a position, seen from black (0) and white (1)
+#[derive(Clone)]
+struct Accumulator {
+    sides: [Vec<i16>; 2],
+}
+
+/// The accumulators along the line being searched, one per ply
+pub struct NnueState {
+    network: Arc<Network>,
+    stack: Vec<Accumulator>,
+    /// the accumulator of the current position is `stack[len - 1]`
+    len: usize,
+}
+
+impl NnueState {
+    pub fn new(network: Arc<Network>) -> NnueState {
+        let accumulator = Accumulator {
+            sides: [vec![0; network.hidden], vec![0; network.hidden]],
+        };
+        NnueState {
+            stack: vec![accumulator; MAX_PLY + 1],
+            len: 0,
+            network,
+        }
+    }
+
+    pub fn network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    /// Starts the line at this position
+    pub fn reset(&mut self, board: &Board) {
+        self.len = 1;
+        for side in [false, true] {
+            self.network
+                .refresh(board, side, &mut self.stack[0].sides[side as usize]);
+        }
+    }
+
+    /// Updates the accumulators for the move from `parent`, the current position, to `child`
+    pub fn push(&mut self, parent: &Board, child: &Board) {
+        if self.len == self.stack.len() {
+            let accumulator = self.stack[0].clone();
+            self.stack.push(accumulator);
+        }
+        let (done, rest) = self.stack.split_at_mut(self.len);
+        let (before, after) = (&done[self.len - 1], &mut rest[0]);
+        self.len += 1;
+
+        for side in [false, true] {
+            let accumulator = &mut after.sides[side as usize];
+            let king = king_square(child, side);
+            if king != king_square(parent, side) {
+                self.network.refresh(child, side, accumulator);
+                continue;
+            }
+            accumulator.copy_from_slice(&before.sides[side as usize]);
+            // comparing the boards also covers castling, en passant and promotions
+            for piece in PieceType::ALL {
+                let (old, new) = (parent.get_pieces(piece), child.get_pieces(piece));
+                let (mut removed, mut added) = (old & !new, new & !old);
+                while removed != 0 {
+                    let bit = removed.trailing_zeros();
+                    removed &= removed - 1;
+                    if let Some(feature) = feature(side, king, piece, bit) {
+                        sub(
+                            accumulator,
+                            self.network.weights(feature),
+                            self.network.avx2,
+                        );
+                    }
+                }
+                while added != 0 {
+                    let bit = added.trailing_zeros();
+                    added &= added - 1;
+                    if let Some(feature) = feature(side, king, piece, bit) {
+                        add(
+                            accumulator,
+                            self.network.weights(feature),
+                            self.network.avx2,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Goes back to the position before the last `push`
+    pub fn pop(&mut self) {
+        self.len -= 1;
+    }
+
+    /// The score of the current position from the point of view of the side to move
+    pub fn evaluate(&self, turn: bool) -> i32 {
+        self.network.output(&self.stack[self.len - 1], turn)
+    }
+}
+
+/// The square of the king of `side` as that side sees the board
+fn king_square(board: &Board, side: bool) -> u32 {
+    let king = if side {
+        PieceType::WhiteKing
+    } else {
+        PieceType::BlackKing
+    };
+    orient(side, board.get_pieces(king).trailing_zeros() & 63)
+}
+
+/// The square of a bit as `side` sees the board, a1 = 0 for white and a8 = 0 for black
+fn orient(side: bool, bit: u32) -> u32 {
+    if side {
+        bit ^ 56
+    } else {
+        bit
+    }
+}
+
+/// The feature of a piece seen from `side`, None for kings
+fn feature(side: bool, king: u32, piece: PieceType, bit: u32) -> Option<usize> {
+    let kind = match piece {
+        PieceType::WhitePawn | PieceType::BlackPawn => 0,
+        PieceType::WhiteKnight | PieceType::BlackKnight => 1,
+        PieceType::WhiteBishop | PieceType::BlackBishop => 2,
+        PieceType::WhiteRook | PieceType::BlackRook => 3,
+        PieceType::WhiteQueen | PieceType::BlackQueen => 4,
+        PieceType::WhiteKing | PieceType::BlackKing => return None,
+    };
+    let index = kind * 2 + (piece.color() != side) as usize;
+    Some(king as usize * 640 + index * 64 + orient(side, bit) as usize)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn has_avx2() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+fn add(accumulator: &mut [i16], weights: &[i16], avx2: bool) {
+    #[cfg(target_arch = "x86_64")]
+    if avx2 {
+        // SAFETY: the CPU supports AVX2 and both slices have the hidden size, a multiple of 16
+        return unsafe { simd::add(accumulator, weights) };
+    }
+    for (value, weight) in accumulator.iter_mut().zip(weights) {
+        *value = value.wrapping_add(*weight);
+    }
+}
+
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+fn sub(accumulator: &mut [i16], weights: &[i16], avx2: bool) {
+    #[cfg(target_arch = "x86_64")]
+    if avx2 {
+        // SAFETY: see `add`
+        return unsafe { simd::sub(accumulator, weights) };
+    }
+    for (value, weight) in accumulator.iter_mut().zip(weights) {
+        *value = value.wrapping_sub(*weight);
+    }
+}
+
+/// The clipped accumulator times the weights, overflow wraps around the same way in both versions
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+fn dot(accumulator: &[i16], weights: &[i16], avx2: bool) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    if avx2 {
+        // SAFETY: see `add`
+        return unsafe { simd::dot(accumulator, weights) };
+    }
+    accumulator
+        .iter()
+        .zip(weights)
+        .map(|(value, weight)| (*value as i32).clamp(0, QA) * *weight as i32)
+        .fold(0, i32::wrapping_add)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::arch::x86_64::*;
+
+    use super::QA;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn add(accumulator: &mut [i16], weights: &[i16]) {
+        for i in (0..accumulator.len()).step_by(16) {
+            let a = _mm256_loadu_si256(accumulator.as_ptr().add(i) as *const __m256i);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            _mm256_storeu_si256(
+                accumulator.as_mut_ptr().add(i) as *mut __m256i,
+                _mm256_add_epi16(a, w),
+            );
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sub(accumulator: &mut [i16], weights: &[i16]) {
+        for i in (0..accumulator.len()).step_by(16) {
+            let a = _mm256_loadu_si256(accumulator.as_ptr().add(i) as *const __m256i);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            _mm256_storeu_si256(
+                accumulator.as_mut_ptr().add(i) as *mut __m256i,
+                _mm256_sub_epi16(a, w),
+            );
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn dot(accumulator: &[i16], weights: &[i16]) -> i32 {
+        let zero = _mm256_setzero_si256();
+        let max = _mm256_set1_epi16(QA as i16);
+        let mut sum = _mm256_setzero_si256();
+        for i in (0..accumulator.len()).step_by(16) {
+            let a = _mm256_loadu_si256(accumulator.as_ptr().add(i) as *const __m256i);
+            let a = _mm256_min_epi16(_mm256_max_epi16(a, zero), max);
+            let w = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(a, w));
+        }
+        let sum = _mm_add_epi32(
+            _mm256_castsi256_si128(sum),
+            _mm256_extracti128_si256(sum, 1),
+        );
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+        _mm_cvtsi128_si32(sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::game::Game;
+    use crate::engine::rng::Rng;
+
+    const HIDDEN: usize = 32;
+
+    fn random_i16(rng: &mut Rng, range: i16) -> i16 {
+        (rng.below(2 * range as u64 + 1) as i64 - range as i64) as i16
+    }
+
+    fn random_network(avx2: bool) -> Network {
+        let mut rng = Rng::new(7);
+        let mut values = |n: usize| (0..n).map(|_| random_i16(&mut rng, 64)).collect();
+        Network {
+            hidden: HIDDEN,
+            feature_biases: values(HIDDEN),
+            feature_weights: values(FEATURES * HIDDEN),
+            output_weights: values(2 * HIDDEN),
+            output_bias: 1234,
+            avx2,
+        }
+    }
+
+    fn header(hidden: u32) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(hidden.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn hidden_size_errors() {
+        let message = |hidden| {
+            Network::from_bytes(&header(hidden))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(message(0), "the hidden size has to be a multiple of 16");
+        assert_eq!(message(24), "the hidden size has to be a multiple of 16");
+        assert_eq!(message(4112), "the hidden size can be at most 4096");
+        assert_eq!(
+            message(16),
+            "the size of the weights file doesn't match its hidden size"
+        );
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        if !has_avx2() {
+            return;
+        }
+        let mut rng = Rng::new(1);
+        for len in [16, 32, 256] {
+            let accumulator: Vec<i16> = (0..len).map(|_| random_i16(&mut rng, i16::MAX)).collect();
+            let weights: Vec<i16> = (0..len).map(|_| random_i16(&mut rng, i16::MAX)).collect();
+            for kernel in [add, sub] {
+                let mut scalar = accumulator.clone();
+                let mut simd = accumulator.clone();
+                kernel(&mut scalar, &weights, false);
+                kernel(&mut simd, &weights, true);
+                assert_eq!(scalar, simd);
+            }
+            assert_eq!(
+                dot(&accumulator, &weights, false),
+                dot(&accumulator, &weights, true)
+            );
+        }
+    }
+
+    /// Random moves forward and back, the accumulators always match a full refresh
+    fn check_incremental(avx2: bool) {
+        let network = Arc::new(random_network(avx2));
+        let mut state = NnueState::new(network.clone());
+        let mut rng = Rng::new(3);
+        let mut games = vec![Game::new(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )];
+        state.reset(&games[0].board);
+        for _ in 0..400 {
+            let game = games.last().unwrap();
+            let legal_moves = game.board.get_legal_moves(game.turn);
+            if games.len() > 1 && (legal_moves.is_empty() || rng.below(3) == 0) {
+                games.pop();
+                state.pop();
+            } else if legal_moves.is_empty() {
+                break;
+            } else {
+                let mut child = game.clone();
+                child.make_move(&legal_moves[rng.below(legal_moves.len() as u64) as usize]);
+                state.push(&game.board, &child.board);
+                games.push(child);
+            }
+
+            let game = games.last().unwrap();
+            let mut fresh = NnueState::new(network.clone());
+            fresh.reset(&game.board);
+            for side in [false, true] {
+                assert_eq!(
+                    state.stack[state.len - 1].sides[side as usize],
+                    fresh.stack[0].sides[side as usize]
+                );
+            }
+            assert_eq!(state.evaluate(game.turn), fresh.evaluate(game.turn));
+        }
+    }
+
+    #[test]
+    fn incremental_matches_refresh() {
+        check_incremental(false);
+        if has_avx2() {
+            check_incremental(true);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+// Purpose: Pawn structure evaluation, cached in a hash table keyed on the pawn-only Zobrist key.
+
+use crate::core::{board::Board, PieceType};
+
+use super::eval_params::{BACKWARD_PAWN, DOUBLED_PAWN, ISOLATED_PAWN, PASSED_PAWN};
+
+const FILE_A: u64 = 0x0101010101010101;
+const FILE_H: u64 = FILE_A << 7;
+
+/// Default amount of entries, must be a power of two
+pub const DEFAULT_ENTRIES: usize = 1 << 14;
+
+/// Cached pawn structure of one pawn configuration.
+/// The masks hold the pawns of both colors, use the board to tell them apart.
+#[derive(Clone, Copy, Default)]
+pub struct PawnEntry {
+    pub key: u64,
+    pub passed: u64,
+    pub isolated: u64,
+    pub doubled: u64,
+    pub backward: u64,
+    /// the pawn structure score in centipawns from white's point of view
+    pub score: i32,
+}
+
+pub struct PawnHashTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl Default for PawnHashTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_ENTRIES)
+    }
+}
+
+impl PawnHashTable {
+    /// Creates a table with `entries` entries, rounded up to a power of two
+    pub fn new(entries: usize) -> PawnHashTable {
+        PawnHashTable {
+            entries: vec![PawnEntry::default(); entries.max(1).next_power_of_two()],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(PawnEntry::default());
+    }
+
+    /// Returns the pawn structure of the board, computing and storing it on a miss
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let key = board.pawn_key();
+        let index = key as usize & (self.entries.len() - 1);
+        // an empty entry has key 0, which is also the key of a board without pawns
+        // and the masks and score of an empty entry are correct for that board
+        if self.entries[index].key != key {
+            self.entries[index] = evaluate_pawns(board, key);
+        }
+        self.entries[index]
+    }
+}
+
+/// Computes the pawn structure masks and score from scratch
+pub fn evaluate_pawns(board: &Board, key: u64) -> PawnEntry {
+    let white_pawns = board.get_pieces(PieceType::WhitePawn);
+    let black_pawns = board.get_pieces(PieceType::BlackPawn);
+    let white_attacks = pawn_attacks(white_pawns, true);
+    let black_attacks = pawn_attacks(black_pawns, false);
+
+    let mut entry = PawnEntry {
+        key,
+        ..Default::default()
+    };
+    for (pawns, enemy_pawns, enemy_attacks, color) in [
+        (white_pawns, black_pawns, black_attacks, true),
+        (black_pawns, white_pawns, white_attacks, false),
+    ] {
+        let sign = if color { 1 } else { -1 };
+        let mut remaining = pawns;
+        while remaining != 0 {
+            let square = remaining.trailing_zeros();
+            remaining &= remaining - 1;
+            let pos = 1u64 << square;
+            let (column, row) = (square % 8, square / 8);
+            let file = FILE_A << column;
+            let neighbours = adjacent_files(column);
+            let ahead = rows_ahead(row, color);
+
+            if pawns & file & ahead != 0 {
+                entry.doubled |= pos;
+                entry.score += sign * DOUBLED_PAWN;
+            }
+            if enemy_pawns & (file | neighbours) & ahead == 0 {
+                let relative_rank = if color { 7 - row } else { row };
+                entry.passed |= pos;
+                entry.score += sign * PASSED_PAWN[relative_rank as usize];
+            }
+            if pawns & neighbours == 0 {
+                entry.isolated |= pos;
+                entry.score += sign * ISOLATED_PAWN;
+            } else if pawns & neighbours & !ahead == 0 {
+                // every neighbour has already advanced past this pawn, so it can only
+                // catch up by stepping onto a square the enemy pawns control
+                let stop = if color { pos >> 8 } else { pos << 8 };
+                if stop & enemy_attacks != 0 {
+                    entry.backward |= pos;
+                    entry.score += sign * BACKWARD_PAWN;
+                }
+            }
+        }
+    }
+    entry
+}
+
+/// The squares attacked by the pawns, white pawns move towards row 0
+pub fn pawn_attacks(pawns: u64, color: bool) -> u64 {
+    if color {
+        ((pawns & !FILE_A) >> 9) | ((pawns & !FILE_H) >> 7)
+    } else {
+        ((pawns & !FILE_A) << 7) | ((pawns & !FILE_H) << 9)
+    }
+}
+
+fn adjacent_files(column: u32) -> u64 {
+    let mut mask = 0;
+    if column > 0 {
+        mask |= FILE_A << (column - 1);
+    }
+    if column < 7 {
+        mask |= FILE_A << (column + 1);
+    }
+    mask
+}
+
+/// All rows in front of `row` from the point of view of `color`
+fn rows_ahead(row: u32, color: bool) -> u64 {
+    if color {
+        (1u64 << (row * 8)) - 1
+    } else if row == 7 {
+        0
+    } else {
+        !((1u64 << ((row + 1) * 8)) - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{game::Game, Move};
+
+    fn board(fen: &str) -> Board {
+        Game::from_fen(fen).unwrap().board
+    }
+
+    /// The bit of a square like "a3", row 0 is the 8th rank
+    fn bit(square: &str) -> u64 {
+        let square = square.as_bytes();
+        1 << ((square[0] - b'a') as u32 + (b'8' - square[1]) as u32 * 8)
+    }
+
+    #[test]
+    fn pawn_key_only_sees_pawns() {
+        let mut game = Game::default();
+        let key = game.board.pawn_key();
+        game.make_move(&Move::from_algebraic_notation("g1f3").unwrap());
+        assert_eq!(game.board.pawn_key(), key);
+        game.make_move(&Move::from_algebraic_notation("e7e5").unwrap());
+        assert_ne!(game.board.pawn_key(), key);
+        assert_eq!(board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").pawn_key(), 0);
+    }
+
+    #[test]
+    fn doubled_isolated_and_passed() {
+        let white = board("4k3/8/8/8/8/P7/P4P2/4K3 w - - 0 1");
+        let entry = evaluate_pawns(&white, white.pawn_key());
+        // only the pawn behind the other one counts as doubled
+        assert_eq!(entry.doubled, bit("a2"));
+        assert_eq!(entry.isolated, bit("a2") | bit("a3") | bit("f2"));
+        assert_eq!(entry.passed, bit("a2") | bit("a3") | bit("f2"));
+        assert_eq!(entry.backward, 0);
+        // the same pawns for black score the same for black
+        let black = board("4k3/p4p2/p7/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(evaluate_pawns(&black, black.pawn_key()).score, -entry.score);
+    }
+
+    #[test]
+    fn backward() {
+        // c3 can't move up to d4 without b5 taking it
+        let board = board("4k3/8/8/1p6/3P4/2P5/8/4K3 w - - 0 1");
+        let entry = evaluate_pawns(&board, board.pawn_key());
+        assert_eq!(entry.backward, bit("c3"));
+    }
+
+    #[test]
+    fn probe_matches_evaluate() {
+        // a single entry, so every probe of another board replaces it
+        let mut table = PawnHashTable::new(1);
+        for fen in [
+            "4k3/8/8/8/8/P7/P4P2/4K3 w - - 0 1",
+            "4k3/8/8/1p6/3P4/2P5/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/P7/P4P2/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        ] {
+            let board = board(fen);
+            let entry = table.probe(&board);
+            let expected = evaluate_pawns(&board, board.pawn_key());
+            assert_eq!(entry.key, expected.key);
+            assert_eq!(
+                (entry.passed, entry.isolated, entry.doubled, entry.backward),
+                (
+                    expected.passed,
+                    expected.isolated,
+                    expected.doubled,
+                    expected.backward
+                )
+            );
+            assert_eq!(entry.score, expected.score);
+        }
+    }
+}
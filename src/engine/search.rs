@@ -0,0 +1,583 @@
+// Purpose: Alpha-beta search, run by every thread of the Lazy SMP search.
+//
+// All threads search the same position and only share the transposition table.
+// The helpers start at different depths so they fill the table with results the
+// main thread can use, the main thread alone watches the clock and reports.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::core::{board::Board, Move, PieceType, Promotion};
+
+use super::evaluation::Evaluator;
+use super::syzygy::{Tablebases, Wdl};
+use super::time_manager::{TimeControl, TimeManager};
+use super::transposition::{Bound, TranspositionTable, TtEntry};
+
+pub const INFINITY: i32 = 32_000;
+pub const MATE: i32 = 31_000;
+pub const MAX_PLY: usize = 100;
+/// Scores beyond this are mate scores
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+/// Score of a position the tablebases say is won, minus the ply
+pub const TB_WIN: i32 = MATE_BOUND - MAX_PLY as i32;
+/// Nodes a thread searches between looking at the clock and the node limit
+const CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub time: TimeControl,
+}
+
+/// Progress of the search, reported after every finished iteration
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: u32,
+    /// which of the MultiPV lines this is, starting at 1
+    pub multi_pv: usize,
+    /// score in centipawns from the point of view of the side to move
+    pub score: i32,
+    /// nodes searched by all threads together
+    pub nodes: u64,
+    pub time: Duration,
+    pub hashfull: u32,
+    pub pv: Vec<Move>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    /// the best lines with their scores, best first, see `Searcher::set_multi_pv`
+    pub lines: Vec<(i32, Vec<Move>)>,
+}
+
+/// State shared by all threads of one search
+pub struct SharedState<'a> {
+    pub tt: &'a TranspositionTable,
+    pub stop: &'a AtomicBool,
+    /// set while the search ponders, time limits only start counting once it is cleared
+    pub ponder: &'a AtomicBool,
+    pub tablebases: &'a Tablebases,
+    /// nodes of all threads, every thread adds its nodes in batches
+    pub nodes: AtomicU64,
+}
+
+pub struct Searcher<'a> {
+    shared: &'a SharedState<'a>,
+    evaluator: &'a mut Evaluator,
+    limits: SearchLimits,
+    /// 0 is the main thread
+    thread: usize,
+    /// only the main thread has a clock
+    time: Option<TimeManager>,
+    /// this thread still thinks we are pondering
+    pondering: bool,
+    nodes: u64,
+    /// nodes between looking at the limits, fewer than CHECK_INTERVAL for a small node limit
+    check_interval: u64,
+    killers: Vec<[Option<Move>; 2]>,
+    /// amount of lines to search in the root
+    multi_pv: usize,
+    /// moves of the lines already found in this iteration, skipped in the root
+    excluded_root_moves: Vec<Move>,
+    /// when not empty the root only searches these moves
+    root_moves: Vec<Move>,
+    /// keys of the positions before the root and along the line being searched
+    history: Vec<u64>,
+    pv: Vec<Vec<Move>>,
+}
+
+impl<'a> Searcher<'a> {
+    pub fn new(
+        shared: &'a SharedState<'a>,
+        evaluator: &'a mut Evaluator,
+        limits: SearchLimits,
+        thread: usize,
+        history: &[u64],
+        time: Option<TimeManager>,
+    ) -> Searcher<'a> {
+        Searcher {
+            shared,
+            evaluator,
+            limits,
+            thread,
+            time,
+            pondering: shared.ponder.load(Ordering::Relaxed),
+            nodes: 0,
+            check_interval: limits
+                .nodes
+                .map_or(CHECK_INTERVAL, |limit| limit.clamp(1, CHECK_INTERVAL)),
+            killers: vec![[None; 2]; MAX_PLY],
+            multi_pv: 1,
+            excluded_root_moves: Vec::new(),
+            root_moves: Vec::new(),
+            history: history.to_vec(),
+            pv: vec![Vec::new(); MAX_PLY + 1],
+        }
+    }
+
+    /// Search the best `lines` moves of the root instead of only the best one
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.multi_pv = lines.max(1);
+    }
+
+    /// Only search these moves in the root, like the moves the tablebases say are best
+    pub fn set_root_moves(&mut self, moves: &[Move]) {
+        self.root_moves = moves.to_vec();
+    }
+
+    /// Searches one depth deeper every iteration until a limit is hit or the search is stopped
+    pub fn iterative_deepening(
+        &mut self,
+        board: &Board,
+        turn: bool,
+        report: &mut dyn FnMut(&SearchInfo),
+    ) -> SearchResult {
+        let max_depth = self
+            .limits
+            .depth
+            .unwrap_or(MAX_PLY as u32)
+            .clamp(1, MAX_PLY as u32 - 1);
+        // odd helpers start a ply deeper so the threads don't all search the same tree
+        let mut depth = 1 + (self.thread % 2) as u32;
+        let mut result = SearchResult::default();
+        self.evaluator.reset(board);
+
+        'iterations: while depth <= max_depth {
+            // search the root once per line, every time without the moves of the lines before
+            self.excluded_root_moves.clear();
+            let mut lines: Vec<(i32, Vec<Move>)> = Vec::new();
+            while lines.len() < self.multi_pv {
+                let score = self.negamax(board, turn, depth, -INFINITY, INFINITY, 0);
+                if self.stopped() {
+                    break 'iterations;
+                }
+                match self.pv[0].first() {
+                    Some(r#move) => self.excluded_root_moves.push(*r#move),
+                    None if lines.is_empty() => {
+                        // no legal moves, the score is mate or stalemate
+                        lines.push((score, Vec::new()));
+                        break;
+                    }
+                    None => break,
+                }
+                lines.push((score, self.pv[0].clone()));
+            }
+            lines.sort_by_key(|(score, _)| -score);
+            let (score, pv) = lines[0].clone();
+            result = SearchResult {
+                best_move: pv.first().copied(),
+                score,
+                depth,
+                nodes: 0,
+                pv,
+                lines,
+            };
+            if self.thread == 0 {
+                for (i, (score, pv)) in result.lines.iter().enumerate() {
+                    report(&SearchInfo {
+                        depth,
+                        multi_pv: i + 1,
+                        score: *score,
+                        nodes: self.total_nodes(),
+                        time: self.elapsed(),
+                        hashfull: self.shared.tt.hashfull(),
+                        pv: pv.clone(),
+                    });
+                }
+                self.check_ponderhit();
+                if let (Some(time), Some(best_move)) = (&mut self.time, result.best_move) {
+                    time.on_iteration(best_move, score);
+                    if !self.pondering && time.should_stop_soft() {
+                        break;
+                    }
+                }
+            }
+            depth += 1;
+        }
+        self.shared
+            .nodes
+            .fetch_add(self.nodes % self.check_interval, Ordering::Relaxed);
+        result
+    }
+
+    fn elapsed(&self) -> Duration {
+        match &self.time {
+            Some(time) => time.elapsed(),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// The nodes of all threads, as far as the main thread knows
+    fn total_nodes(&self) -> u64 {
+        self.shared.nodes.load(Ordering::Relaxed) + self.nodes % self.check_interval
+    }
+
+    fn stopped(&self) -> bool {
+        self.shared.stop.load(Ordering::Relaxed)
+    }
+
+    /// After a ponderhit the search goes on as a normal search, the clock starts now
+    fn check_ponderhit(&mut self) {
+        if self.pondering && !self.shared.ponder.load(Ordering::Relaxed) {
+            self.pondering = false;
+            if let Some(time) = &mut self.time {
+                time.restart();
+            }
+        }
+    }
+
+    /// Counts a node and returns true if the search has to stop
+    fn count_node(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(self.check_interval) {
+            let total = self
+                .shared
+                .nodes
+                .fetch_add(self.check_interval, Ordering::Relaxed)
+                + self.check_interval;
+            let out_of_nodes = matches!(self.limits.nodes, Some(limit) if total >= limit);
+            self.check_ponderhit();
+            let out_of_time =
+                !self.pondering && matches!(&self.time, Some(time) if time.should_stop_hard());
+            if out_of_nodes || out_of_time {
+                self.shared.stop.store(true, Ordering::Relaxed);
+            }
+        }
+        self.stopped()
+    }
+
+    fn negamax(
+        &mut self,
+        board: &Board,
+        turn: bool,
+        mut depth: u32,
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+    ) -> i32 {
+        self.pv[ply].clear();
+        if self.count_node() {
+            return 0;
+        }
+        let key = board.zobrist_key(turn);
+        if ply > 0 && self.history.contains(&key) {
+            return 0;
+        }
+        let in_check = board.is_in_check(turn);
+        if in_check {
+            depth += 1;
+        }
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(board, turn, alpha, beta, ply);
+        }
+
+        let tt_entry = self.shared.tt.probe(key);
+        if let Some(entry) = tt_entry {
+            let score = score_from_tt(entry.score, ply);
+            if ply > 0 && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        if ply > 0 && self.shared.tablebases.can_probe(board) {
+            if let Some(wdl) = self.shared.tablebases.probe_wdl(board, turn) {
+                let (score, bound) = match wdl {
+                    Wdl::Win => (TB_WIN - ply as i32, Bound::Lower),
+                    Wdl::Loss => (-TB_WIN + ply as i32, Bound::Upper),
+                    // the 50 move rule makes these draws
+                    _ => (0, Bound::Exact),
+                };
+                let cutoff = match bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cutoff {
+                    self.shared.tt.store(
+                        key,
+                        TtEntry {
+                            best_move: None,
+                            score,
+                            depth,
+                            bound,
+                        },
+                    );
+                    return score;
+                }
+            }
+        }
+
+        let mut moves = board.get_legal_moves(turn);
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        let restricted =
+            ply == 0 && !(self.excluded_root_moves.is_empty() && self.root_moves.is_empty());
+        if restricted {
+            moves.retain(|m| !self.excluded_root_moves.contains(m));
+            if !self.root_moves.is_empty() {
+                moves.retain(|m| self.root_moves.contains(m));
+            }
+            if moves.is_empty() {
+                return -INFINITY;
+            }
+        }
+        let moves = self.order_moves(board, moves, tt_entry.and_then(|e| e.best_move), ply);
+
+        self.history.push(key);
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        for (i, r#move) in moves.iter().enumerate() {
+            let mut child = board.clone();
+            let (_, captured) = child.move_piece(r#move);
+            self.evaluator.push(board, &child);
+            let score = if i == 0 {
+                -self.negamax(&child, !turn, depth - 1, -beta, -alpha, ply + 1)
+            } else {
+                // principal variation search, prove the move is worse with a null window
+                let score = -self.negamax(&child, !turn, depth - 1, -alpha - 1, -alpha, ply + 1);
+                if score > alpha && score < beta {
+                    -self.negamax(&child, !turn, depth - 1, -beta, -alpha, ply + 1)
+                } else {
+                    score
+                }
+            };
+            self.evaluator.pop();
+            if self.stopped() {
+                self.history.pop();
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(*r#move);
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(*r#move, ply);
+                }
+            }
+            if alpha >= beta {
+                if captured.is_none() && self.killers[ply][0] != Some(*r#move) {
+                    self.killers[ply][1] = self.killers[ply][0];
+                    self.killers[ply][0] = Some(*r#move);
+                }
+                break;
+            }
+        }
+        self.history.pop();
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // the root without some of its moves is not the position the key stands for
+        if restricted {
+            return best_score;
+        }
+        self.shared.tt.store(
+            key,
+            TtEntry {
+                best_move,
+                score: score_to_tt(best_score, ply),
+                depth,
+                bound,
+            },
+        );
+        best_score
+    }
+
+    /// Only searches captures, so the static evaluation isn't taken in the middle of an exchange
+    fn quiescence(
+        &mut self,
+        board: &Board,
+        turn: bool,
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+    ) -> i32 {
+        self.pv[ply].clear();
+        if self.count_node() {
+            return 0;
+        }
+        let stand_pat = self.evaluator.evaluate(board, turn);
+        if stand_pat >= beta || ply >= MAX_PLY - 1 {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let captures = board
+            .get_legal_moves(turn)
+            .into_iter()
+            // underpromotions are left to the main search
+            .filter(|m| is_noisy(board, m) && matches!(m.promotion, None | Some(Promotion::Queen)))
+            .collect();
+        let mut best_score = stand_pat;
+        for r#move in self.order_moves(board, captures, None, ply) {
+            let mut child = board.clone();
+            child.move_piece(&r#move);
+            self.evaluator.push(board, &child);
+            let score = -self.quiescence(&child, !turn, -beta, -alpha, ply + 1);
+            self.evaluator.pop();
+            if self.stopped() {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(r#move, ply);
+                }
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best_score
+    }
+
+    fn update_pv(&mut self, r#move: Move, ply: usize) {
+        let (head, tail) = self.pv.split_at_mut(ply + 1);
+        head[ply].clear();
+        head[ply].push(r#move);
+        head[ply].extend_from_slice(&tail[0]);
+    }
+
+    /// Sorts the moves: the move from the transposition table, captures with the most
+    /// valuable victim and least valuable attacker first, killer moves, the rest, and
+    /// underpromotions last
+    fn order_moves(
+        &self,
+        board: &Board,
+        moves: Vec<Move>,
+        tt_move: Option<Move>,
+        ply: usize,
+    ) -> Vec<Move> {
+        let mut scored: Vec<(i32, Move)> = moves
+            .into_iter()
+            .map(|r#move| {
+                let score = if Some(r#move) == tt_move {
+                    1_000_000
+                } else if let Some(victim) = board.get_piece_pos(r#move.column_to, r#move.row_to) {
+                    let attacker = board
+                        .get_piece_pos(r#move.column_from, r#move.row_from)
+                        .unwrap();
+                    100_000 + victim.points() as i32 * 10 - attacker.points() as i32 / 100
+                } else if self.killers[ply][0] == Some(r#move) {
+                    90_000
+                } else if self.killers[ply][1] == Some(r#move) {
+                    80_000
+                } else {
+                    0
+                };
+                let score = match r#move.promotion {
+                    Some(Promotion::Queen) | None => score,
+                    Some(_) => score - 200_000,
+                };
+                (score, r#move)
+            })
+            .collect();
+        scored.sort_by_key(|(score, _)| -score);
+        scored.into_iter().map(|(_, r#move)| r#move).collect()
+    }
+}
+
+/// Captures, including en passant, and promotions
+pub fn is_noisy(board: &Board, r#move: &Move) -> bool {
+    if board
+        .get_piece_pos(r#move.column_to, r#move.row_to)
+        .is_some()
+    {
+        return true;
+    }
+    let piece = board.get_piece_pos(r#move.column_from, r#move.row_from);
+    let is_pawn = matches!(piece, Some(PieceType::WhitePawn | PieceType::BlackPawn));
+    is_pawn && (r#move.column_from != r#move.column_to || r#move.row_to == 0 || r#move.row_to == 7)
+}
+
+/// Mate scores are stored relative to the node instead of the root
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::game::Game;
+    use crate::engine::Engine;
+
+    #[test]
+    fn small_node_limits_are_kept() {
+        let mut engine = Engine::default();
+        for limit in [50, 200, 1000, 5000] {
+            let limits = SearchLimits {
+                nodes: Some(limit),
+                ..Default::default()
+            };
+            let result = engine.search(&Game::default(), &[], &limits, &mut |_| {});
+            assert!(result.best_move.is_some());
+            if limit < CHECK_INTERVAL {
+                assert_eq!(result.nodes, limit);
+            } else {
+                assert!(
+                    result.nodes < limit + CHECK_INTERVAL,
+                    "{} nodes",
+                    result.nodes
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn multi_pv_lines_are_distinct_and_sorted() {
+        let mut engine = Engine::default();
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let game = Game::default();
+        let lines = engine.analyse(&game, &[], &limits, 5);
+        assert_eq!(lines.len(), 5);
+        let legal_moves = game.board.get_legal_moves(game.turn);
+        let mut first_moves: Vec<Move> = lines.iter().map(|(_, pv)| pv[0]).collect();
+        assert!(first_moves.iter().all(|m| legal_moves.contains(m)));
+        first_moves.sort_by_key(Move::to_algebraic_notation);
+        first_moves.dedup();
+        assert_eq!(first_moves.len(), 5);
+        assert!(lines.windows(2).all(|pair| pair[0].0 >= pair[1].0));
+
+        // never more lines than legal moves: the king has a2, b1 and b2
+        let game = Game::from_fen("7k/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(engine.analyse(&game, &[], &limits, 5).len(), 3);
+    }
+}
@@ -0,0 +1,384 @@
+// Purpose: Self-play, the bot plays itself to make positions for tuning and training networks.
+//
+// Every game starts from a few book moves and/or random moves, after that both sides search
+// with a fixed amount of nodes or depth. A game ends by the rules, or is adjudicated when both
+// sides agree on the score for a while or when the tablebases know the result.
+// Every game has its own seed, so the same config writes the same positions whatever the
+// amount of threads.
+//
+// Output has one position per line, the score of the search in centipawns and the result
+// of the game from white's point of view, the tuner reads it as it is:
+//   <fen> | <score> | <result 1.0, 0.5 or 0.0>
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use crate::core::{
+    game::{Game, GameResult},
+    Move,
+};
+
+use super::book::Book;
+use super::rng::Rng;
+use super::search::{is_noisy, SearchLimits, MATE_BOUND};
+use super::syzygy::Wdl;
+use super::{Engine, EngineOptions};
+
+/// When a game is decided before it is over
+#[derive(Clone, Copy, Debug)]
+pub struct Adjudication {
+    /// a side wins when both sides agree it is ahead by `win_score` for `win_plies` plies in a row
+    pub win_score: i32,
+    pub win_plies: u32,
+    /// a draw when the score stays within `draw_score` for `draw_plies` plies in a row,
+    /// but not before ply `draw_after`
+    pub draw_score: i32,
+    pub draw_plies: u32,
+    pub draw_after: u32,
+    /// a draw after this many plies
+    pub max_plies: u32,
+    /// ask the tablebases of the engine, when it has them
+    pub tablebases: bool,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Adjudication {
+            win_score: 1000,
+            win_plies: 8,
+            draw_score: 10,
+            draw_plies: 16,
+            draw_after: 80,
+            max_plies: 400,
+            tablebases: true,
+        }
+    }
+}
+
+/// Keeps track of the scores of one game for `Adjudication`
+#[derive(Clone, Debug)]
+pub struct Adjudicator {
+    rules: Adjudication,
+    plies: u32,
+    /// plies in a row white has been winning (positive) or losing (negative)
+    win_streak: i32,
+    draw_streak: u32,
+}
+
+impl Adjudicator {
+    pub fn new(rules: Adjudication) -> Adjudicator {
+        Adjudicator {
+            rules,
+            plies: 0,
+            win_streak: 0,
+            draw_streak: 0,
+        }
+    }
+
+    /// Call after every search with its score from white's point of view, before the move is
+    /// made. Returns the result when the game can be adjudicated.
+    pub fn update(&mut self, white_score: i32) -> Option<GameResult> {
+        self.plies += 1;
+        let rules = &self.rules;
+        self.win_streak = if white_score >= rules.win_score {
+            self.win_streak.max(0) + 1
+        } else if white_score <= -rules.win_score {
+            self.win_streak.min(0) - 1
+        } else {
+            0
+        };
+        if white_score.abs() <= rules.draw_score {
+            self.draw_streak += 1;
+        } else {
+            self.draw_streak = 0;
+        }
+
+        if self.win_streak.unsigned_abs() >= rules.win_plies {
+            Some(if self.win_streak > 0 {
+                GameResult::WhiteWins
+            } else {
+                GameResult::BlackWins
+            })
+        } else if (self.plies >= rules.draw_after && self.draw_streak >= rules.draw_plies)
+            || self.plies >= rules.max_plies
+        {
+            Some(GameResult::Draw)
+        } else {
+            None
+        }
+    }
+}
+
+/// The result the tablebases give the position, None when it isn't in them
+pub fn tablebase_result(engine: &Engine, game: &Game) -> Option<GameResult> {
+    let wdl = engine.tablebases().probe_wdl(&game.board, game.turn)?;
+    Some(match (wdl, game.turn) {
+        (Wdl::Win, true) | (Wdl::Loss, false) => GameResult::WhiteWins,
+        (Wdl::Win, false) | (Wdl::Loss, true) => GameResult::BlackWins,
+        _ => GameResult::Draw,
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct SelfPlayConfig {
+    pub games: usize,
+    /// games played at the same time, every game searches with one thread
+    pub concurrency: usize,
+    pub seed: u64,
+    /// the search of every move, use nodes or depth so the games don't depend on the machine
+    pub limits: SearchLimits,
+    /// games start with book moves when there is a book...
+    pub book: Option<Book>,
+    /// ...followed by this many random moves
+    pub random_plies: u32,
+    pub adjudication: Adjudication,
+    /// directories of the Syzygy tablebases, empty for none
+    pub syzygy_path: String,
+    /// hash size of every engine in MB
+    pub hash_size: usize,
+    /// leave out positions in check or where the best move captures or promotes
+    pub quiet_only: bool,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        SelfPlayConfig {
+            games: 100,
+            concurrency: 1,
+            seed: 0,
+            limits: SearchLimits {
+                nodes: Some(5000),
+                ..Default::default()
+            },
+            book: None,
+            random_plies: 8,
+            adjudication: Adjudication::default(),
+            syzygy_path: String::new(),
+            hash_size: 16,
+            quiet_only: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SelfPlayStats {
+    pub games: usize,
+    pub positions: usize,
+    pub white_wins: usize,
+    pub black_wins: usize,
+    pub draws: usize,
+}
+
+/// A position of a game with the score of the search from white's point of view
+struct Position {
+    fen: String,
+    score: i32,
+}
+
+/// Plays `config.games` games and writes their positions in the order of the games.
+/// `progress` is called after every game.
+pub fn generate(
+    config: &SelfPlayConfig,
+    out: &mut dyn Write,
+    progress: &mut dyn FnMut(&SelfPlayStats),
+) -> io::Result<SelfPlayStats> {
+    let next_game = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut stats = SelfPlayStats::default();
+
+    std::thread::scope(|scope| {
+        for _ in 0..config.concurrency.clamp(1, config.games.max(1)) {
+            let sender = sender.clone();
+            let next_game = &next_game;
+            scope.spawn(move || {
+                let mut engine = Engine::new(EngineOptions {
+                    hash_size: config.hash_size,
+                    ..Default::default()
+                });
+                engine.set_syzygy_path(&config.syzygy_path);
+                loop {
+                    let index = next_game.fetch_add(1, Ordering::Relaxed);
+                    if index >= config.games {
+                        break;
+                    }
+                    let game = play_game(&mut engine, config, index);
+                    if sender.send((index, game)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // games finish in any order, but are written in the order they were started
+        let mut finished = BTreeMap::new();
+        let mut next_write = 0;
+        for (index, game) in receiver {
+            finished.insert(index, game);
+            while let Some((positions, result)) = finished.remove(&next_write) {
+                next_write += 1;
+                for position in &positions {
+                    writeln!(
+                        out,
+                        "{} | {} | {:.1}",
+                        position.fen,
+                        position.score,
+                        result.white_score()
+                    )?;
+                }
+                stats.games += 1;
+                stats.positions += positions.len();
+                match result {
+                    GameResult::WhiteWins => stats.white_wins += 1,
+                    GameResult::BlackWins => stats.black_wins += 1,
+                    GameResult::Draw => stats.draws += 1,
+                }
+                progress(&stats);
+            }
+        }
+        out.flush()
+    })?;
+    Ok(stats)
+}
+
+/// Plays game number `index` of the config
+fn play_game(
+    engine: &mut Engine,
+    config: &SelfPlayConfig,
+    index: usize,
+) -> (Vec<Position>, GameResult) {
+    let mut rng = Rng::new(config.seed ^ (index as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    engine.new_game();
+    engine.set_seed(rng.next_u64());
+
+    let (mut game, mut history) = loop {
+        if let Some(opening) = opening(config, &mut rng) {
+            break opening;
+        }
+    };
+    let mut adjudicator = Adjudicator::new(config.adjudication);
+    let mut positions = Vec::new();
+    let result = loop {
+        if let Some(result) = game.result(&history) {
+            break result;
+        }
+        if config.adjudication.tablebases {
+            if let Some(result) = tablebase_result(engine, &game) {
+                break result;
+            }
+        }
+        let search = engine.search(&game, &history, &config.limits, &mut |_| {});
+        let Some(best_move) = search.best_move else {
+            break GameResult::Draw;
+        };
+        let white_score = if game.turn {
+            search.score
+        } else {
+            -search.score
+        };
+        let quiet = !game.board.is_in_check(game.turn) && !is_noisy(&game.board, &best_move);
+        if search.score.abs() < MATE_BOUND && (quiet || !config.quiet_only) {
+            positions.push(Position {
+                fen: game.exportPosition(),
+                score: white_score,
+            });
+        }
+        if let Some(result) = adjudicator.update(white_score) {
+            break result;
+        }
+        history.push(game.board.zobrist_key(game.turn));
+        game.make_move(&best_move);
+    };
+    (positions, result)
+}
+
+/// The book and random moves a game starts with, None when they end the game
+fn opening(config: &SelfPlayConfig, rng: &mut Rng) -> Option<(Game, Vec<u64>)> {
+    let mut game = Game::default();
+    let mut history = Vec::new();
+    let mut random_plies = 0;
+    loop {
+        // a book that keeps repeating itself would never end
+        let book_move = match &config.book {
+            Some(book) if history.len() < 100 => book.probe(&game, rng),
+            _ => None,
+        };
+        let r#move: Move = match book_move {
+            Some(r#move) => r#move,
+            None if random_plies < config.random_plies => {
+                let moves = game.board.get_legal_moves(game.turn);
+                if moves.is_empty() {
+                    return None;
+                }
+                random_plies += 1;
+                moves[rng.below(moves.len() as u64) as usize]
+            }
+            None => break,
+        };
+        history.push(game.board.zobrist_key(game.turn));
+        game.make_move(&r#move);
+    }
+    if game.result(&history).is_some() {
+        return None;
+    }
+    Some((game, history))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: Adjudication = Adjudication {
+        win_score: 1000,
+        win_plies: 4,
+        draw_score: 10,
+        draw_plies: 6,
+        draw_after: 20,
+        max_plies: 100,
+        tablebases: false,
+    };
+
+    /// The plies until the scores are adjudicated and the result
+    fn adjudicate(scores: impl IntoIterator<Item = i32>) -> Option<(usize, GameResult)> {
+        let mut adjudicator = Adjudicator::new(RULES);
+        scores
+            .into_iter()
+            .enumerate()
+            .find_map(|(ply, score)| Some((ply + 1, adjudicator.update(score)?)))
+    }
+
+    #[test]
+    fn win_after_enough_plies_in_a_row() {
+        assert_eq!(adjudicate([1000; 10]), Some((4, GameResult::WhiteWins)));
+        assert_eq!(adjudicate([-1500; 10]), Some((4, GameResult::BlackWins)));
+        // a score below the margin starts counting again
+        assert_eq!(
+            adjudicate([1000, 1000, 1000, 999, 1000, 1000, 1000, 1000]),
+            Some((8, GameResult::WhiteWins))
+        );
+        // and so does the other side winning
+        assert_eq!(
+            adjudicate([1000, 1000, 1000, -1000, -1000, -1000, -1000]),
+            Some((7, GameResult::BlackWins))
+        );
+        assert_eq!(adjudicate([1000, -1000].repeat(10)), None);
+    }
+
+    #[test]
+    fn draw_only_after_draw_after() {
+        // the streak started before ply 20 counts, the draw waits for it
+        assert_eq!(adjudicate([0; 30]), Some((20, GameResult::Draw)));
+        let mut scores = vec![50; 20];
+        scores.extend([10, -10, 0, 5, -5]);
+        assert_eq!(adjudicate(scores.clone()), None);
+        scores.push(0);
+        assert_eq!(adjudicate(scores), Some((26, GameResult::Draw)));
+    }
+
+    #[test]
+    fn draw_at_max_plies() {
+        assert_eq!(adjudicate([500; 200]), Some((100, GameResult::Draw)));
+    }
+}
@@ -0,0 +1,155 @@
+// Purpose: Makes the bot play weaker on purpose, at a level given as an Elo-like rating.
+//
+// A weaker bot searches less deep and with fewer nodes, and it doesn't always play its best
+// move: it searches a few lines and picks one of them at random, where lines that are much
+// worse than the best one get a smaller chance. The lower the rating, the more the chance of
+// a worse line grows. The limits are in depth and nodes rather than time, so a level plays
+// the same on every machine and can be calibrated with the match runner.
+
+use crate::core::Move;
+
+use super::rng::Rng;
+use super::search::{SearchLimits, MATE_BOUND};
+
+pub const MIN_ELO: u32 = 600;
+pub const MAX_ELO: u32 = 2400;
+/// Lines the weakened bot chooses from
+const CANDIDATES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Strength {
+    elo: u32,
+}
+
+impl Strength {
+    pub fn new(elo: u32) -> Strength {
+        Strength {
+            elo: elo.clamp(MIN_ELO, MAX_ELO),
+        }
+    }
+
+    pub fn elo(&self) -> u32 {
+        self.elo
+    }
+
+    /// 0 at the lowest rating and 1 at the highest
+    fn level(&self) -> f64 {
+        (self.elo - MIN_ELO) as f64 / (MAX_ELO - MIN_ELO) as f64
+    }
+
+    /// The limits of the search at this level, never more than `limits`
+    pub fn limits(&self, limits: &SearchLimits) -> SearchLimits {
+        let level = self.level();
+        let depth = 1 + (level * 9.0).round() as u32;
+        // from 50 nodes to 500k nodes, growing as fast as the rating
+        let nodes = (50.0 * 10_000f64.powf(level)) as u64;
+        SearchLimits {
+            depth: Some(limits.depth.map_or(depth, |d| d.min(depth))),
+            nodes: Some(limits.nodes.map_or(nodes, |n| n.min(nodes))),
+            time: limits.time,
+        }
+    }
+
+    /// The amount of lines to search
+    pub fn multi_pv(&self) -> usize {
+        CANDIDATES
+    }
+
+    /// Picks the move to play from the lines of the search, best line first.
+    /// A line that is `temperature` centipawns worse than the best is e times less likely.
+    pub fn pick(&self, lines: &[(i32, Vec<Move>)], rng: &mut Rng) -> Option<usize> {
+        let best = lines.first()?.0;
+        // never throw away a mate
+        if best.abs() >= MATE_BOUND {
+            return Some(0);
+        }
+        let temperature = 5.0 + 150.0 * (1.0 - self.level()).powi(2);
+        let weights: Vec<f64> = lines
+            .iter()
+            .map(|(score, pv)| {
+                if pv.is_empty() {
+                    0.0
+                } else {
+                    (-((best - score) as f64) / temperature).exp()
+                }
+            })
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let mut choice = rng.next_f64() * total;
+        for (i, weight) in weights.iter().enumerate() {
+            if choice < *weight {
+                return Some(i);
+            }
+            choice -= weight;
+        }
+        Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::search::MATE;
+
+    #[test]
+    fn limits_grow_with_the_rating() {
+        let unlimited = SearchLimits::default();
+        let mut previous = Strength::new(MIN_ELO).limits(&unlimited);
+        assert_eq!(previous.depth, Some(1));
+        assert_eq!(previous.nodes, Some(50));
+        for elo in (MIN_ELO..=MAX_ELO).step_by(50).skip(1) {
+            let limits = Strength::new(elo).limits(&unlimited);
+            assert!(limits.depth >= previous.depth, "depth at {}", elo);
+            assert!(limits.nodes >= previous.nodes, "nodes at {}", elo);
+            previous = limits;
+        }
+        assert_eq!(previous.depth, Some(10));
+        assert_eq!(previous.nodes, Some(500_000));
+        // never more than the limits that were asked for
+        let asked = SearchLimits {
+            depth: Some(3),
+            nodes: Some(1000),
+            ..Default::default()
+        };
+        let limits = Strength::new(MAX_ELO).limits(&asked);
+        assert_eq!((limits.depth, limits.nodes), (Some(3), Some(1000)));
+    }
+
+    #[test]
+    fn pick_keeps_a_mate() {
+        let line = |notation: &str| vec![Move::from_algebraic_notation(notation).unwrap()];
+        let lines = [
+            (MATE - 3, line("d1h5")),
+            (200, line("e2e4")),
+            (190, line("d2d4")),
+            (180, line("g1f3")),
+        ];
+        let mut rng = Rng::new(1);
+        for elo in [MIN_ELO, 1500, MAX_ELO] {
+            for _ in 0..100 {
+                assert_eq!(Strength::new(elo).pick(&lines, &mut rng), Some(0));
+            }
+        }
+    }
+
+    #[test]
+    fn pick_spreads_at_low_ratings() {
+        let line = |notation: &str| vec![Move::from_algebraic_notation(notation).unwrap()];
+        let lines = [
+            (30, line("e2e4")),
+            (20, line("d2d4")),
+            (10, line("g1f3")),
+            (0, line("c2c4")),
+        ];
+        let mut rng = Rng::new(1);
+        let picks = |elo, rng: &mut Rng| {
+            (0..1000)
+                .filter(|_| Strength::new(elo).pick(&lines, rng) != Some(0))
+                .count()
+        };
+        let weak = picks(MIN_ELO, &mut rng);
+        let strong = picks(MAX_ELO, &mut rng);
+        assert!(weak > strong, "{} against {}", weak, strong);
+        assert_eq!(Strength::new(MIN_ELO).pick(&[], &mut rng), None);
+    }
+}
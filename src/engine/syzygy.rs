@@ -0,0 +1,1383 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// Purpose: Syzygy endgame tablebases, perfect play once only a few pieces are left.
+//
+// The tables are local files: `.rtbw` files store win/draw/loss (WDL) and `.rtbz`
+// files the distance to zeroing (DTZ), the amount of plies until the next capture
+// or pawn move of a perfect game. The format is only documented by its generator,
+// this follows the probing code of the generator and of Stockfish. Stockfish is GPL-3,
+// so unlike the rest of the crate this file is too, and it is only built with the
+// `syzygy` feature. Without it syzygy_disabled.rs takes its place.
+//
+// A table is memory mapped the first time it is probed, so only the parts of it that
+// are used get read from the disk. A missing or broken file is never an error, probing just returns None and the search goes on without.
+
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Neg;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use memmap2::Mmap;
+
+use crate::core::{board::Board, Move, PieceType};
+
+/// Tables with more pieces don't exist
+pub const MAX_PIECES: usize = 7;
+/// Highest rank of a move in `Tablebases::rank_root_moves`
+pub const MAX_DTZ: i32 = 1 << 18;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// flags of a sub table, all but the last one are only used by DTZ tables
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// Result of a position with perfect play, from the point of view of the side to move.
+/// Cursed wins and blessed losses are draws because of the 50 move rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32))
+    }
+}
+
+/// A move of the root with how good it is according to the tablebases
+#[derive(Clone, Copy, Debug)]
+pub struct RootMove {
+    pub r#move: Move,
+    /// distance to zeroing in plies after the move, positive when we win
+    pub dtz: i32,
+    /// higher is better, positive wins, 0 draws, negative loses.
+    /// Certain wins are `MAX_DTZ`, wins that may be a draw by the 50 move rule are lower.
+    pub rank: i32,
+}
+
+/// How a probe went besides its result
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ProbeState {
+    Ok,
+    /// the DTZ table only stores the other side to move
+    ChangeStm,
+    /// the best move is a capture or pawn move, the table stores a "don't care" value
+    ZeroingBestMove,
+}
+
+/// All tables found in the Syzygy directories
+#[derive(Default)]
+pub struct Tablebases {
+    tables: Vec<TableFiles>,
+    /// index into `tables` by material, ex: "KRvK", a table is listed under both colors
+    by_material: HashMap<String, usize>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    pub fn new() -> Tablebases {
+        Tablebases::default()
+    }
+
+    /// Finds the tables in one or more directories, separated by ':' (';' on Windows)
+    pub fn open(paths: &str) -> Tablebases {
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        let mut tablebases = Tablebases::new();
+        for directory in paths.split(separator).filter(|p| !p.is_empty()) {
+            let entries = match fs::read_dir(directory) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|e| e == "rtbw") {
+                    tablebases.add_table(&path, paths, separator);
+                }
+            }
+        }
+        tablebases
+    }
+
+    fn add_table(&mut self, wdl_path: &Path, paths: &str, separator: char) {
+        let name = match wdl_path.file_stem().and_then(|s| s.to_str()) {
+            Some(name) => name.to_string(),
+            None => return,
+        };
+        let material = match Material::parse(&name) {
+            Some(material) => material,
+            None => return,
+        };
+        if self.by_material.contains_key(&name) {
+            return;
+        }
+        let dtz_path = paths
+            .split(separator)
+            .map(|directory| Path::new(directory).join(format!("{}.rtbz", name)))
+            .find(|path| path.is_file());
+        let (white, black) = (material.white.clone(), material.black.clone());
+        self.max_pieces = self.max_pieces.max(material.piece_count);
+        self.tables.push(TableFiles {
+            material,
+            wdl_path: wdl_path.to_path_buf(),
+            dtz_path,
+            wdl: OnceLock::new(),
+            dtz: OnceLock::new(),
+        });
+        let index = self.tables.len() - 1;
+        self.by_material
+            .insert(format!("{}v{}", white, black), index);
+        self.by_material
+            .insert(format!("{}v{}", black, white), index);
+    }
+
+    /// Amount of WDL tables found
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// The most pieces of any table, 0 without tables
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Is the position small enough to be in the tables, positions with castling rights never are
+    pub fn can_probe(&self, board: &Board) -> bool {
+        board.castling_rights() == 0
+            && board.get_all_pieces().count_ones() as usize <= self.max_pieces
+    }
+
+    /// Win, draw or loss of the position, None when it isn't in the tables
+    pub fn probe_wdl(&self, board: &Board, turn: bool) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        self.search(board, turn, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Distance to zeroing in plies, positive when the side to move wins, 0 for a draw.
+    /// None when the position or a position after it isn't in the tables.
+    pub fn probe_dtz(&self, board: &Board, turn: bool) -> Option<i32> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        self.dtz(board, turn)
+    }
+
+    /// Ranks all legal moves of the root, so the search only has to look at the best ones.
+    /// `halfmove_clock` is the 50 move counter and `repeated` tells whether the position
+    /// was already on the board since the last zeroing move.
+    pub fn rank_root_moves(
+        &self,
+        board: &Board,
+        turn: bool,
+        halfmove_clock: u32,
+        repeated: bool,
+    ) -> Option<Vec<RootMove>> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        let halfmove_clock = halfmove_clock as i32;
+        let mut root_moves = Vec::new();
+        for r#move in board.get_legal_moves(turn) {
+            let mut child = board.clone();
+            let (piece, captured) = child.move_piece(&r#move);
+            let mut dtz = if captured.is_some() || is_pawn(piece) {
+                // after a zeroing move the dtz of the move itself is all that counts
+                dtz_before_zeroing(-self.search(&child, !turn, false)?.0)
+            } else {
+                let dtz = -self.dtz(&child, !turn)?;
+                dtz + dtz.signum()
+            };
+            // a mating move has a dtz of 1
+            if dtz == 2 && child.is_in_check(!turn) && child.get_legal_moves(!turn).is_empty() {
+                dtz = 1;
+            }
+            let rank = if dtz > 0 {
+                if dtz + halfmove_clock <= 99 && !repeated {
+                    MAX_DTZ
+                } else {
+                    MAX_DTZ - (dtz + halfmove_clock)
+                }
+            } else if dtz < 0 {
+                if -dtz * 2 + halfmove_clock < 100 {
+                    -MAX_DTZ
+                } else {
+                    -MAX_DTZ + (-dtz + halfmove_clock)
+                }
+            } else {
+                0
+            };
+            root_moves.push(RootMove { r#move, dtz, rank });
+        }
+        Some(root_moves)
+    }
+
+    /// Probes the WDL table after looking at the captures, and at the pawn moves with
+    /// `check_zeroing`. Tables store "don't care" values when a capture or pawn move
+    /// is the best move, so those have to be searched.
+    fn search(&self, board: &Board, turn: bool, check_zeroing: bool) -> Option<(Wdl, ProbeState)> {
+        let moves = board.get_legal_moves(turn);
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for r#move in &moves {
+            let mut child = board.clone();
+            let (piece, captured) = child.move_piece(r#move);
+            if captured.is_none() && !(check_zeroing && is_pawn(piece)) {
+                continue;
+            }
+            searched += 1;
+            let value = -self.search(&child, !turn, false)?.0;
+            if value > best {
+                best = value;
+                if value >= Wdl::Win {
+                    return Some((value, ProbeState::ZeroingBestMove));
+                }
+            }
+        }
+
+        // with all moves searched the table isn't needed, it could even be wrong
+        // because tables don't know about en passant
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            // the tables store the WDL + 2
+            Wdl::from_value(self.probe_table(board, turn, TableKind::Wdl, Wdl::Draw)?.0 - 2)
+        };
+        if best >= value {
+            let state = if best > Wdl::Draw || no_more_moves {
+                ProbeState::ZeroingBestMove
+            } else {
+                ProbeState::Ok
+            };
+            return Some((best, state));
+        }
+        Some((value, ProbeState::Ok))
+    }
+
+    fn dtz(&self, board: &Board, turn: bool) -> Option<i32> {
+        let (wdl, state) = self.search(board, turn, true)?;
+        // DTZ tables don't store draws
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if state == ProbeState::ZeroingBestMove {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        let (dtz, state) = self.probe_table(board, turn, TableKind::Dtz, wdl)?;
+        if state != ProbeState::ChangeStm {
+            let cursed = matches!(wdl, Wdl::BlessedLoss | Wdl::CursedWin);
+            return Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum());
+        }
+
+        // the table only has the other side to move, find the best move with a 1 ply search
+        let mut min_dtz = i32::MAX;
+        for r#move in board.get_legal_moves(turn) {
+            let mut child = board.clone();
+            let (piece, captured) = child.move_piece(&r#move);
+            let zeroing = captured.is_some() || is_pawn(piece);
+            // for a zeroing move we want the dtz before the move, the search of the
+            // position after the move gives the sign
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&child, !turn, false)?.0)
+            } else {
+                -self.dtz(&child, !turn)?
+            };
+            if dtz == 1 && child.is_in_check(!turn) && child.get_legal_moves(!turn).is_empty() {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+        }
+        // without legal moves we are mated
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    /// Looks the position up in its table, the value is the WDL + 2 or the DTZ
+    fn probe_table(
+        &self,
+        board: &Board,
+        turn: bool,
+        kind: TableKind,
+        wdl: Wdl,
+    ) -> Option<(i32, ProbeState)> {
+        let (white, black) = material_of(board);
+        // king against king
+        if white == "K" && black == "K" {
+            return Some((Wdl::Draw as i32 + 2, ProbeState::Ok));
+        }
+        let files = &self.tables[*self.by_material.get(&format!("{}v{}", white, black))?];
+        let table = files.table(kind)?;
+        let material = &files.material;
+        encode_and_probe(
+            board,
+            turn,
+            material,
+            table,
+            kind,
+            wdl,
+            white != material.white,
+        )
+    }
+}
+
+fn is_pawn(piece: PieceType) -> bool {
+    matches!(piece, PieceType::WhitePawn | PieceType::BlackPawn)
+}
+
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::Draw => 0,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+    }
+}
+
+/// The pieces of both sides as in the file names, ex: ("KRP", "K")
+fn material_of(board: &Board) -> (String, String) {
+    let side = |pieces: [PieceType; 6]| {
+        let mut side = String::new();
+        for (piece, letter) in pieces.into_iter().zip("KQRBNP".chars()) {
+            for _ in 0..board.get_pieces(piece).count_ones() {
+                side.push(letter);
+            }
+        }
+        side
+    };
+    (
+        side([
+            PieceType::WhiteKing,
+            PieceType::WhiteQueen,
+            PieceType::WhiteRook,
+            PieceType::WhiteBishop,
+            PieceType::WhiteKnight,
+            PieceType::WhitePawn,
+        ]),
+        side([
+            PieceType::BlackKing,
+            PieceType::BlackQueen,
+            PieceType::BlackRook,
+            PieceType::BlackBishop,
+            PieceType::BlackKnight,
+            PieceType::BlackPawn,
+        ]),
+    )
+}
+
+/// The piece numbers of the table format: white pawn..king is 1..6, black is 9..14
+#[rustfmt::skip]
+fn piece_code(piece: PieceType) -> u8 {
+    match piece {
+        PieceType::WhitePawn   => 1,
+        PieceType::WhiteKnight => 2,
+        PieceType::WhiteBishop => 3,
+        PieceType::WhiteRook   => 4,
+        PieceType::WhiteQueen  => 5,
+        PieceType::WhiteKing   => 6,
+        PieceType::BlackPawn   => 9,
+        PieceType::BlackKnight => 10,
+        PieceType::BlackBishop => 11,
+        PieceType::BlackRook   => 12,
+        PieceType::BlackQueen  => 13,
+        PieceType::BlackKing   => 14,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+/// What a table is about, known from its file name before the file is read
+struct Material {
+    /// pieces of the stronger side as in the file name, ex: "KRP"
+    white: String,
+    black: String,
+    piece_count: usize,
+    has_pawns: bool,
+    /// some side has a piece other than the king of which it has only one
+    has_unique_pieces: bool,
+    /// pawns of the leading color and of the other color, the leading color has the least pawns
+    pawn_count: [usize; 2],
+    /// both sides have the same pieces
+    symmetric: bool,
+}
+
+impl Material {
+    /// Parses a file name like "KRPvKR"
+    fn parse(name: &str) -> Option<Material> {
+        let (white, black) = name.split_once('v')?;
+        let valid = |side: &str| {
+            side.starts_with('K')
+                && side.matches('K').count() == 1
+                && side.chars().all(|c| "KQRBNP".contains(c))
+        };
+        if !valid(white) || !valid(black) || white.len() + black.len() > MAX_PIECES {
+            return None;
+        }
+        let count = |side: &str, c: char| side.matches(c).count();
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|c| count(side, c) == 1));
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = if white_leads {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+        Some(Material {
+            white: white.to_string(),
+            black: black.to_string(),
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count,
+            symmetric: white == black,
+        })
+    }
+}
+
+/// The files of one material combination, mapped on first use
+struct TableFiles {
+    material: Material,
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+
+impl TableFiles {
+    fn table(&self, kind: TableKind) -> Option<&Table> {
+        let (cell, path) = match kind {
+            TableKind::Wdl => (&self.wdl, Some(&self.wdl_path)),
+            TableKind::Dtz => (&self.dtz, self.dtz_path.as_ref()),
+        };
+        cell.get_or_init(|| {
+            let file = fs::File::open(path?).ok()?;
+            // the tables are never written to while the engine runs
+            let data = unsafe { Mmap::map(&file) }.ok()?;
+            Table::parse(data, kind, &self.material)
+        })
+        .as_ref()
+    }
+}
+
+/// Indexing and compression information of one sub table, see `Table`
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    /// every `span` values there is an entry in the sparse index
+    span: u64,
+    num_blocks: usize,
+    max_sym_len: usize,
+    min_sym_len: usize,
+    // offsets into the file
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    /// base64[l - min_sym_len] is the lowest symbol of length l, padded to 64 bits
+    base64: Vec<u64>,
+    /// amount of values minus one a symbol stands for
+    symlen: Vec<u8>,
+    /// the pieces in the order they are encoded, this defines the groups
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    /// pieces per group, ends with a 0
+    group_len: [usize; MAX_PIECES + 1],
+    /// offsets of the DTZ value maps for win, loss, cursed win and blessed loss
+    map_idx: [usize; 4],
+}
+
+/// A memory mapped file. Tables with pawns have a sub table for every file of the
+/// leading pawn (a-d), WDL tables that aren't symmetric have one per side to move.
+struct Table {
+    data: Mmap,
+    /// [side to move][file of the leading pawn]
+    items: [[PairsData; 4]; 2],
+}
+
+impl Table {
+    fn parse(data: Mmap, kind: TableKind, material: &Material) -> Option<Table> {
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if data.len() < 5 || data[..4] != magic {
+            return None;
+        }
+        let flags = data[4];
+        if (flags & 2 != 0) != material.has_pawns || (flags & 1 != 0) == material.symmetric {
+            return None;
+        }
+        let mut table = Table {
+            data,
+            items: Default::default(),
+        };
+        table.init(kind, material)?;
+        Some(table)
+    }
+
+    fn init(&mut self, kind: TableKind, material: &Material) -> Option<()> {
+        let data = &self.data;
+        let mut p = 5;
+        let sides = if kind == TableKind::Wdl && !material.symmetric {
+            2
+        } else {
+            1
+        };
+        let files = if material.has_pawns { 4 } else { 1 };
+        // both sides have pawns
+        let pp = material.has_pawns && material.pawn_count[1] > 0;
+
+        for file in 0..files {
+            let second = if pp { *data.get(p + 1)? } else { 0xFF };
+            let first = *data.get(p)?;
+            let order = [[first & 0xF, second & 0xF], [first >> 4, second >> 4]];
+            p += 1 + pp as usize;
+            for k in 0..material.piece_count {
+                let byte = *data.get(p)?;
+                for (side, items) in self.items.iter_mut().enumerate().take(sides) {
+                    items[file].pieces[k] = if side == 1 { byte >> 4 } else { byte & 0xF };
+                }
+                p += 1;
+            }
+            for (side, order) in order.into_iter().enumerate().take(sides) {
+                set_groups(material, &mut self.items[side][file], order, file);
+            }
+        }
+        p += p & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                p = set_sizes(&mut self.items[side][file], data, p)?;
+            }
+        }
+        if kind == TableKind::Dtz {
+            p = self.set_dtz_map(p, files)?;
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.sparse_index = p;
+                p += d.sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.block_length = p;
+                p += d.block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                p = (p + 0x3F) & !0x3F;
+                d.data = p;
+                p += d.num_blocks * d.block_size;
+            }
+        }
+        (p <= self.data.len()).then_some(())
+    }
+
+    /// Finds the maps that turn the stored DTZ values into real ones
+    fn set_dtz_map(&mut self, mut p: usize, files: usize) -> Option<usize> {
+        for file in 0..files {
+            let d = &mut self.items[0][file];
+            if d.flags & FLAG_MAPPED == 0 {
+                continue;
+            }
+            for i in 0..4 {
+                if d.flags & FLAG_WIDE != 0 {
+                    p += p & 1;
+                    d.map_idx[i] = p + 2;
+                    p += 2 * read_u16_le(&self.data, p) as usize + 2;
+                } else {
+                    d.map_idx[i] = p + 1;
+                    p += *self.data.get(p)? as usize + 1;
+                }
+            }
+        }
+        Some(p + (p & 1))
+    }
+}
+
+/// Splits the pieces into groups and computes the size of the index of every group
+fn set_groups(material: &Material, d: &mut PairsData, order: [u8; 2], file: usize) {
+    let encoding = encoding();
+    let mut n = 0;
+    let mut first_len: i32 = if material.has_pawns {
+        0
+    } else if material.has_unique_pieces {
+        3
+    } else {
+        2
+    };
+    d.group_len[0] = 1;
+    for i in 1..material.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    // the groups are encoded in the order of the table, the leading group is at
+    // order[0] and the remaining pawns, when both sides have pawns, at order[1]
+    let pp = material.has_pawns && material.pawn_count[1] > 0;
+    let mut next = if pp { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if pp { d.group_len[1] } else { 0 };
+    let mut idx: u64 = 1;
+    let mut k = 0;
+    while next < n || k == order[0] as usize || k == order[1] as usize {
+        if k == order[0] as usize {
+            d.group_idx[0] = idx;
+            idx *= if material.has_pawns {
+                encoding.lead_pawns_size[d.group_len[0]][file]
+            } else if material.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] as usize {
+            d.group_idx[1] = idx;
+            idx *= encoding.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_idx[next] = idx;
+            idx *= encoding.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_idx[n] = idx;
+}
+
+/// Reads the sizes of a sub table and its Huffman code, returns the offset after them
+fn set_sizes(d: &mut PairsData, data: &[u8], mut p: usize) -> Option<usize> {
+    d.flags = *data.get(p)?;
+    p += 1;
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        // the single value is stored as the min symbol length
+        d.min_sym_len = *data.get(p)? as usize;
+        return Some(p + 1);
+    }
+
+    let groups = d
+        .group_len
+        .iter()
+        .position(|len| *len == 0)
+        .unwrap_or(MAX_PIECES);
+    let table_size = d.group_idx[groups];
+    d.block_size = 1usize.checked_shl(*data.get(p)? as u32)?;
+    d.span = 1u64.checked_shl(*data.get(p + 1)? as u32)?;
+    d.sparse_index_size = table_size.div_ceil(d.span) as usize;
+    let padding = *data.get(p + 2)? as usize;
+    d.num_blocks = read_u32_le(data, p + 3) as usize;
+    d.block_length_size = d.num_blocks + padding;
+    d.max_sym_len = *data.get(p + 7)? as usize;
+    d.min_sym_len = *data.get(p + 8)? as usize;
+    p += 9;
+    // the decoder keeps at least 32 bits in its buffer
+    if d.max_sym_len < d.min_sym_len || d.min_sym_len == 0 || d.max_sym_len > 32 {
+        return None;
+    }
+    d.lowest_sym = p;
+
+    // canonical Huffman code: longer symbols have lower values
+    let lengths = d.max_sym_len - d.min_sym_len + 1;
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        d.base64[i] = d.base64[i + 1]
+            .wrapping_add(read_u16_le(data, d.lowest_sym + 2 * i) as u64)
+            .wrapping_sub(read_u16_le(data, d.lowest_sym + 2 * (i + 1)) as u64)
+            / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base = base
+            .checked_shl((64 - i - d.min_sym_len) as u32)
+            .unwrap_or(0);
+    }
+    p += lengths * 2;
+
+    let symbols = read_u16_le(data, p) as usize;
+    p += 2;
+    d.btree = p;
+    d.symlen = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for symbol in 0..symbols {
+        if !visited[symbol] {
+            set_symlen(d, data, symbol, &mut visited);
+        }
+    }
+    Some(p + symbols * 3 + (symbols & 1))
+}
+
+/// Symbols are pairs of smaller symbols (recursive pairing), counts the values of a symbol
+fn set_symlen(d: &mut PairsData, data: &[u8], symbol: usize, visited: &mut [bool]) {
+    visited[symbol] = true;
+    let (left, right) = btree(d, data, symbol);
+    if right == 0xFFF {
+        return;
+    }
+    for child in [left, right] {
+        if child < visited.len() && !visited[child] {
+            set_symlen(d, data, child, visited);
+        }
+    }
+    let count = |s: usize| d.symlen.get(s).copied().unwrap_or(0) as usize;
+    d.symlen[symbol] = (count(left) + count(right) + 1) as u8;
+}
+
+/// The left and right symbol a symbol stands for, 12 bits each
+fn btree(d: &PairsData, data: &[u8], symbol: usize) -> (usize, usize) {
+    let p = d.btree + symbol * 3;
+    let byte = |i: usize| data.get(p + i).copied().unwrap_or(0) as usize;
+    ((byte(1) & 0xF) << 8 | byte(0), byte(2) << 4 | byte(1) >> 4)
+}
+
+/// The value at `idx` of a sub table
+fn decompress_pairs(d: &PairsData, data: &[u8], idx: u64) -> i32 {
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        return d.min_sym_len as i32;
+    }
+
+    // the sparse index points at the block of every `span`-th value, from there
+    // walk to the block that holds our value
+    let k = (idx / d.span) as usize;
+    let mut block = read_u32_le(data, d.sparse_index + 6 * k) as i64;
+    let mut offset = read_u16_le(data, d.sparse_index + 6 * k + 4) as i64;
+    offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+    let block_length = |block: i64| read_u16_le(data, d.block_length + 2 * block as usize) as i64;
+    while offset < 0 && block > 0 {
+        block -= 1;
+        offset += block_length(block) + 1;
+    }
+    while offset > block_length(block) && (block as usize) < d.block_length_size {
+        offset -= block_length(block) + 1;
+        block += 1;
+    }
+
+    // read Huffman symbols until we reach the one that holds our value
+    let mut p = d.data + block as usize * d.block_size;
+    let mut buffer = read_u64_be(data, p);
+    p += 8;
+    let mut buffer_size = 64;
+    let mut symbol;
+    loop {
+        let mut len = 0;
+        while len + 1 < d.base64.len() && buffer < d.base64[len] {
+            len += 1;
+        }
+        symbol = (buffer.wrapping_sub(d.base64[len]) >> (64 - len - d.min_sym_len)) as usize;
+        symbol += read_u16_le(data, d.lowest_sym + 2 * len) as usize;
+        let count = d.symlen.get(symbol).copied().unwrap_or(0) as i64 + 1;
+        if offset < count {
+            break;
+        }
+        offset -= count;
+        let len = len + d.min_sym_len;
+        buffer <<= len;
+        buffer_size -= len;
+        if buffer_size <= 32 {
+            buffer_size += 32;
+            buffer |= (read_u32_be(data, p) as u64) << (64 - buffer_size);
+            p += 4;
+        }
+    }
+
+    // expand the symbol into its pairs until we reach a single value
+    while d.symlen.get(symbol).is_some_and(|len| *len != 0) {
+        let (left, right) = btree(d, data, symbol);
+        let left_count = d.symlen.get(left).copied().unwrap_or(0) as i64 + 1;
+        if offset < left_count {
+            symbol = left;
+        } else {
+            offset -= left_count;
+            symbol = right;
+        }
+    }
+    btree(d, data, symbol).0 as i32
+}
+
+/// Computes the index of the position in its table and reads the value there.
+/// Squares are numbered like the tables do: a1 = 0, h1 = 7, h8 = 63.
+fn encode_and_probe(
+    board: &Board,
+    turn: bool,
+    material: &Material,
+    table: &Table,
+    kind: TableKind,
+    wdl: Wdl,
+    black_stronger: bool,
+) -> Option<(i32, ProbeState)> {
+    let encoding = encoding();
+    let square_of = |bit: u32| (bit ^ 56) as usize;
+    let rank = |square: usize| square >> 3;
+    let file_of = |square: usize| square & 7;
+    let off_diagonal = |square: usize| rank(square) as i32 - file_of(square) as i32;
+
+    // tables are made with white as the stronger side, and symmetric tables only
+    // have white to move, else we swap the colors and flip the board
+    let flip = (material.symmetric && !turn) || black_stronger;
+    let flip_color = if flip { 8 } else { 0 };
+    let flip_squares = if flip { 56 } else { 0 };
+    let stm = flip as usize ^ !turn as usize;
+
+    let mut squares = [0usize; MAX_PIECES];
+    let mut pieces = [0u8; MAX_PIECES];
+    let mut size = 0;
+    let mut lead_pawns_count = 0;
+    let mut lead_pawns = 0;
+    let mut file = 0;
+
+    // the pawns of the leading color come first, the leading pawn is the one with
+    // the highest MapPawns value: nearest to the edge and with the lowest rank
+    if material.has_pawns {
+        let lead_color = table.items[0][0].pieces[0] ^ flip_color;
+        let pawn = if lead_color < 8 {
+            PieceType::WhitePawn
+        } else {
+            PieceType::BlackPawn
+        };
+        lead_pawns = board.get_pieces(pawn);
+        let mut bitboard = lead_pawns;
+        while bitboard != 0 {
+            squares[size] = square_of(bitboard.trailing_zeros()) ^ flip_squares;
+            size += 1;
+            bitboard &= bitboard - 1;
+        }
+        lead_pawns_count = size;
+        let lead = (0..lead_pawns_count).max_by_key(|i| encoding.map_pawns[squares[*i]])?;
+        squares.swap(0, lead);
+        file = file_of(squares[0]);
+        if file > 3 {
+            file = file_of(squares[0] ^ 7);
+        }
+    }
+
+    // DTZ tables only store one side to move
+    if kind == TableKind::Dtz {
+        let flags = table.items[0][file].flags;
+        // symmetric tables without pawns are the same for both sides
+        let both_sides = material.symmetric && !material.has_pawns;
+        if !both_sides && (flags & FLAG_STM) as usize != stm {
+            return Some((0, ProbeState::ChangeStm));
+        }
+    }
+
+    for piece in PieceType::ALL {
+        let mut bitboard = board.get_pieces(piece) & !lead_pawns;
+        while bitboard != 0 {
+            squares[size] = square_of(bitboard.trailing_zeros()) ^ flip_squares;
+            pieces[size] = piece_code(piece) ^ flip_color;
+            size += 1;
+            bitboard &= bitboard - 1;
+        }
+    }
+    let d = &table.items[if kind == TableKind::Wdl { stm } else { 0 }][file];
+
+    // put the pieces in the order of the table
+    for i in lead_pawns_count..size.saturating_sub(1) {
+        if let Some(j) = (i..size).find(|j| pieces[*j] == d.pieces[i]) {
+            pieces.swap(i, j);
+            squares.swap(i, j);
+        }
+    }
+
+    // mirror so the leading piece is on files a-d
+    if file_of(squares[0]) > 3 {
+        for square in &mut squares[..size] {
+            *square ^= 7;
+        }
+    }
+
+    let mut idx: u64;
+    if material.has_pawns {
+        idx = encoding.lead_pawn_idx[lead_pawns_count][squares[0]];
+        squares[1..lead_pawns_count].sort_by_key(|s| encoding.map_pawns[*s]);
+        for (i, square) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+            idx += encoding.binomial[i][encoding.map_pawns[*square]];
+        }
+    } else {
+        // without pawns the leading piece is also mirrored below the 5th rank
+        if rank(squares[0]) > 3 {
+            for square in &mut squares[..size] {
+                *square ^= 56;
+            }
+        }
+        // and the first piece of the leading group that isn't on the a1-h8 diagonal below it
+        for i in 0..d.group_len[0] {
+            if off_diagonal(squares[i]) == 0 {
+                continue;
+            }
+            if off_diagonal(squares[i]) > 0 {
+                for square in &mut squares[i..size] {
+                    *square = ((*square >> 3) | (*square << 3)) & 63;
+                }
+            }
+            break;
+        }
+
+        if material.has_unique_pieces {
+            // the three leading pieces are encoded together
+            let s = &squares;
+            let adjust1 = (s[1] > s[0]) as usize;
+            let adjust2 = (s[2] > s[0]) as usize + (s[2] > s[1]) as usize;
+            idx = if off_diagonal(s[0]) != 0 {
+                (encoding.map_a1d1d4[s[0]] * 63 + (s[1] - adjust1)) * 62 + s[2] - adjust2
+            } else if off_diagonal(s[1]) != 0 {
+                (6 * 63 + rank(s[0]) * 28 + encoding.map_b1h1h7[s[1]]) * 62 + s[2] - adjust2
+            } else if off_diagonal(s[2]) != 0 {
+                6 * 63 * 62
+                    + 4 * 28 * 62
+                    + rank(s[0]) * 7 * 28
+                    + (rank(s[1]) - adjust1) * 28
+                    + encoding.map_b1h1h7[s[2]]
+            } else {
+                6 * 63 * 62
+                    + 4 * 28 * 62
+                    + 4 * 7 * 28
+                    + rank(s[0]) * 7 * 6
+                    + (rank(s[1]) - adjust1) * 6
+                    + (rank(s[2]) - adjust2)
+            } as u64;
+        } else {
+            // only the kings are encoded together
+            idx = encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]] as u64;
+        }
+    }
+
+    // the other groups, sorted by square, skipping the squares of the groups before
+    idx *= d.group_idx[0];
+    let mut start = d.group_len[0];
+    let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+    let mut next = 1;
+    while d.group_len[next] != 0 {
+        let len = d.group_len[next];
+        squares[start..start + len].sort_unstable();
+        let mut n = 0;
+        for i in 0..len {
+            let square = squares[start + i];
+            let adjust = squares[..start].iter().filter(|s| square > **s).count();
+            let free = square - adjust - if remaining_pawns { 8 } else { 0 };
+            n += encoding.binomial[i + 1][free];
+        }
+        remaining_pawns = false;
+        idx += n * d.group_idx[next];
+        start += len;
+        next += 1;
+    }
+
+    let value = decompress_pairs(d, &table.data, idx);
+    match kind {
+        TableKind::Wdl => Some((value, ProbeState::Ok)),
+        TableKind::Dtz => Some((map_dtz(table, file, value, wdl), ProbeState::Ok)),
+    }
+}
+
+/// Turns a stored DTZ value into plies
+fn map_dtz(table: &Table, file: usize, mut value: i32, wdl: Wdl) -> i32 {
+    let d = &table.items[0][file];
+    if d.flags & FLAG_MAPPED != 0 {
+        let map = match wdl {
+            Wdl::Win => 0,
+            Wdl::Loss => 1,
+            Wdl::CursedWin => 2,
+            _ => 3,
+        };
+        value = if d.flags & FLAG_WIDE != 0 {
+            read_u16_le(&table.data, d.map_idx[map] + 2 * value as usize) as i32
+        } else {
+            table
+                .data
+                .get(d.map_idx[map] + value as usize)
+                .copied()
+                .unwrap_or(0) as i32
+        };
+    }
+    // some tables store moves instead of plies
+    let in_moves = match wdl {
+        Wdl::Win => d.flags & FLAG_WIN_PLIES == 0,
+        Wdl::Loss => d.flags & FLAG_LOSS_PLIES == 0,
+        _ => true,
+    };
+    if in_moves {
+        value *= 2;
+    }
+    value + 1
+}
+
+fn read_bytes<const N: usize>(data: &[u8], p: usize) -> [u8; N] {
+    // a broken file gives garbage instead of a crash
+    data.get(p..p + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .unwrap_or([0; N])
+}
+
+fn read_u16_le(data: &[u8], p: usize) -> u16 {
+    u16::from_le_bytes(read_bytes(data, p))
+}
+
+fn read_u32_le(data: &[u8], p: usize) -> u32 {
+    u32::from_le_bytes(read_bytes(data, p))
+}
+
+fn read_u32_be(data: &[u8], p: usize) -> u32 {
+    u32::from_be_bytes(read_bytes(data, p))
+}
+
+fn read_u64_be(data: &[u8], p: usize) -> u64 {
+    u64::from_be_bytes(read_bytes(data, p))
+}
+
+/// Lookup tables of the index calculation, squares are numbered a1 = 0 .. h8 = 63
+struct Encoding {
+    /// a2-h7 to 0..47, the pawn with the highest value leads
+    map_pawns: [usize; 64],
+    /// squares below the a1-h8 diagonal to 0..27
+    map_b1h1h7: [usize; 64],
+    /// the a1-d1-d4 triangle to 0..9, the diagonal last
+    map_a1d1d4: [usize; 64],
+    /// the 462 legal placements of two kings with the first in the a1-d1-d4 triangle
+    map_kk: [[usize; 64]; 10],
+    /// binomial[k][n], the ways to choose k of n
+    binomial: [[u64; 64]; MAX_PIECES],
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+}
+
+fn encoding() -> &'static Encoding {
+    static ENCODING: OnceLock<Encoding> = OnceLock::new();
+    ENCODING.get_or_init(Encoding::new)
+}
+
+impl Encoding {
+    fn new() -> Encoding {
+        let mut e = Encoding {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; MAX_PIECES],
+            lead_pawn_idx: [[0; 64]; MAX_PIECES],
+            lead_pawns_size: [[0; 4]; MAX_PIECES],
+        };
+        let off_diagonal = |s: usize| (s >> 3) as i32 - (s & 7) as i32;
+
+        let mut code = 0;
+        for s in 0..64 {
+            if off_diagonal(s) < 0 {
+                e.map_b1h1h7[s] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = Vec::new();
+        code = 0;
+        for s in 0..=27 {
+            if off_diagonal(s) < 0 && s & 7 <= 3 {
+                e.map_a1d1d4[s] = code;
+                code += 1;
+            } else if off_diagonal(s) == 0 && s & 7 <= 3 {
+                diagonal.push(s);
+            }
+        }
+        for s in diagonal {
+            e.map_a1d1d4[s] = code;
+            code += 1;
+        }
+
+        // when the first king is on the diagonal the second may not be above it,
+        // placements with both kings on the diagonal come last
+        let mut both_on_diagonal = Vec::new();
+        code = 0;
+        for idx in 0..10 {
+            for s1 in 0..=27 {
+                if e.map_a1d1d4[s1] != idx || (idx == 0 && s1 != 1) {
+                    continue;
+                }
+                for s2 in 0..64 {
+                    let touching =
+                        (s1 & 7).abs_diff(s2 & 7) <= 1 && (s1 >> 3).abs_diff(s2 >> 3) <= 1;
+                    if touching || (off_diagonal(s1) == 0 && off_diagonal(s2) > 0) {
+                        continue;
+                    }
+                    if off_diagonal(s1) == 0 && off_diagonal(s2) == 0 {
+                        both_on_diagonal.push((idx, s2));
+                    } else {
+                        e.map_kk[idx][s2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, s2) in both_on_diagonal {
+            e.map_kk[idx][s2] = code;
+            code += 1;
+        }
+
+        e.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                e.binomial[k][n] = if k > 0 { e.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { e.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // the available squares for the other pawns when the leading pawn is on a square:
+        // 47 on a2 and two less for every rank further, because of the mirroring
+        let mut available: usize = 48;
+        for lead_pawns in 1..MAX_PIECES {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        available -= 2;
+                        e.map_pawns[square] = available + 1;
+                        e.map_pawns[square ^ 7] = available;
+                    }
+                    e.lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += e.binomial[lead_pawns - 1][e.map_pawns[square]];
+                }
+                e.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+        e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::game::Game;
+
+    #[test]
+    fn binomial_table() {
+        let e = encoding();
+        for n in 0..64u64 {
+            let mut expected = 1;
+            for k in 0..MAX_PIECES as u64 {
+                assert_eq!(
+                    e.binomial[k as usize][n as usize], expected,
+                    "{} of {}",
+                    k, n
+                );
+                // C(n, k + 1) = C(n, k) * (n - k) / (k + 1)
+                expected = expected * n.saturating_sub(k) / (k + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn king_maps() {
+        let e = encoding();
+        let squares = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| {
+                    let name = name.as_bytes();
+                    (name[0] - b'a') as usize + (name[1] - b'1') as usize * 8
+                })
+                .collect::<Vec<_>>()
+        };
+        // the triangle below the diagonal first, then the diagonal
+        let triangle = squares(&["b1", "c1", "d1", "c2", "d2", "d3", "a1", "b2", "c3", "d4"]);
+        for (code, square) in triangle.into_iter().enumerate() {
+            assert_eq!(e.map_a1d1d4[square], code);
+        }
+        let mut codes: Vec<usize> = e.map_kk.iter().flatten().copied().collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), 462);
+        assert_eq!(codes.last(), Some(&461));
+    }
+
+    #[test]
+    fn pawn_file_encoding() {
+        let e = encoding();
+        // a2-h7 are numbered 0..47 and a2, the first pawn to lead, has the highest number
+        let mut codes: Vec<usize> = (8..56).map(|square| e.map_pawns[square]).collect();
+        codes.sort_unstable();
+        assert_eq!(codes, (0..48).collect::<Vec<_>>());
+        assert_eq!(e.map_pawns[8], 47);
+        for square in 8..56 {
+            // a file and its mirror are next to each other, ex: a2 and h2
+            assert_eq!(e.map_pawns[square].abs_diff(e.map_pawns[square ^ 7]), 1);
+        }
+        // a single leading pawn can be on any of the 6 squares of its file
+        for file in 0..4 {
+            assert_eq!(e.lead_pawns_size[1][file], 6);
+            for rank in 1..7 {
+                assert_eq!(e.lead_pawn_idx[1][rank * 8 + file], rank as u64 - 1);
+            }
+        }
+        // with two leading pawns the other one is on a square with a lower number
+        for file in 0..4 {
+            let expected: u64 = (1..7).map(|rank| e.map_pawns[rank * 8 + file] as u64).sum();
+            assert_eq!(e.lead_pawns_size[2][file], expected);
+        }
+    }
+
+    #[test]
+    fn material_from_file_name() {
+        let material = Material::parse("KRPvKR").unwrap();
+        assert_eq!(
+            (material.white.as_str(), material.black.as_str()),
+            ("KRP", "KR")
+        );
+        assert_eq!(material.piece_count, 5);
+        assert!(material.has_pawns && material.has_unique_pieces && !material.symmetric);
+        // black has no pawns, white leads
+        assert_eq!(material.pawn_count, [1, 0]);
+        let material = Material::parse("KPPvKP").unwrap();
+        assert_eq!(material.pawn_count, [1, 2]);
+        assert!(Material::parse("KRvKR").unwrap().symmetric);
+        for name in ["KRKvK", "RvK", "KXvK", "KQQQQvKQQ", "KRK"] {
+            assert!(Material::parse(name).is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn single_value_table() {
+        // KRvK isn't symmetric so it has a sub table per side to move, both hold a
+        // single value: white to move wins and black to move loses
+        let mut file = WDL_MAGIC.to_vec();
+        file.push(1);
+        // the order of the groups, the pieces for both sides (kings and rook) and padding
+        file.extend([0x00, 0x66, 0xEE, 0x44, 0]);
+        file.extend([FLAG_SINGLE_VALUE, (Wdl::Win as i32 + 2) as u8]);
+        file.extend([FLAG_SINGLE_VALUE, (Wdl::Loss as i32 + 2) as u8]);
+        file.resize(64, 0);
+        let directory =
+            std::env::temp_dir().join(format!("chess_bot_syzygy_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("KRvK.rtbw"), &file).unwrap();
+        let tablebases = Tablebases::open(directory.to_str().unwrap());
+        assert_eq!((tablebases.len(), tablebases.max_pieces()), (1, 3));
+
+        for (fen, wdl) in [
+            ("k7/8/2K5/8/8/8/8/7R w - - 0 1", Some(Wdl::Win)),
+            ("k7/8/2K5/8/8/8/8/7R b - - 0 1", Some(Wdl::Loss)),
+            // black has the rook, the colors are swapped
+            ("K7/8/2k5/8/8/8/8/7r b - - 0 1", Some(Wdl::Win)),
+            ("K7/8/2k5/8/8/8/8/7r w - - 0 1", Some(Wdl::Loss)),
+            // taking the rook draws, whatever the table says
+            ("8/8/8/8/8/8/1k6/R5K1 b - - 0 1", Some(Wdl::Draw)),
+            ("k7/8/1K6/8/8/8/8/7Q w - - 0 1", None),
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(tablebases.probe_wdl(&game.board, game.turn), wdl, "{}", fen);
+        }
+        drop(tablebases);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn huffman_decoding() {
+        // symbols 0 and 1 are the values 2 and 4, symbol 2 is the pair of them. '1' is
+        // symbol 2, '00' and '01' are symbols 0 and 1.
+        let mut data = vec![0, 5, 3, 0, 1, 0, 0, 0, 2, 1];
+        data.extend([2, 0, 0, 0]);
+        data.extend([3, 0]);
+        data.extend([2, 0xF0, 0xFF, 4, 0xF0, 0xFF, 0, 0x10, 0x00, 0]);
+        let mut d = PairsData::default();
+        // a table of 8 values
+        d.group_idx[0] = 8;
+        assert_eq!(set_sizes(&mut d, &data, 0), Some(data.len()));
+        assert_eq!(d.symlen, [0, 0, 1]);
+
+        // one block of 8 values, the sparse index points into its middle
+        d.sparse_index = data.len();
+        data.extend([0, 0, 0, 0, 4, 0]);
+        d.block_length = data.len();
+        data.extend([7, 0]);
+        d.data = 64;
+        data.resize(64, 0);
+        // 1 1 01 00 1
+        data.push(0b1101_0010);
+        data.resize(64 + d.block_size, 0);
+        let values: Vec<i32> = (0..8).map(|idx| decompress_pairs(&d, &data, idx)).collect();
+        assert_eq!(values, [2, 4, 2, 4, 4, 2, 2, 4]);
+    }
+
+    /// Needs the 3 and 4 piece tables, ex: `SYZYGY_PATH=/path/to/syzygy cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn probes_known_positions() {
+        let path = std::env::var("SYZYGY_PATH").expect("SYZYGY_PATH is not set");
+        let tablebases = Tablebases::open(&path);
+        assert!(tablebases.max_pieces() >= 4);
+        for (fen, wdl, dtz) in [
+            // the king in front of its pawn on the 6th rank always wins
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win, None),
+            // Rh8 mates at once
+            ("k7/8/2K5/8/8/8/8/7R w - - 0 1", Wdl::Win, Some(1)),
+            ("k7/8/2K5/8/8/8/8/7R b - - 0 1", Wdl::Loss, None),
+            ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", Wdl::Draw, Some(0)),
+        ] {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(
+                tablebases.probe_wdl(&game.board, game.turn),
+                Some(wdl),
+                "{}",
+                fen
+            );
+            let probed = tablebases.probe_dtz(&game.board, game.turn).unwrap();
+            assert_eq!(probed.signum(), wdl.signum(), "{}", fen);
+            if let Some(dtz) = dtz {
+                assert_eq!(probed, dtz, "{}", fen);
+            }
+        }
+    }
+}
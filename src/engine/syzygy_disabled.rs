@@ -0,0 +1,72 @@
+// Purpose: Takes the place of syzygy.rs when the crate is built without the `syzygy` feature.
+//
+// The probing code is GPL-3 licensed, see syzygy.rs. Without it no tables are ever found,
+// so the engine searches every position itself.
+
+use crate::core::{board::Board, Move};
+
+/// Result of a position with perfect play, from the point of view of the side to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+/// A move of the root with how good it is according to the tablebases
+#[derive(Clone, Copy, Debug)]
+pub struct RootMove {
+    pub r#move: Move,
+    pub dtz: i32,
+    pub rank: i32,
+}
+
+/// Never holds any tables
+#[derive(Default)]
+pub struct Tablebases;
+
+impl Tablebases {
+    pub fn new() -> Tablebases {
+        Tablebases
+    }
+
+    pub fn open(_paths: &str) -> Tablebases {
+        Tablebases
+    }
+
+    pub fn len(&self) -> usize {
+        0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        true
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        0
+    }
+
+    pub fn can_probe(&self, _board: &Board) -> bool {
+        false
+    }
+
+    pub fn probe_wdl(&self, _board: &Board, _turn: bool) -> Option<Wdl> {
+        None
+    }
+
+    pub fn probe_dtz(&self, _board: &Board, _turn: bool) -> Option<i32> {
+        None
+    }
+
+    pub fn rank_root_moves(
+        &self,
+        _board: &Board,
+        _turn: bool,
+        _halfmove_clock: u32,
+        _repeated: bool,
+    ) -> Option<Vec<RootMove>> {
+        None
+    }
+}
@@ -0,0 +1,252 @@
+// Purpose: Decides how long the engine may think about a move under a clock.
+
+use std::time::{Duration, Instant};
+
+use crate::core::Move;
+
+/// Time kept in reserve for the communication with the GUI, so we never flag
+pub const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+/// Moves we expect still have to be played when the time control doesn't say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+/// Never plan for more than this many moves, so we keep using our time
+const MAX_MOVES_TO_GO: u32 = 50;
+/// Score drop in centipawns between iterations that counts as a fail low
+const FAIL_LOW_MARGIN: i32 = 30;
+
+/// The clock information for the side to move, as given by UCI `go` or the GUI
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeControl {
+    /// remaining time on our clock, None when we are not playing under a clock
+    pub time: Option<Duration>,
+    /// time added to our clock after every move
+    pub increment: Duration,
+    /// moves until the next time control, None for sudden death
+    pub moves_to_go: Option<u32>,
+    /// think exactly this long, overrides the clock
+    pub move_time: Option<Duration>,
+}
+
+impl TimeControl {
+    pub fn infinite() -> TimeControl {
+        TimeControl::default()
+    }
+
+    pub fn move_time(move_time: Duration) -> TimeControl {
+        TimeControl {
+            move_time: Some(move_time),
+            ..Default::default()
+        }
+    }
+}
+
+/// Tracks the time of a single search.
+///
+/// The soft limit is checked between iterations of the iterative deepening and is
+/// scaled by how stable the search looks. The hard limit is checked inside the
+/// search and is never exceeded, whatever the search looks like.
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+    single_legal_move: bool,
+    last_best_move: Option<Move>,
+    last_score: Option<i32>,
+    /// amount of iterations in a row that returned the same best move
+    best_move_stability: u32,
+    /// multiplier for the soft limit, grows when the search is unstable
+    scale: f64,
+}
+
+impl TimeManager {
+    /// Starts the clock for a search, `legal_moves` is the amount of legal moves in the root
+    pub fn new(control: &TimeControl, legal_moves: usize) -> TimeManager {
+        let (soft_limit, hard_limit) = allocate(control);
+        TimeManager {
+            start: Instant::now(),
+            soft_limit,
+            hard_limit,
+            single_legal_move: legal_moves == 1,
+            last_best_move: None,
+            last_score: None,
+            best_move_stability: 0,
+            scale: 1.0,
+        }
+    }
+
+    /// A time manager that never stops the search
+    pub fn infinite() -> TimeManager {
+        TimeManager::new(&TimeControl::infinite(), 0)
+    }
+
+    /// Starts counting the time again, for a ponder search that became a normal search
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn soft_limit(&self) -> Option<Duration> {
+        self.soft_limit.map(|limit| limit.mul_f64(self.scale))
+    }
+
+    pub fn hard_limit(&self) -> Option<Duration> {
+        self.hard_limit
+    }
+
+    /// Feeds the result of a finished iteration, which adjusts the soft limit
+    pub fn on_iteration(&mut self, best_move: Move, score: i32) {
+        if self.last_best_move == Some(best_move) {
+            self.best_move_stability += 1;
+        } else {
+            self.best_move_stability = 0;
+        }
+        // a stable best move lets us stop early, a changing one asks for more time
+        let mut scale = match self.best_move_stability {
+            0 => 1.3,
+            1 => 1.1,
+            2 => 1.0,
+            3 => 0.8,
+            _ => 0.6,
+        };
+        if let Some(last_score) = self.last_score {
+            if score < last_score - FAIL_LOW_MARGIN {
+                scale *= 1.5;
+            }
+        }
+        self.scale = scale;
+        self.last_best_move = Some(best_move);
+        self.last_score = Some(score);
+    }
+
+    /// Should we stop before starting the next iteration
+    pub fn should_stop_soft(&self) -> bool {
+        if self.single_legal_move && self.last_best_move.is_some() {
+            return true;
+        }
+        match self.soft_limit() {
+            Some(limit) => self.elapsed() >= limit,
+            None => false,
+        }
+    }
+
+    /// Should we abort the iteration that is running now
+    pub fn should_stop_hard(&self) -> bool {
+        match self.hard_limit {
+            Some(limit) => self.elapsed() >= limit,
+            None => false,
+        }
+    }
+}
+
+/// Splits the remaining time into a soft and a hard limit for this move
+fn allocate(control: &TimeControl) -> (Option<Duration>, Option<Duration>) {
+    if let Some(move_time) = control.move_time {
+        let limit = move_time
+            .saturating_sub(MOVE_OVERHEAD)
+            .max(Duration::from_millis(1));
+        return (Some(limit), Some(limit));
+    }
+    let time = match control.time {
+        Some(time) => time,
+        None => return (None, None),
+    };
+    let available = time.saturating_sub(MOVE_OVERHEAD);
+    let moves_to_go = control
+        .moves_to_go
+        .unwrap_or(DEFAULT_MOVES_TO_GO)
+        .clamp(1, MAX_MOVES_TO_GO);
+
+    let soft = available / moves_to_go + control.increment.mul_f64(0.75);
+    // with more moves to come we must keep some time in the bank
+    let max_share = if moves_to_go == 1 { 0.9 } else { 0.5 };
+    let hard = (soft * 4).min(available.mul_f64(max_share));
+    let soft = soft.min(hard);
+    (Some(soft), Some(hard.max(Duration::from_millis(1))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(time_ms: u64, increment_ms: u64, moves_to_go: Option<u32>) -> TimeControl {
+        TimeControl {
+            time: Some(Duration::from_millis(time_ms)),
+            increment: Duration::from_millis(increment_ms),
+            moves_to_go,
+            move_time: None,
+        }
+    }
+
+    /// Both limits, checking the hard one never uses the time kept for the overhead. Only
+    /// the floor of 1 ms, so the search can find a move at all, may go over it.
+    fn limits(control: &TimeControl) -> (Duration, Duration) {
+        let (soft, hard) = allocate(control);
+        let (soft, hard) = (soft.unwrap(), hard.unwrap());
+        let available = control.time.unwrap().saturating_sub(MOVE_OVERHEAD);
+        assert!(
+            hard <= available.max(Duration::from_millis(1)),
+            "{:?}",
+            control
+        );
+        assert!(soft <= hard, "{:?}", control);
+        (soft, hard)
+    }
+
+    #[test]
+    fn no_clock_no_limits() {
+        assert_eq!(allocate(&TimeControl::infinite()), (None, None));
+    }
+
+    #[test]
+    fn move_time_keeps_the_overhead() {
+        let (soft, hard) = allocate(&TimeControl::move_time(Duration::from_secs(1)));
+        assert_eq!(soft, Some(Duration::from_millis(950)));
+        assert_eq!(hard, soft);
+        let (soft, hard) = allocate(&TimeControl::move_time(Duration::from_millis(10)));
+        assert_eq!(soft, Some(Duration::from_millis(1)));
+        assert_eq!(hard, soft);
+    }
+
+    #[test]
+    fn sudden_death() {
+        let (soft, hard) = limits(&clock(60_000, 0, None));
+        // the time left over the moves we expect to play, and at most 4 times that
+        assert_eq!(soft, Duration::from_millis(59_950) / DEFAULT_MOVES_TO_GO);
+        assert_eq!(hard, soft * 4);
+    }
+
+    #[test]
+    fn increment_adds_time() {
+        let (without, _) = limits(&clock(10_000, 0, None));
+        let (with, _) = limits(&clock(10_000, 1_000, None));
+        assert_eq!(with, without + Duration::from_millis(750));
+        // an increment bigger than the clock can't make us flag
+        limits(&clock(500, 2_000, None));
+    }
+
+    #[test]
+    fn last_move_before_the_time_control() {
+        let (soft, hard) = limits(&clock(10_000, 0, Some(1)));
+        assert_eq!(hard, Duration::from_millis(9_950).mul_f64(0.9));
+        assert_eq!(soft, hard);
+        // with more moves to go we keep half of the time
+        let (_, hard) = limits(&clock(10_000, 0, Some(2)));
+        assert_eq!(hard, Duration::from_millis(9_950) / 2);
+    }
+
+    #[test]
+    fn short_clocks() {
+        for time_ms in [0, 1, 10, 49, 50, 51, 100, 200, 1_000] {
+            for increment_ms in [0, 10, 100] {
+                for moves_to_go in [None, Some(1), Some(5), Some(100)] {
+                    limits(&clock(time_ms, increment_ms, moves_to_go));
+                }
+            }
+        }
+        // under the overhead only the floor is left
+        let (soft, hard) = limits(&clock(30, 0, None));
+        assert_eq!((soft, hard), (Duration::ZERO, Duration::from_millis(1)));
+    }
+}
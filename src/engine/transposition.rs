@@ -0,0 +1,152 @@
+// Purpose: Transposition table shared between the search threads.
+//
+// Every slot is two atomics, the key is stored xored with the data. A slot that
+// was torn by two threads writing at the same time no longer matches its key,
+// so readers never see a mix of two entries and no locks are needed.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::core::{Move, Promotion};
+
+pub const DEFAULT_HASH_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// the score is exact
+    Exact,
+    /// the score is at least this high, the search failed high
+    Lower,
+    /// the score is at most this high, the search failed low
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TtEntry {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+impl TtEntry {
+    /// Packs the entry in 64 bits: 16 bits move, 16 bits score, 8 bits depth, 2 bits bound
+    fn pack(&self) -> u64 {
+        let r#move = match self.best_move {
+            Some(m) => {
+                let from = (m.column_from + m.row_from * 8) as u64;
+                let to = (m.column_to + m.row_to * 8) as u64;
+                let promotion = match m.promotion {
+                    Some(promotion) => promotion as u64 + 1,
+                    None => 0,
+                };
+                1 << 15 | promotion << 12 | from << 6 | to
+            }
+            None => 0,
+        };
+        let score = self.score as i16 as u16 as u64;
+        let depth = self.depth.min(255) as u64;
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        r#move | score << 16 | depth << 32 | bound << 40
+    }
+
+    fn unpack(data: u64) -> TtEntry {
+        let best_move = if data & 1 << 15 != 0 {
+            let from = (data >> 6 & 63) as u32;
+            let to = (data & 63) as u32;
+            let r#move = Move::new((from % 8, from / 8), (to % 8, to / 8));
+            match data >> 12 & 7 {
+                0 => Some(r#move),
+                promotion => Some(r#move.with_promotion(Promotion::ALL[promotion as usize - 1])),
+            }
+        } else {
+            None
+        };
+        let bound = match data >> 40 & 3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+        TtEntry {
+            best_move,
+            score: (data >> 16 & 0xFFFF) as u16 as i16 as i32,
+            depth: (data >> 32 & 0xFF) as u32,
+            bound,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_SIZE)
+    }
+}
+
+impl TranspositionTable {
+    /// Creates a table of about `megabytes` MB
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let slots = (megabytes.max(1) << 20) / std::mem::size_of::<Slot>();
+        // round down to a power of two so the index is a mask
+        let slots = 1 << (usize::BITS - 1 - slots.leading_zeros());
+        TranspositionTable {
+            slots: (0..slots).map(|_| Slot::default()).collect(),
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[key as usize & (self.slots.len() - 1)]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        if data != 0 && slot.key.load(Ordering::Relaxed) ^ data == key {
+            Some(TtEntry::unpack(data))
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&self, key: u64, entry: TtEntry) {
+        let slot = self.slot(key);
+        // keep a deeper result of the same position unless we have an exact score
+        if let Some(existing) = self.probe(key) {
+            if existing.depth > entry.depth && entry.bound != Bound::Exact {
+                return;
+            }
+        }
+        let data = entry.pack();
+        slot.key.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    /// How full the table is in permille, estimated from the first thousand slots
+    pub fn hashfull(&self) -> u32 {
+        let sample = self.slots.len().min(1000);
+        let used = self.slots[..sample]
+            .iter()
+            .filter(|slot| slot.data.load(Ordering::Relaxed) != 0)
+            .count();
+        (used * 1000 / sample) as u32
+    }
+}
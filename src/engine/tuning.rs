@@ -0,0 +1,429 @@
+// Purpose: Texel tuning, fits the weights of the classical evaluation to the results of games.
+//
+// Every position of the dataset is labelled with the result of its game. The error of the
+// evaluation is the mean squared difference between those results and the score the
+// evaluation predicts, sigmoid(eval) = 1 / (1 + 10^(-k * eval / 400)). The classical
+// evaluation is a sum of weights times counts of things on the board, so the counts of every
+// position are computed once and the weights are fitted to them with gradient descent.
+//
+// The dataset has one position per line: a FEN, the move counters may be left out, with the
+// result of the game from white's point of view as "1-0", "0-1", "1/2-1/2" or a number, ex:
+//   rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1 [0.5]
+//   6k1/5ppp/8/8/8/8/r4PPP/6K1 w - - c9 "0-1";
+// The positions should be quiet, they are evaluated without a quiescence search.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::{board::Board, game::Game, PieceType};
+
+use super::eval_params::{BACKWARD_PAWN, DOUBLED_PAWN, ISOLATED_PAWN, PASSED_PAWN, PIECE_VALUES};
+use super::pawn_hash::evaluate_pawns;
+
+/// amount of weights of the classical evaluation
+pub const PARAMETERS: usize = 16;
+const PIECES: usize = 0;
+const DOUBLED: usize = 5;
+const ISOLATED: usize = 6;
+const BACKWARD: usize = 7;
+const PASSED: usize = 8;
+
+/// The weights of the classical evaluation in the order of `eval_params`
+pub type Weights = [f64; PARAMETERS];
+
+/// The weights the evaluation uses now
+pub fn current_weights() -> Weights {
+    let mut weights = [0.0; PARAMETERS];
+    for (i, value) in PIECE_VALUES.iter().enumerate() {
+        weights[PIECES + i] = *value as f64;
+    }
+    weights[DOUBLED] = DOUBLED_PAWN as f64;
+    weights[ISOLATED] = ISOLATED_PAWN as f64;
+    weights[BACKWARD] = BACKWARD_PAWN as f64;
+    for (i, value) in PASSED_PAWN.iter().enumerate() {
+        weights[PASSED + i] = *value as f64;
+    }
+    weights
+}
+
+/// The weights as the source of `eval_params.rs`, rounded to whole centipawns
+pub fn to_rust_source(weights: &Weights) -> String {
+    let list = |values: &[f64]| {
+        let values: Vec<String> = values
+            .iter()
+            .map(|v| format!("{}", v.round() as i32))
+            .collect();
+        values.join(", ")
+    };
+    format!(
+        "// Purpose: Weights of the classical evaluation, in centipawns.\n\
+         // The tuner writes this file, see `tuning` and `chess_bot tune`.\n\
+         \n\
+         /// value of a pawn, knight, bishop, rook and queen\n\
+         pub const PIECE_VALUES: [i32; 5] = [{}];\n\
+         pub const DOUBLED_PAWN: i32 = {};\n\
+         pub const ISOLATED_PAWN: i32 = {};\n\
+         pub const BACKWARD_PAWN: i32 = {};\n\
+         /// bonus for a passed pawn, indexed by the rank as seen from the pawn's own side\n\
+         pub const PASSED_PAWN: [i32; 8] = [{}];\n",
+        list(&weights[PIECES..PIECES + 5]),
+        weights[DOUBLED].round() as i32,
+        weights[ISOLATED].round() as i32,
+        weights[BACKWARD].round() as i32,
+        list(&weights[PASSED..PASSED + 8]),
+    )
+}
+
+/// How often every weight counts in the evaluation of the board, white's minus black's
+pub fn features(board: &Board) -> [i8; PARAMETERS] {
+    let mut counts = [0i8; PARAMETERS];
+    for piece in PieceType::ALL {
+        let kind = match piece {
+            PieceType::BlackPawn | PieceType::WhitePawn => 0,
+            PieceType::BlackKnight | PieceType::WhiteKnight => 1,
+            PieceType::BlackBishop | PieceType::WhiteBishop => 2,
+            PieceType::BlackRook | PieceType::WhiteRook => 3,
+            PieceType::BlackQueen | PieceType::WhiteQueen => 4,
+            PieceType::BlackKing | PieceType::WhiteKing => continue,
+        };
+        let sign = if piece.color() { 1 } else { -1 };
+        counts[PIECES + kind] += sign * board.get_pieces(piece).count_ones() as i8;
+    }
+
+    let entry = evaluate_pawns(board, 0);
+    for color in [true, false] {
+        let (pawns, sign) = if color {
+            (board.get_pieces(PieceType::WhitePawn), 1)
+        } else {
+            (board.get_pieces(PieceType::BlackPawn), -1)
+        };
+        counts[DOUBLED] += sign * (entry.doubled & pawns).count_ones() as i8;
+        counts[ISOLATED] += sign * (entry.isolated & pawns).count_ones() as i8;
+        counts[BACKWARD] += sign * (entry.backward & pawns).count_ones() as i8;
+        let mut passed = entry.passed & pawns;
+        while passed != 0 {
+            let row = passed.trailing_zeros() / 8;
+            passed &= passed - 1;
+            let relative_rank = if color { 7 - row } else { row };
+            counts[PASSED + relative_rank as usize] += sign;
+        }
+    }
+    counts
+}
+
+/// A position of the dataset
+struct Sample {
+    features: [i8; PARAMETERS],
+    /// 1 when white won, 0.5 for a draw and 0 when black won
+    result: f64,
+}
+
+pub struct Tuner {
+    samples: Vec<Sample>,
+    /// scales centipawns to the win chance, fitted to the dataset with `fit_k`
+    k: f64,
+    /// lines of the dataset that could not be read
+    skipped: usize,
+}
+
+impl Default for Tuner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tuner {
+    pub fn new() -> Tuner {
+        Tuner {
+            samples: Vec::new(),
+            k: 1.0,
+            skipped: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    pub fn k(&self) -> f64 {
+        self.k
+    }
+
+    /// Adds a position of the dataset, returns false when the line can't be read
+    pub fn add_line(&mut self, line: &str) -> bool {
+        match parse_line(line) {
+            Some((board, result)) => {
+                self.samples.push(Sample {
+                    features: features(&board),
+                    result,
+                });
+                true
+            }
+            None => {
+                self.skipped += 1;
+                false
+            }
+        }
+    }
+
+    /// Adds every position of a dataset file, empty lines are ignored
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let text = fs::read_to_string(path)?;
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            self.add_line(line);
+        }
+        Ok(())
+    }
+
+    /// The mean squared error of the evaluation with these weights
+    pub fn error(&self, weights: &Weights) -> f64 {
+        let sum: f64 = self.parallel(|samples| {
+            samples
+                .iter()
+                .map(|sample| (sample.result - self.sigmoid(evaluate(weights, sample))).powi(2))
+                .sum()
+        });
+        sum / self.samples.len().max(1) as f64
+    }
+
+    /// Picks the k for which these weights have the lowest error, so tuning doesn't
+    /// just scale all weights up or down
+    pub fn fit_k(&mut self, weights: &Weights) -> f64 {
+        let (mut low, mut high) = (0.0, 10.0);
+        for _ in 0..50 {
+            let (a, b) = (low + (high - low) / 3.0, high - (high - low) / 3.0);
+            self.k = a;
+            let error_a = self.error(weights);
+            self.k = b;
+            let error_b = self.error(weights);
+            if error_a < error_b {
+                high = b;
+            } else {
+                low = a;
+            }
+        }
+        self.k = (low + high) / 2.0;
+        self.k
+    }
+
+    /// Lowers the error with `iterations` steps of gradient descent (Adam) from `weights`.
+    /// `report` is called every 100 iterations with the iteration and the error.
+    pub fn tune(
+        &self,
+        weights: &Weights,
+        iterations: usize,
+        report: &mut dyn FnMut(usize, f64),
+    ) -> Weights {
+        const LEARNING_RATE: f64 = 1.0;
+        const BETA1: f64 = 0.9;
+        const BETA2: f64 = 0.999;
+        let mut weights = *weights;
+        let mut momentum = [0.0; PARAMETERS];
+        let mut velocity = [0.0; PARAMETERS];
+        for iteration in 1..=iterations {
+            let gradient = self.gradient(&weights);
+            for i in 0..PARAMETERS {
+                momentum[i] = BETA1 * momentum[i] + (1.0 - BETA1) * gradient[i];
+                velocity[i] = BETA2 * velocity[i] + (1.0 - BETA2) * gradient[i].powi(2);
+                let momentum = momentum[i] / (1.0 - BETA1.powi(iteration as i32));
+                let velocity = velocity[i] / (1.0 - BETA2.powi(iteration as i32));
+                weights[i] -= LEARNING_RATE * momentum / (velocity.sqrt() + 1e-8);
+            }
+            if iteration % 100 == 0 || iteration == iterations {
+                report(iteration, self.error(&weights));
+            }
+        }
+        weights
+    }
+
+    /// The gradient of the error to every weight
+    fn gradient(&self, weights: &Weights) -> Weights {
+        let scale = self.k * std::f64::consts::LN_10 / 400.0;
+        let gradient = self.parallel(|samples| {
+            let mut gradient = [0.0; PARAMETERS];
+            for sample in samples {
+                let predicted = self.sigmoid(evaluate(weights, sample));
+                let slope =
+                    -2.0 * (sample.result - predicted) * predicted * (1.0 - predicted) * scale;
+                for (g, feature) in gradient.iter_mut().zip(sample.features) {
+                    *g += slope * feature as f64;
+                }
+            }
+            GradientSum(gradient)
+        });
+        gradient.0.map(|g| g / self.samples.len().max(1) as f64)
+    }
+
+    fn sigmoid(&self, eval: f64) -> f64 {
+        1.0 / (1.0 + 10f64.powf(-self.k * eval / 400.0))
+    }
+
+    /// Splits the samples over all cores and adds up the results
+    fn parallel<T: std::iter::Sum<T> + Send>(&self, f: impl Fn(&[Sample]) -> T + Sync) -> T {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = self.samples.len().div_ceil(threads).max(1);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .samples
+                .chunks(chunk)
+                .map(|samples| scope.spawn(|| f(samples)))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        })
+    }
+}
+
+/// A gradient that can be summed over the threads
+struct GradientSum(Weights);
+
+impl std::iter::Sum for GradientSum {
+    fn sum<I: Iterator<Item = GradientSum>>(iter: I) -> GradientSum {
+        let mut total = [0.0; PARAMETERS];
+        for GradientSum(gradient) in iter {
+            for (t, g) in total.iter_mut().zip(gradient) {
+                *t += g;
+            }
+        }
+        GradientSum(total)
+    }
+}
+
+/// The classical evaluation of a sample from white's point of view
+fn evaluate(weights: &Weights, sample: &Sample) -> f64 {
+    weights
+        .iter()
+        .zip(sample.features)
+        .map(|(w, f)| w * f as f64)
+        .sum()
+}
+
+/// Reads the board and the result of a line of the dataset
+fn parse_line(line: &str) -> Option<(Board, f64)> {
+    let fen: Vec<&str> = line.split_whitespace().take(4).collect();
+    let game = Game::from_fen(&fen.join(" "))?;
+    let result = if line.contains("1/2-1/2") {
+        0.5
+    } else if line.contains("1-0") {
+        1.0
+    } else if line.contains("0-1") {
+        0.0
+    } else {
+        // the last number on the line, ex: "[0.5]"
+        let last = line.split_whitespace().last()?;
+        last.trim_matches(|c: char| !c.is_ascii_digit() && c != '.')
+            .parse::<f64>()
+            .ok()
+            .filter(|result| (0.0..=1.0).contains(result))?
+    };
+    Some((game.board, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::evaluation::material;
+
+    const POSITIONS: [&str; 4] = [
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+        "4k3/8/8/1p6/3P4/2P5/8/4K3 w - - 0 1",
+        "4k3/p4p2/p7/8/8/8/6P1/4K1N1 w - - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    ];
+
+    #[test]
+    fn features_match_the_evaluation() {
+        let weights = current_weights();
+        for fen in POSITIONS {
+            let board = Game::from_fen(fen).unwrap().board;
+            let sample = Sample {
+                features: features(&board),
+                result: 0.5,
+            };
+            let expected = material(&board) + evaluate_pawns(&board, 0).score;
+            assert_eq!(evaluate(&weights, &sample), expected as f64, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn current_weights_are_eval_params() {
+        assert_eq!(
+            to_rust_source(&current_weights()),
+            include_str!("eval_params.rs")
+        );
+    }
+
+    #[test]
+    fn reads_results() {
+        let fen = "6k1/5ppp/8/8/8/8/r4PPP/6K1 w - -";
+        for (suffix, result) in [
+            ("c9 \"0-1\";", 0.0),
+            ("1-0", 1.0),
+            ("[1/2-1/2]", 0.5),
+            ("0 1 [0.5]", 0.5),
+            ("[1.0]", 1.0),
+        ] {
+            let (_, read) = parse_line(&format!("{} {}", fen, suffix)).unwrap();
+            assert_eq!(read, result, "{}", suffix);
+        }
+        assert!(parse_line(&format!("{} [2.0]", fen)).is_none());
+        assert!(parse_line("not a position 1-0").is_none());
+    }
+
+    /// White wins when it has more material, knights are worth the most
+    fn tuner() -> Tuner {
+        let mut tuner = Tuner::new();
+        for (fen, result) in [
+            ("4k3/8/8/8/8/8/8/3NKN2 w - - 0 1", "1-0"),
+            ("3nkn2/8/8/8/8/8/8/4K3 w - - 0 1", "0-1"),
+            ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", "1-0"),
+            ("4kn2/8/8/8/8/8/8/4K3 w - - 0 1", "0-1"),
+            ("4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1", "1/2-1/2"),
+            ("4kn2/8/8/8/8/8/8/4KN2 w - - 0 1", "1/2-1/2"),
+        ] {
+            assert!(tuner.add_line(&format!("{} {}", fen, result)));
+        }
+        assert!(!tuner.add_line("garbage"));
+        assert_eq!((tuner.len(), tuner.skipped()), (6, 1));
+        tuner
+    }
+
+    #[test]
+    fn gradient_matches_the_error() {
+        let mut tuner = tuner();
+        let weights = current_weights();
+        tuner.fit_k(&weights);
+        let gradient = tuner.gradient(&weights);
+        for (i, expected) in gradient.iter().enumerate() {
+            let step = 1e-3;
+            let (mut up, mut down) = (weights, weights);
+            up[i] += step;
+            down[i] -= step;
+            let numeric = (tuner.error(&up) - tuner.error(&down)) / (2.0 * step);
+            assert!((numeric - expected).abs() < 1e-9, "weight {}", i);
+        }
+    }
+
+    #[test]
+    fn tuning_lowers_the_error() {
+        let mut tuner = tuner();
+        let mut weights = current_weights();
+        weights[PIECES + 1] = 0.0;
+        tuner.fit_k(&current_weights());
+        let before = tuner.error(&weights);
+        let mut reports = 0;
+        let tuned = tuner.tune(&weights, 200, &mut |_, _| reports += 1);
+        assert!(tuner.error(&tuned) < before / 2.0);
+        // a knight is worth something again
+        assert!(tuned[PIECES + 1] > 20.0);
+        assert_eq!(reports, 2);
+    }
+}
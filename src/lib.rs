@@ -0,0 +1,10 @@
+// Purpose: The bot as a library: the rules of chess, the engine, the text protocols and the
+// command line. These don't need a window or a GPU. The GUI is behind the `gui` feature,
+// which is on by default, build with `--no-default-features` for the library without macroquad.
+
+pub mod cli;
+pub mod core;
+pub mod engine;
+pub mod protocol;
+#[cfg(feature = "gui")]
+pub mod renderer;
@@ -1,12 +1,30 @@
-pub mod core;
-pub mod renderer;
-
-use crate::core::{board::Board, game::Game, utils::*, PieceType};
-use crate::renderer::{input_handler::InputHandler, renderer::Renderer, textures::Textures};
-use std::cmp::min;
+use chess_bot::cli;
+use chess_bot::protocol;
+#[cfg(feature = "gui")]
+use chess_bot::{
+    core::{
+        clock::{Clock, ClockMode, ClockSettings},
+        game::{Game, GameResult, Termination},
+        game_record::GameRecord,
+    },
+    engine::{
+        analysis::Analysis, bot_player::BotPlayer, search::SearchLimits, strength::Strength,
+        time_manager::TimeControl,
+    },
+    renderer::{
+        animation,
+        game_over::{self, GameOverAction},
+        renderer::Renderer,
+    },
+};
+use std::process::ExitCode;
+#[cfg(feature = "gui")]
+use std::time::Duration;
 
+#[cfg(feature = "gui")]
 use macroquad::prelude::*;
 
+#[cfg(feature = "gui")]
 fn window_conf() -> Conf {
     Conf {
         window_title: "Chess - Dyscalcuchess".to_owned(),
@@ -19,20 +37,374 @@ fn window_conf() -> Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    // let mut game = Game::new("RNBQKBNR/PPPPPPPP/8/8/8/8/pppppppp/rnbqkbnr w KQkq - 0 1");
-    // let mut game = Game::new("RNBQKBNR/P1P2P1P/3KP3/8/1Ppppp2/7P/pp4p1/rnbqkbnr w KQkq c3 0 1");
-    // let mut game =
-    // Game::new("rn1qkbnr/1p3pp1/p3p2p/2ppPb1P/3P2P1/2P5/PP1N1P2/R1BQKBNR b KQkq g3 0 8");
-    let mut game = Game::new("2R5/5p1k/p2Bp2P/1p2Pp2/1P1P4/P1p5/3bn3/6RK b - - 0 42");
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => ("play", &[][..]),
+    };
+    let result = match command {
+        "play" => play(args),
+        "uci" => {
+            protocol::uci::run();
+            Ok(())
+        }
+        "xboard" => {
+            protocol::xboard::run();
+            Ok(())
+        }
+        "analyse" | "analyze" => cli::analyse(args),
+        "perft" => cli::perft(args),
+        "bench" => cli::bench(args),
+        "match" => cli::play_match(args),
+        "book" => cli::make_book(args),
+        "tune" => cli::tune(args),
+        "selfplay" => cli::self_play(args),
+        "help" | "--help" | "-h" => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown command {}\n\n{}", command, cli::USAGE)),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// play [--fen <fen>] [--white human|bot] [--black human|bot]
+///     [--clock <minutes>+<seconds>] [--clock-mode fischer|bronstein|delay] [--animation <ms>]
+/// Opens the window to play in, from the starting position with two humans by default
+#[cfg(feature = "gui")]
+fn play(args: &[String]) -> Result<(), String> {
+    let mut game = Game::default();
+    // the sides the bot plays
+    let mut bots = Vec::new();
+    let mut clock = None;
+    let mut clock_mode = ClockMode::Fischer;
+    let mut animation = animation::DEFAULT_DURATION;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = iter.next().map(|v| v.as_str()).unwrap_or_default();
+        match (arg.as_str(), value) {
+            ("--fen", fen) => {
+                game = Game::from_fen(fen).ok_or_else(|| format!("invalid position {}", fen))?
+            }
+            ("--white", "bot") => bots.push(true),
+            ("--black", "bot") => bots.push(false),
+            ("--white" | "--black", "human") => {}
+            ("--clock", time_control) => clock = Some(time_control),
+            ("--clock-mode", "fischer") => clock_mode = ClockMode::Fischer,
+            ("--clock-mode", "bronstein") => clock_mode = ClockMode::Bronstein,
+            ("--clock-mode", "delay") => clock_mode = ClockMode::Delay,
+            ("--animation", ms) if ms.parse::<u64>().is_ok() => {
+                animation = Duration::from_millis(ms.parse().unwrap())
+            }
+            _ => {
+                return Err(
+                    "usage: chess_bot play [--fen <fen>] [--white human|bot] [--black human|bot] \
+                     [--clock <minutes>+<seconds>] [--clock-mode fischer|bronstein|delay] \
+                     [--animation <ms>]"
+                        .to_string(),
+                );
+            }
+        }
+    }
+    let clock = match clock {
+        Some(text) => Some(
+            ClockSettings::parse(text, clock_mode)
+                .ok_or_else(|| format!("cannot read time control {}", text))?,
+        ),
+        None => None,
+    };
+    macroquad::Window::from_config(window_conf(), gui(game, bots, clock, animation));
+    Ok(())
+}
+
+#[cfg(not(feature = "gui"))]
+fn play(_args: &[String]) -> Result<(), String> {
+    Err(format!(
+        "chess_bot was built without the gui feature\n\n{}",
+        cli::USAGE
+    ))
+}
+
+/// Lets the bot play the sides in `bot_sides`, humans play the others.
+/// Without a clock the bot thinks a second about every move.
+/// Pieces take `animation` to slide to their square.
+#[cfg(feature = "gui")]
+async fn gui(start: Game, bot_sides: Vec<bool>, clock: Option<ClockSettings>, animation: Duration) {
     let mut renderer = Renderer::new().await;
+    renderer.set_animation_duration(animation);
+    // the moves so far, the arrow keys and the move list go back and forth through them
+    let mut record = GameRecord::new(start.clone());
+    let clock_settings = clock;
+    let start_clock = |record: &GameRecord| {
+        clock_settings.map(|settings| {
+            let mut clock = Clock::new(settings);
+            clock.start(record.game().turn);
+            clock
+        })
+    };
+    let mut clock = start_clock(&record);
+    // the result and why the game ended, kept from the moment it ends
+    let mut ended: Option<(GameResult, Termination)> = None;
+    let mut pondering = false;
+    // arrows for the bot's expected reply and its threat, see H
+    let mut hints = false;
+    // the engine analysing the position on the board, see A
+    let mut analysis: Option<Analysis> = None;
+    // the levels L goes through, None is full strength
+    let levels = [None, Some(800), Some(1200), Some(1600), Some(2000)];
+    let mut level = 0;
+    let new_bot = |color: bool, pondering: bool, level: usize| {
+        let limits = SearchLimits {
+            time: TimeControl::move_time(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let mut bot = BotPlayer::new(color, limits);
+        bot.set_pondering(pondering);
+        bot.set_strength(levels[level].map(Strength::new));
+        bot
+    };
+    let mut bots: Vec<BotPlayer> = bot_sides
+        .into_iter()
+        .map(|color| new_bot(color, pondering, level))
+        .collect();
+    auto_flip(&mut renderer, &bots);
 
     loop {
-        if let Some(r#move) = renderer.user_inputs(&game) {
-            game.make_move(&r#move);
+        // B lets the bot play the side to move, or turns the bots off again
+        if is_key_pressed(KeyCode::B) {
+            if bots.is_empty() {
+                bots.push(new_bot(record.game().turn, pondering, level));
+                auto_flip(&mut renderer, &bots);
+            } else {
+                bots.clear();
+            }
+            renderer.show_status(if bots.is_empty() { "Bot off" } else { "Bot on" });
+        }
+        // F turns the board around
+        if is_key_pressed(KeyCode::F) {
+            renderer.set_flipped(!renderer.is_flipped());
+        }
+        // P lets the bot think on our time
+        if is_key_pressed(KeyCode::P) {
+            pondering = !pondering;
+            for bot in bots.iter_mut() {
+                bot.set_pondering(pondering);
+            }
+            renderer.show_status(if pondering {
+                "Pondering on"
+            } else {
+                "Pondering off"
+            });
+        }
+        // H shows the move the bot expects from us and what it threatens after it
+        if is_key_pressed(KeyCode::H) {
+            hints = !hints;
+            renderer.show_status(if hints { "Hints on" } else { "Hints off" });
+        }
+        // A shows what the engine thinks of the position on the board
+        if is_key_pressed(KeyCode::A) {
+            analysis = match analysis {
+                Some(_) => None,
+                None => Some(Analysis::new()),
+            };
+            renderer.show_status(if analysis.is_some() {
+                "Analysis on"
+            } else {
+                "Analysis off"
+            });
+        }
+        // L makes the bot play at the next level
+        if is_key_pressed(KeyCode::L) {
+            level = (level + 1) % levels.len();
+            for bot in bots.iter_mut() {
+                bot.set_strength(levels[level].map(Strength::new));
+            }
+            renderer.show_status(match levels[level] {
+                Some(elo) => format!("Level {} Elo", elo),
+                None => "Full strength".to_string(),
+            });
+        }
+
+        // Left and Right go a move back or forward, Up and Down to the start or the end
+        if is_key_pressed(KeyCode::Left) {
+            record.back();
+        }
+        if is_key_pressed(KeyCode::Right) {
+            record.forward();
+        }
+        if is_key_pressed(KeyCode::Up) {
+            record.go_to(0);
+        }
+        if is_key_pressed(KeyCode::Down) {
+            record.go_to(record.moves().len());
         }
-        renderer.render(&game);
+        if let Some(ply) = renderer.move_list_inputs(&record) {
+            record.go_to(ply);
+        }
+        renderer.annotation_inputs();
+
+        // the game is over by the rules or when a side runs out of time, both judged on the
+        // last position, so looking back through the game doesn't change how it ended
+        if ended.is_none() {
+            ended = record.end().or_else(|| {
+                clock
+                    .as_ref()
+                    .and_then(|clock| clock.timeout_result(record.last_position()))
+                    .map(|result| (result, Termination::Timeout))
+            });
+        }
+        // R resigns for the human, D offers a draw, a bot takes it when it isn't ahead
+        if ended.is_none() && is_key_pressed(KeyCode::R) {
+            let human = human_side(&bots, record.last_position().turn);
+            let winner = if human {
+                GameResult::BlackWins
+            } else {
+                GameResult::WhiteWins
+            };
+            ended = Some((winner, Termination::Resignation));
+        }
+        if ended.is_none() && is_key_pressed(KeyCode::D) {
+            let human = human_side(&bots, record.last_position().turn);
+            let accepted = bots
+                .iter()
+                .filter(|bot| bot.color() != human)
+                .all(|bot| bot.accepts_draw());
+            if accepted {
+                ended = Some((GameResult::Draw, Termination::Agreement));
+            }
+            renderer.show_status(if accepted {
+                "Draw agreed"
+            } else {
+                "Draw declined"
+            });
+        }
+        let result = ended;
+        if let (Some(_), Some(clock)) = (result, clock.as_mut()) {
+            clock.stop();
+        }
+
+        // the box with the result shows at the end of the game, not while looking back
+        let game_over = result.filter(|_| record.is_at_end());
+        if game_over.is_some() {
+            match renderer.game_over_inputs() {
+                Some(action @ (GameOverAction::Rematch | GameOverAction::NewGame)) => {
+                    let swap = action == GameOverAction::Rematch;
+                    bots = bots
+                        .iter()
+                        .map(|bot| new_bot(bot.color() != swap, pondering, level))
+                        .collect();
+                    if swap && bots.len() != 1 {
+                        renderer.set_flipped(!renderer.is_flipped());
+                    }
+                    auto_flip(&mut renderer, &bots);
+                    record = GameRecord::new(start.clone());
+                    clock = start_clock(&record);
+                    ended = None;
+                }
+                Some(GameOverAction::CopyPgn) => {
+                    let pgn =
+                        record.to_pgn(player_name(&bots, true), player_name(&bots, false), result);
+                    game_over::copy_to_clipboard(&pgn);
+                    renderer.show_status("PGN copied");
+                }
+                Some(GameOverAction::Analyse) => {
+                    analysis.get_or_insert_with(Analysis::new);
+                    record.go_to(0);
+                }
+                None => {}
+            }
+        }
+
+        // the bots wait while we look back through the game
+        let game = record.game();
+        let turn = game.turn;
+        // a human waiting for a bot can premove, those are played first on their turn
+        let human_waits = !bots.iter().any(|bot| bot.color() != turn);
+        let bot_to_move = bots
+            .iter_mut()
+            .find(|bot| bot.color() == turn)
+            .filter(|_| record.is_at_end());
+        let r#move = match bot_to_move {
+            // no moves once the game is over, also not from an earlier position
+            _ if result.is_some() => None,
+            Some(bot) => {
+                if human_waits {
+                    renderer.premove_inputs(game);
+                }
+                if let Some(clock) = &clock {
+                    bot.set_limits(clock_limits(clock, turn));
+                }
+                bot.update(game, record.history())
+            }
+            None if record.is_at_end() => renderer
+                .take_premove(game)
+                .or_else(|| renderer.user_inputs(game)),
+            None => renderer.user_inputs(game),
+        };
+        // a move from an earlier position throws away the moves after it
+        if let Some(r#move) = r#move {
+            record.play(r#move);
+            if let Some(clock) = clock.as_mut() {
+                clock.press(turn);
+            }
+        }
+        renderer.set_hint(
+            bots.iter()
+                .find_map(|bot| bot.hint(record.game()))
+                .filter(|_| hints),
+        );
+        if let Some(analysis) = analysis.as_mut() {
+            analysis.update(record.game(), record.history());
+        }
+        renderer.render(&record, clock.as_ref(), analysis.as_ref(), game_over);
         next_frame().await
     }
 }
+
+/// Turns the board so the human sees it from their own side, when one side is a human
+#[cfg(feature = "gui")]
+fn auto_flip(renderer: &mut Renderer, bots: &[BotPlayer]) {
+    if let [bot] = bots {
+        renderer.set_flipped(bot.color());
+    }
+}
+
+/// The side the human plays: the other side than the bot's, or the side to move
+#[cfg(feature = "gui")]
+fn human_side(bots: &[BotPlayer], turn: bool) -> bool {
+    match bots {
+        [bot] => !bot.color(),
+        _ => turn,
+    }
+}
+
+/// The name of the player of `color` in the PGN
+#[cfg(feature = "gui")]
+fn player_name(bots: &[BotPlayer], color: bool) -> &'static str {
+    if bots.iter().any(|bot| bot.color() == color) {
+        "chess_bot"
+    } else {
+        "Human"
+    }
+}
+
+/// The search limits of the bot playing `color` with the time left on its clock
+#[cfg(feature = "gui")]
+fn clock_limits(clock: &Clock, color: bool) -> SearchLimits {
+    SearchLimits {
+        time: TimeControl {
+            time: Some(clock.remaining(color)),
+            // a delay saves about as much time as an increment
+            increment: clock.settings().increment,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
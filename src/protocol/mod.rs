@@ -0,0 +1,39 @@
+/// src/protocol/mod.rs
+/// text protocols to let other programs talk to the engine
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+
+pub mod uci;
+pub mod xboard;
+
+/// Where a front-end writes its answers, the search thread writes through a clone
+#[derive(Clone)]
+enum Output {
+    Stdout,
+    /// keeps the lines so the tests can read them
+    #[cfg_attr(not(test), allow(dead_code))]
+    Buffer(Arc<Mutex<Vec<String>>>),
+}
+
+impl Output {
+    fn line(&self, text: impl Display) {
+        match self {
+            Output::Stdout => println!("{}", text),
+            Output::Buffer(lines) => lines.lock().unwrap().push(text.to_string()),
+        }
+    }
+
+    #[cfg(test)]
+    fn buffer() -> Output {
+        Output::Buffer(Arc::default())
+    }
+
+    /// The lines written since the last call
+    #[cfg(test)]
+    fn take(&self) -> Vec<String> {
+        match self {
+            Output::Stdout => Vec::new(),
+            Output::Buffer(lines) => std::mem::take(&mut *lines.lock().unwrap()),
+        }
+    }
+}
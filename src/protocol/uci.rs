@@ -0,0 +1,556 @@
+// Purpose: UCI front-end for the engine.
+// See https://backscattering.de/chess/uci/ for the protocol.
+
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::Output;
+use crate::core::{game::Game, Move};
+use crate::engine::{
+    book::{Book, BookSelection},
+    search::{SearchInfo, SearchLimits, MATE, MATE_BOUND},
+    strength::{Strength, MAX_ELO, MIN_ELO},
+    time_manager::TimeControl,
+    Engine,
+};
+
+/// UCI_Elo until the GUI sets it
+const DEFAULT_ELO: u32 = 1500;
+
+/// Reads UCI commands from stdin until `quit`
+pub fn run() {
+    let mut uci = Uci::new(Output::Stdout);
+    for line in std::io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !uci.handle_command(&line) {
+            break;
+        }
+    }
+    uci.stop_search();
+}
+
+struct Uci {
+    engine: Arc<Mutex<Engine>>,
+    out: Output,
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+    game: Game,
+    /// keys of the positions before the current one
+    history: Vec<u64>,
+    search: Option<JoinHandle<()>>,
+    /// the book of the BookFile option, only given to the engine with OwnBook
+    book: Option<Book>,
+    own_book: bool,
+    limit_strength: bool,
+    elo: u32,
+}
+
+impl Uci {
+    fn new(out: Output) -> Uci {
+        let engine = Engine::default();
+        Uci {
+            stop: engine.stop_handle(),
+            ponder: engine.ponder_handle(),
+            engine: Arc::new(Mutex::new(engine)),
+            out,
+            game: Game::default(),
+            history: Vec::new(),
+            search: None,
+            book: None,
+            own_book: false,
+            limit_strength: false,
+            elo: DEFAULT_ELO,
+        }
+    }
+
+    /// Handles one line of input, returns false when the engine has to quit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => {
+                self.out.line("id name Dyscalcuchess");
+                self.out.line("id author Teun Schuur");
+                self.out
+                    .line("option name Hash type spin default 16 min 1 max 4096");
+                self.out
+                    .line("option name Threads type spin default 1 min 1 max 256");
+                self.out
+                    .line("option name MultiPV type spin default 1 min 1 max 256");
+                self.out.line("option name Ponder type check default false");
+                self.out
+                    .line("option name SyzygyPath type string default <empty>");
+                self.out
+                    .line("option name EvalFile type string default <empty>");
+                self.out
+                    .line("option name Use NNUE type check default false");
+                self.out
+                    .line("option name UCI_LimitStrength type check default false");
+                self.out.line(format_args!(
+                    "option name UCI_Elo type spin default {} min {} max {}",
+                    DEFAULT_ELO, MIN_ELO, MAX_ELO
+                ));
+                self.out
+                    .line("option name OwnBook type check default false");
+                self.out
+                    .line("option name BookFile type string default <empty>");
+                self.out
+                    .line("option name BookDepth type spin default 0 min 0 max 1000");
+                self.out.line(
+                    "option name BookSelection type combo default Weighted var Weighted var Best",
+                );
+                self.out.line("uciok");
+            }
+            Some(&"isready") => self.out.line("readyok"),
+            Some(&"setoption") => {
+                self.stop_search();
+                self.set_option(&tokens[1..]);
+            }
+            Some(&"ucinewgame") => {
+                self.stop_search();
+                self.engine.lock().unwrap().new_game();
+                self.game = Game::default();
+                self.history.clear();
+            }
+            Some(&"position") => {
+                self.stop_search();
+                self.set_position(&tokens[1..]);
+            }
+            Some(&"go") => {
+                self.stop_search();
+                self.go(&tokens[1..]);
+            }
+            Some(&"ponderhit") => self.ponder.store(false, Ordering::Relaxed),
+            Some(&"stop") => self.stop_search(),
+            Some(&"quit") => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// setoption name <name> value <value>, anything else is ignored
+    fn set_option(&mut self, tokens: &[&str]) {
+        if tokens.first() != Some(&"name") {
+            return;
+        }
+        let value_index = tokens.iter().position(|t| *t == "value");
+        let Some(name) = tokens.get(1..value_index.unwrap_or(tokens.len())) else {
+            return;
+        };
+        let name = name.join(" ");
+        let value = value_index.map(|i| tokens[i + 1..].join(" "));
+        let name = name.to_lowercase();
+        if name.starts_with("book") || name == "ownbook" {
+            self.set_book_option(&name, value);
+            let book = self.book.clone().filter(|_| self.own_book);
+            self.engine.lock().unwrap().set_book(book);
+            return;
+        }
+        let mut engine = self.engine.lock().unwrap();
+        match (name.as_str(), value) {
+            ("threads", Some(value)) => {
+                if let Ok(threads) = value.parse() {
+                    engine.set_threads(threads);
+                }
+            }
+            ("multipv", Some(value)) => {
+                if let Ok(lines) = value.parse() {
+                    engine.set_multi_pv(lines);
+                }
+            }
+            ("syzygypath", Some(path)) => {
+                let path = if path == "<empty>" { "" } else { path.as_str() };
+                let tables = engine.set_syzygy_path(path);
+                if !path.is_empty() {
+                    self.out
+                        .line(format_args!("info string found {} tablebases", tables));
+                }
+            }
+            ("evalfile", Some(path)) if path.is_empty() || path == "<empty>" => {
+                engine.set_network(None)
+            }
+            ("evalfile", Some(path)) => match engine.load_network(&path) {
+                Ok(()) => self
+                    .out
+                    .line(format_args!("info string loaded network {}", path)),
+                Err(error) => self.out.line(format_args!(
+                    "info string cannot read network {}: {}",
+                    path, error
+                )),
+            },
+            ("use nnue", Some(value)) => {
+                engine.set_use_nnue(value == "true");
+                if engine.options().use_nnue && !engine.uses_nnue() {
+                    self.out
+                        .line("info string no network loaded, set EvalFile first");
+                }
+            }
+            ("uci_limitstrength", Some(value)) => {
+                self.limit_strength = value == "true";
+                engine.set_strength(self.limit_strength.then(|| Strength::new(self.elo)));
+            }
+            ("uci_elo", Some(value)) => {
+                if let Ok(elo) = value.parse() {
+                    self.elo = elo;
+                    engine.set_strength(self.limit_strength.then(|| Strength::new(self.elo)));
+                }
+            }
+            ("hash", Some(value)) => {
+                if let Ok(megabytes) = value.parse() {
+                    engine.set_hash_size(megabytes);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_book_option(&mut self, name: &str, value: Option<String>) {
+        match (name, value) {
+            ("ownbook", Some(value)) => self.own_book = value == "true",
+            ("bookfile", Some(path)) if path.is_empty() || path == "<empty>" => self.book = None,
+            ("bookfile", Some(path)) => {
+                let (selection, max_depth) = match &self.book {
+                    Some(book) => (book.selection(), book.max_depth()),
+                    None => (BookSelection::default(), None),
+                };
+                self.book = match Book::open(&path) {
+                    Ok(mut book) => {
+                        book.set_selection(selection);
+                        book.set_max_depth(max_depth);
+                        Some(book)
+                    }
+                    Err(error) => {
+                        self.out.line(format_args!(
+                            "info string cannot read book {}: {}",
+                            path, error
+                        ));
+                        None
+                    }
+                };
+            }
+            ("bookdepth", Some(value)) => {
+                if let (Some(book), Ok(depth)) = (&mut self.book, value.parse::<u32>()) {
+                    book.set_max_depth(if depth == 0 { None } else { Some(depth) });
+                }
+            }
+            ("bookselection", Some(value)) => {
+                if let Some(book) = &mut self.book {
+                    book.set_selection(if value.eq_ignore_ascii_case("best") {
+                        BookSelection::Best
+                    } else {
+                        BookSelection::Weighted
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// position [startpos | fen <fen>] [moves <move>...]
+    fn set_position(&mut self, tokens: &[&str]) {
+        let moves_index = tokens.iter().position(|t| *t == "moves");
+        let position = &tokens[..moves_index.unwrap_or(tokens.len())];
+        let game = match position {
+            ["fen", fen @ ..] => Game::from_fen(&fen.join(" ")),
+            _ => Some(Game::default()),
+        };
+        // a position we can't read is ignored, the GUI hears why
+        let Some(game) = game else {
+            self.out.line(format_args!(
+                "info string invalid position {}",
+                position.join(" ")
+            ));
+            return;
+        };
+        self.game = game;
+        self.history.clear();
+        if let Some(moves_index) = moves_index {
+            for notation in &tokens[moves_index + 1..] {
+                let legal = Move::from_algebraic_notation(notation)
+                    .ok()
+                    .filter(|r#move| {
+                        self.game
+                            .board
+                            .get_legal_moves(self.game.turn)
+                            .contains(r#move)
+                    });
+                let Some(r#move) = legal else {
+                    self.out
+                        .line(format_args!("info string illegal move {}", notation));
+                    break;
+                };
+                self.history
+                    .push(self.game.board.zobrist_key(self.game.turn));
+                self.game.make_move(&r#move);
+            }
+        }
+    }
+
+    /// go [wtime <ms>] [btime <ms>] [winc <ms>] [binc <ms>] [movestogo <n>]
+    ///    [movetime <ms>] [depth <n>] [nodes <n>] [infinite] [ponder]
+    fn go(&mut self, tokens: &[&str]) {
+        let (limits, ponder) = self.go_limits(tokens);
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponder.store(ponder, Ordering::Relaxed);
+        let engine = self.engine.clone();
+        let game = self.game.clone();
+        let history = self.history.clone();
+        let out = self.out.clone();
+        self.search = Some(std::thread::spawn(move || {
+            let mut engine = engine.lock().unwrap();
+            let result = engine.search(&game, &history, &limits, &mut |info| {
+                print_info(&out, info);
+            });
+            match (result.best_move, result.pv.get(1)) {
+                (Some(best_move), Some(ponder_move)) if result.pv[0] == best_move => {
+                    out.line(format_args!(
+                        "bestmove {} ponder {}",
+                        best_move.to_algebraic_notation(),
+                        ponder_move.to_algebraic_notation()
+                    ));
+                }
+                (Some(best_move), _) => out.line(format_args!(
+                    "bestmove {}",
+                    best_move.to_algebraic_notation()
+                )),
+                (None, _) => out.line("bestmove 0000"),
+            }
+        }));
+    }
+
+    /// The limits of a go command for the side to move, and whether it is a ponder search
+    fn go_limits(&self, tokens: &[&str]) -> (SearchLimits, bool) {
+        let mut limits = SearchLimits::default();
+        let mut ponder = false;
+        let (mut white_time, mut black_time) = (None, None);
+        let (mut white_increment, mut black_increment) = (Duration::ZERO, Duration::ZERO);
+        let mut iter = tokens.iter();
+        while let Some(token) = iter.next() {
+            let mut value = || iter.next().and_then(|v| v.parse::<u64>().ok());
+            match *token {
+                "wtime" => white_time = value().map(Duration::from_millis),
+                "btime" => black_time = value().map(Duration::from_millis),
+                "winc" => white_increment = Duration::from_millis(value().unwrap_or(0)),
+                "binc" => black_increment = Duration::from_millis(value().unwrap_or(0)),
+                "movestogo" => limits.time.moves_to_go = value().map(|v| v as u32),
+                "movetime" => limits.time.move_time = value().map(Duration::from_millis),
+                "depth" => limits.depth = value().map(|v| v as u32),
+                "nodes" => limits.nodes = value(),
+                "ponder" => ponder = true,
+                _ => {}
+            }
+        }
+        let (time, increment) = if self.game.turn {
+            (white_time, white_increment)
+        } else {
+            (black_time, black_increment)
+        };
+        limits.time = TimeControl {
+            time,
+            increment,
+            ..limits.time
+        };
+        (limits, ponder)
+    }
+
+    /// Stops the running search and waits until it has printed its best move
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            search.join().unwrap();
+        }
+    }
+}
+
+fn print_info(out: &Output, info: &SearchInfo) {
+    let score = if info.score >= MATE_BOUND {
+        format!("mate {}", (MATE - info.score + 1) / 2)
+    } else if info.score <= -MATE_BOUND {
+        format!("mate -{}", (MATE + info.score) / 2)
+    } else {
+        format!("cp {}", info.score)
+    };
+    let millis = info.time.as_millis().max(1) as u64;
+    let pv: Vec<String> = info.pv.iter().map(Move::to_algebraic_notation).collect();
+    out.line(format_args!(
+        "info depth {} multipv {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+        info.depth,
+        info.multi_pv,
+        score,
+        info.nodes,
+        info.nodes * 1000 / millis,
+        info.time.as_millis(),
+        info.hashfull,
+        pv.join(" ")
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn uci(commands: &[&str]) -> Uci {
+        let mut uci = Uci::new(Output::buffer());
+        for command in commands {
+            assert!(uci.handle_command(command), "{}", command);
+        }
+        uci
+    }
+
+    /// Waits until the search has printed its best move, returns everything it printed
+    fn wait_for_search(uci: &mut Uci) -> Vec<String> {
+        let start = Instant::now();
+        while !uci.search.as_ref().unwrap().is_finished() {
+            assert!(
+                start.elapsed() < Duration::from_secs(30),
+                "the search doesn't end"
+            );
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        uci.stop_search();
+        uci.out.take()
+    }
+
+    #[test]
+    fn uci_and_isready() {
+        let uci = uci(&["uci", "isready"]);
+        let lines = uci.out.take();
+        assert_eq!(lines[0], "id name Dyscalcuchess");
+        assert!(lines.contains(&"option name MultiPV type spin default 1 min 1 max 256".into()));
+        assert!(lines.contains(&"option name Ponder type check default false".into()));
+        assert_eq!(lines[lines.len() - 2..], ["uciok", "readyok"]);
+    }
+
+    #[test]
+    fn position() {
+        let mut uci = uci(&["position startpos moves e2e4 e7e5 g1f3"]);
+        let mut expected = Game::default();
+        for notation in ["e2e4", "e7e5", "g1f3"] {
+            expected.make_move(&Move::from_algebraic_notation(notation).unwrap());
+        }
+        assert_eq!(uci.game.exportPosition(), expected.exportPosition());
+        assert_eq!(uci.history.len(), 3);
+
+        let fen = "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1";
+        uci.handle_command(&format!("position fen {} moves b7b8n e8e7", fen));
+        assert_eq!(uci.game.exportPosition(), "1N6/4k3/8/8/8/8/8/4K3 w - - 1 2");
+        // the moves up to an illegal one are played
+        uci.handle_command(&format!("position fen {} moves e1d1 e8e9 d1e1", fen));
+        assert_eq!(uci.out.take(), ["info string illegal move e8e9"]);
+        assert_eq!(uci.history.len(), 1);
+        assert!(!uci.game.turn);
+        // a position that can't be read keeps the old one
+        let before = uci.game.exportPosition();
+        uci.handle_command("position fen 8/8 w - - 0 1");
+        assert_eq!(
+            uci.out.take(),
+            ["info string invalid position fen 8/8 w - - 0 1"]
+        );
+        assert_eq!(uci.game.exportPosition(), before);
+    }
+
+    #[test]
+    fn go_limits() {
+        let uci = uci(&["position startpos moves e2e4"]);
+        let command = "wtime 1000 btime 2000 winc 10 binc 20 movestogo 5";
+        let tokens: Vec<&str> = command.split(' ').collect();
+        let (limits, ponder) = uci.go_limits(&tokens);
+        // black is to move
+        assert_eq!(limits.time.time, Some(Duration::from_secs(2)));
+        assert_eq!(limits.time.increment, Duration::from_millis(20));
+        assert_eq!(limits.time.moves_to_go, Some(5));
+        assert_eq!((limits.depth, limits.nodes, ponder), (None, None, false));
+
+        let tokens = ["ponder", "movetime", "300", "depth", "6", "nodes", "5000"];
+        let (limits, ponder) = uci.go_limits(&tokens);
+        assert_eq!(limits.time.move_time, Some(Duration::from_millis(300)));
+        assert_eq!(
+            (limits.depth, limits.nodes, ponder),
+            (Some(6), Some(5000), true)
+        );
+
+        let (limits, ponder) = uci.go_limits(&["infinite"]);
+        assert_eq!(limits.time.time, None);
+        assert_eq!(limits.time.move_time, None);
+        assert_eq!((limits.depth, limits.nodes, ponder), (None, None, false));
+    }
+
+    #[test]
+    fn go_prints_the_lines_and_the_best_move() {
+        let mut uci = uci(&[
+            "setoption name MultiPV value 3",
+            "position startpos",
+            "go depth 3",
+        ]);
+        let lines = wait_for_search(&mut uci);
+        let last_iteration: Vec<Vec<&str>> = lines
+            .iter()
+            .filter(|line| line.starts_with("info depth 3 "))
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(last_iteration.len(), 3);
+        let mut first_moves = Vec::new();
+        for (i, info) in last_iteration.iter().enumerate() {
+            assert_eq!(info[4], (i + 1).to_string());
+            let pv = info.iter().position(|token| *token == "pv").unwrap();
+            first_moves.push(info[pv + 1]);
+        }
+        first_moves.sort_unstable();
+        first_moves.dedup();
+        assert_eq!(first_moves.len(), 3);
+
+        let bestmove: Vec<&str> = lines.last().unwrap().split_whitespace().collect();
+        assert_eq!(bestmove[0], "bestmove");
+        let best_move = Move::from_algebraic_notation(bestmove[1]).unwrap();
+        assert!(Game::default()
+            .board
+            .get_legal_moves(true)
+            .contains(&best_move));
+    }
+
+    #[test]
+    fn ponder_waits_for_ponderhit() {
+        let mut uci = uci(&["position startpos", "go ponder depth 2"]);
+        // the depth is reached quickly, but a ponder search only ends on ponderhit or stop
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!uci.search.as_ref().unwrap().is_finished());
+        assert!(!uci
+            .out
+            .take()
+            .iter()
+            .any(|line| line.starts_with("bestmove")));
+        uci.handle_command("ponderhit");
+        let lines = wait_for_search(&mut uci);
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+        // the next search is a normal one again
+        uci.handle_command("go depth 2");
+        assert!(wait_for_search(&mut uci)
+            .last()
+            .unwrap()
+            .starts_with("bestmove "));
+    }
+
+    #[test]
+    fn malformed_setoption_is_ignored() {
+        let mut uci = Uci::new(Output::Stdout);
+        for line in [
+            "setoption",
+            "setoption value 4",
+            "setoption Threads value 4",
+            "setoption name",
+            "setoption name value 4",
+            "setoption name Threads value",
+        ] {
+            assert!(uci.handle_command(line));
+        }
+        assert_eq!(uci.engine.lock().unwrap().options().threads, 1);
+        uci.handle_command("setoption name Threads value 4");
+        assert_eq!(uci.engine.lock().unwrap().options().threads, 4);
+    }
+}
@@ -0,0 +1,469 @@
+// Purpose: XBoard (CECP) front-end for the engine, for GUIs and tools that don't speak UCI.
+// See https://www.gnu.org/software/xboard/engine-intf.html for the protocol.
+//
+// Unlike UCI the engine keeps track of the game itself: the GUI sends the moves of the
+// opponent one by one and the engine answers with its own moves when it is its turn.
+
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::Output;
+use crate::core::{
+    game::{Game, GameResult},
+    san::line_to_san,
+    Move,
+};
+use crate::engine::{
+    search::{SearchInfo, SearchLimits, MATE, MATE_BOUND},
+    time_manager::TimeControl,
+    Engine,
+};
+
+/// Reads CECP commands from stdin until `quit`
+pub fn run() {
+    let mut xboard = Xboard::new(Output::Stdout);
+    for line in std::io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !xboard.handle_command(&line) {
+            break;
+        }
+    }
+    xboard.stop_search(false);
+}
+
+struct Xboard {
+    engine: Arc<Mutex<Engine>>,
+    out: Output,
+    stop: Arc<AtomicBool>,
+    /// the position the game started from and the moves played since
+    start: Game,
+    moves: Vec<Move>,
+    game: Game,
+    /// keys of the positions before the current one
+    history: Vec<u64>,
+    /// the side the engine plays, None in force mode
+    engine_color: Option<bool>,
+    /// print the thinking of the engine
+    post: bool,
+    /// from `level`: moves per time control (0 for all), base time and increment
+    moves_per_session: u32,
+    base_time: Duration,
+    increment: Duration,
+    /// from `st`: think exactly this long about every move
+    move_time: Option<Duration>,
+    /// from `sd`
+    depth: Option<u32>,
+    /// the clock of the engine, from `time`
+    engine_time: Option<Duration>,
+    search: Option<Search>,
+}
+
+/// A search on a background thread
+struct Search {
+    handle: JoinHandle<Option<Move>>,
+    /// set by whoever comes first: the search printing its move or the front-end
+    /// throwing the search away
+    claimed: Arc<AtomicBool>,
+}
+
+impl Xboard {
+    fn new(out: Output) -> Xboard {
+        let engine = Engine::default();
+        Xboard {
+            stop: engine.stop_handle(),
+            engine: Arc::new(Mutex::new(engine)),
+            out,
+            start: Game::default(),
+            moves: Vec::new(),
+            game: Game::default(),
+            history: Vec::new(),
+            engine_color: Some(false),
+            post: false,
+            moves_per_session: 0,
+            base_time: Duration::from_secs(300),
+            increment: Duration::ZERO,
+            move_time: None,
+            depth: None,
+            engine_time: None,
+            search: None,
+        }
+    }
+
+    /// Handles one line of input, returns false when the engine has to quit
+    fn handle_command(&mut self, line: &str) -> bool {
+        // a move of the engine that was printed has to be on the board before anything else
+        if self.search.as_ref().is_some_and(|s| s.handle.is_finished()) {
+            self.stop_search(true);
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["protover", ..] => {
+                self.out.line(
+                    "feature myname=\"Dyscalcuchess\" setboard=1 usermove=1 ping=1 san=0 \
+                     time=1 draw=0 sigint=0 sigterm=0 colors=0 analyze=0 reuse=1 done=1",
+                );
+            }
+            ["ping", n] => self.out.line(format_args!("pong {}", n)),
+            ["new"] => {
+                self.stop_search(false);
+                self.engine.lock().unwrap().new_game();
+                self.set_position(Game::default());
+                self.engine_color = Some(false);
+                self.depth = None;
+                self.engine_time = None;
+            }
+            ["setboard", fen @ ..] => {
+                self.stop_search(false);
+                match Game::from_fen(&fen.join(" ")) {
+                    Some(game) => self.set_position(game),
+                    None => self.out.line("tellusererror Illegal position"),
+                }
+            }
+            ["force"] => {
+                self.stop_search(false);
+                self.engine_color = None;
+            }
+            ["go"] => {
+                self.stop_search(false);
+                self.engine_color = Some(self.game.turn);
+                self.start_search();
+            }
+            ["playother"] => {
+                self.stop_search(false);
+                self.engine_color = Some(!self.game.turn);
+            }
+            ["usermove", notation] => self.user_move(notation),
+            ["?"] => self.stop_search(true),
+            ["undo"] => self.take_back(1),
+            ["remove"] => self.take_back(2),
+            ["result", ..] => {
+                self.stop_search(false);
+                self.engine_color = None;
+            }
+            ["level", moves, base, increment] => {
+                self.moves_per_session = moves.parse().unwrap_or(0);
+                self.base_time = parse_minutes(base).unwrap_or(self.base_time);
+                self.increment = increment
+                    .parse::<f64>()
+                    .map_or(Duration::ZERO, Duration::from_secs_f64);
+                self.move_time = None;
+            }
+            ["st", seconds] => {
+                self.move_time = seconds.parse::<f64>().ok().map(Duration::from_secs_f64);
+            }
+            ["sd", depth] => self.depth = depth.parse().ok(),
+            ["time", centiseconds] => {
+                self.engine_time = centiseconds
+                    .parse::<u64>()
+                    .ok()
+                    .map(|cs| Duration::from_millis(cs * 10));
+            }
+            // the clock of the opponent, the time manager only plans with its own
+            ["otim", _] => {}
+            ["post"] => self.post = true,
+            ["nopost"] => self.post = false,
+            ["quit"] => return false,
+            // xboard, accepted, rejected, hard, easy, random, computer, name, ...
+            _ => {}
+        }
+        true
+    }
+
+    /// Starts a new game from this position
+    fn set_position(&mut self, game: Game) {
+        self.start = game.clone();
+        self.game = game;
+        self.moves.clear();
+        self.history.clear();
+    }
+
+    fn make_move(&mut self, r#move: Move) {
+        self.history
+            .push(self.game.board.zobrist_key(self.game.turn));
+        self.game.make_move(&r#move);
+        self.moves.push(r#move);
+    }
+
+    /// usermove <move>, in coordinate notation like e2e4 or e7e8q
+    fn user_move(&mut self, notation: &str) {
+        self.stop_search(false);
+        let legal_move = Move::from_algebraic_notation(notation)
+            .ok()
+            .filter(|m| self.game.board.get_legal_moves(self.game.turn).contains(m));
+        let Some(r#move) = legal_move else {
+            self.out.line(format_args!("Illegal move: {}", notation));
+            return;
+        };
+        self.make_move(r#move);
+        if let Some(result) = self.game.result(&self.history) {
+            print_result(&self.out, &self.game, result);
+        } else if self.engine_color == Some(self.game.turn) {
+            self.start_search();
+        }
+    }
+
+    /// Takes back the last `plies` moves by replaying the game without them
+    fn take_back(&mut self, plies: usize) {
+        self.stop_search(false);
+        let mut moves = std::mem::take(&mut self.moves);
+        moves.truncate(moves.len().saturating_sub(plies));
+        self.set_position(self.start.clone());
+        for r#move in moves {
+            self.make_move(r#move);
+        }
+    }
+
+    fn limits(&self) -> SearchLimits {
+        let time = match self.move_time {
+            Some(move_time) => TimeControl::move_time(move_time),
+            None => {
+                // moves the engine still has to play before the next time control
+                let moves_to_go = (self.moves_per_session > 0).then(|| {
+                    let played =
+                        (self.game.fullmove - self.start.fullmove) % self.moves_per_session;
+                    self.moves_per_session - played
+                });
+                TimeControl {
+                    time: Some(self.engine_time.unwrap_or(self.base_time)),
+                    increment: self.increment,
+                    moves_to_go,
+                    move_time: None,
+                }
+            }
+        };
+        SearchLimits {
+            depth: self.depth,
+            nodes: None,
+            time,
+        }
+    }
+
+    fn start_search(&mut self) {
+        if self.game.result(&self.history).is_some() {
+            return;
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        let claimed = Arc::new(AtomicBool::new(false));
+        let engine = self.engine.clone();
+        let game = self.game.clone();
+        let history = self.history.clone();
+        let limits = self.limits();
+        let post = self.post;
+        let out = self.out.clone();
+        let search_claimed = claimed.clone();
+        let handle = std::thread::spawn(move || {
+            let mut engine = engine.lock().unwrap();
+            let result = engine.search(&game, &history, &limits, &mut |info| {
+                if post && info.multi_pv == 1 {
+                    print_thinking(&out, &game, info);
+                }
+            });
+            let best_move = result.best_move?;
+            // the front-end may have thrown the search away in the meantime
+            if search_claimed.swap(true, Ordering::SeqCst) {
+                return None;
+            }
+            out.line(format_args!("move {}", best_move.to_algebraic_notation()));
+            let mut after = game.clone();
+            let mut history = history;
+            history.push(after.board.zobrist_key(after.turn));
+            after.make_move(&best_move);
+            if let Some(result) = after.result(&history) {
+                print_result(&out, &after, result);
+            }
+            Some(best_move)
+        });
+        self.search = Some(Search { handle, claimed });
+    }
+
+    /// Stops the running search. With `play_move` the engine plays the best move it found,
+    /// otherwise the search is thrown away unless it already printed its move.
+    fn stop_search(&mut self, play_move: bool) {
+        let Some(search) = self.search.take() else {
+            return;
+        };
+        if !play_move {
+            search.claimed.store(true, Ordering::SeqCst);
+        }
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(r#move) = search.handle.join().unwrap() {
+            self.make_move(r#move);
+        }
+    }
+}
+
+/// The time of a level command in minutes, ex: "5" or "0:30"
+fn parse_minutes(text: &str) -> Option<Duration> {
+    let (minutes, seconds) = text.split_once(':').unwrap_or((text, "0"));
+    let seconds = minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+/// The result of the game as the engine claims it, ex: "1-0 {White mates}"
+fn print_result(out: &Output, game: &Game, result: GameResult) {
+    let reason = if game.board.get_legal_moves(game.turn).is_empty() {
+        match result {
+            GameResult::WhiteWins => "White mates",
+            GameResult::BlackWins => "Black mates",
+            GameResult::Draw => "Stalemate",
+        }
+    } else if game.halfmove_clock >= 100 {
+        "50 move rule"
+    } else if game.board.is_insufficient_material() {
+        "Insufficient material"
+    } else {
+        "Draw by repetition"
+    };
+    out.line(format_args!("{} {{{}}}", result.to_pgn(), reason));
+}
+
+/// Thinking output: ply, score, time in centiseconds, nodes and the principal variation
+fn print_thinking(out: &Output, game: &Game, info: &SearchInfo) {
+    // mates are written as 100000 + the moves until mate
+    let score = if info.score >= MATE_BOUND {
+        100_000 + (MATE - info.score + 1) / 2
+    } else if info.score <= -MATE_BOUND {
+        -100_000 - (MATE + info.score) / 2
+    } else {
+        info.score
+    };
+    let pv = line_to_san(&game.board, game.turn, &info.pv);
+    out.line(format_args!(
+        "{} {} {} {} {}",
+        info.depth,
+        score,
+        info.time.as_millis() / 10,
+        info.nodes,
+        pv.join(" ")
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xboard(commands: &[&str]) -> Xboard {
+        let mut xboard = Xboard::new(Output::buffer());
+        for command in commands {
+            assert!(xboard.handle_command(command), "{}", command);
+        }
+        xboard
+    }
+
+    #[test]
+    fn protover_lists_the_features() {
+        let xboard = xboard(&["xboard", "protover 2", "ping 7"]);
+        let lines = xboard.out.take();
+        assert_eq!(lines.len(), 2);
+        let features: Vec<&str> = lines[0].split_whitespace().collect();
+        assert_eq!(features[0], "feature");
+        for feature in [
+            "setboard=1",
+            "usermove=1",
+            "ping=1",
+            "san=0",
+            "time=1",
+            "done=1",
+        ] {
+            assert!(features.contains(&feature), "{}", feature);
+        }
+        assert_eq!(lines[1], "pong 7");
+    }
+
+    #[test]
+    fn setboard() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 3 40";
+        let mut xboard = xboard(&["force", &format!("setboard {}", fen)]);
+        assert_eq!(xboard.game.exportPosition(), fen);
+        assert_eq!(xboard.start.exportPosition(), fen);
+        // a position we can't read leaves the board alone
+        xboard.handle_command("setboard 4k3/8/8 w - - 0 1");
+        assert_eq!(xboard.out.take(), ["tellusererror Illegal position"]);
+        assert_eq!(xboard.game.exportPosition(), fen);
+    }
+
+    #[test]
+    fn user_moves_and_take_backs() {
+        let mut xboard = xboard(&["new", "force", "usermove e2e4", "usermove e7e5"]);
+        assert_eq!(xboard.moves.len(), 2);
+        assert_eq!(xboard.history.len(), 2);
+        xboard.handle_command("usermove e2e4");
+        assert_eq!(xboard.out.take(), ["Illegal move: e2e4"]);
+        assert_eq!(xboard.moves.len(), 2);
+
+        xboard.handle_command("usermove g1f3");
+        xboard.handle_command("undo");
+        assert_eq!(xboard.moves.len(), 2);
+        assert!(xboard.game.turn);
+        xboard.handle_command("remove");
+        assert!(xboard.moves.is_empty() && xboard.history.is_empty());
+        assert_eq!(
+            xboard.game.exportPosition(),
+            Game::default().exportPosition()
+        );
+        // taking back more than was played goes back to the start
+        xboard.handle_command("remove");
+        assert!(xboard.moves.is_empty());
+    }
+
+    #[test]
+    fn engine_answers_user_moves() {
+        let mut xboard = xboard(&["new", "sd 1", "usermove e2e4"]);
+        assert!(xboard.search.is_some());
+        xboard.stop_search(true);
+        assert_eq!(xboard.moves.len(), 2);
+        let lines = xboard.out.take();
+        assert_eq!(
+            lines,
+            [format!("move {}", xboard.moves[1].to_algebraic_notation())]
+        );
+        // in force mode it only keeps track of the moves
+        xboard.handle_command("force");
+        xboard.handle_command("usermove d2d4");
+        assert!(xboard.search.is_none());
+    }
+
+    #[test]
+    fn time_controls() {
+        let mut xboard = xboard(&["level 40 5 2", "otim 1000"]);
+        assert_eq!(xboard.moves_per_session, 40);
+        assert_eq!(xboard.base_time, Duration::from_secs(300));
+        assert_eq!(xboard.increment, Duration::from_secs(2));
+        let limits = xboard.limits();
+        assert_eq!(limits.time.time, Some(Duration::from_secs(300)));
+        assert_eq!(limits.time.moves_to_go, Some(40));
+        assert_eq!(limits.depth, None);
+
+        // the clock of the engine takes the place of the base time
+        xboard.handle_command("time 1234");
+        assert_eq!(
+            xboard.limits().time.time,
+            Some(Duration::from_millis(12340))
+        );
+        // 10 moves later 30 moves are left until the next time control
+        xboard.game.fullmove += 10;
+        assert_eq!(xboard.limits().time.moves_to_go, Some(30));
+
+        xboard.handle_command("level 0 0:30 0.5");
+        assert_eq!(xboard.base_time, Duration::from_secs(30));
+        assert_eq!(xboard.increment, Duration::from_millis(500));
+        assert_eq!(xboard.limits().time.moves_to_go, None);
+
+        xboard.handle_command("st 1.5");
+        xboard.handle_command("sd 4");
+        let limits = xboard.limits();
+        assert_eq!(limits.time.move_time, Some(Duration::from_millis(1500)));
+        assert_eq!(limits.depth, Some(4));
+        // a new level replaces the fixed time per move, new forgets the depth
+        xboard.handle_command("level 0 1 0");
+        assert_eq!(xboard.limits().time.move_time, None);
+        xboard.handle_command("new");
+        assert_eq!(xboard.limits().depth, None);
+    }
+}
@@ -0,0 +1,167 @@
+// Purpose: The engine's analysis next to the board: the eval bar, and a panel with the score,
+// the depth, the speed of the search and its best line in SAN.
+//
+// Scores are shown from white's point of view, like most GUIs do.
+
+use macroquad::{
+    color::Color,
+    color_u8,
+    math::Rect,
+    prelude::WHITE,
+    shapes::draw_rectangle,
+    text::{draw_text, measure_text},
+};
+
+use crate::core::{game::Game, san::line_to_san};
+use crate::engine::search::{SearchInfo, MATE, MATE_BOUND};
+
+/// The score of the search for white, in centipawns
+fn white_score(game: &Game, info: &SearchInfo) -> i32 {
+    if game.turn {
+        info.score
+    } else {
+        -info.score
+    }
+}
+
+/// ex: "+0.35", "-1.20", "#3" when white mates in 3 or "#-3" when black does
+fn format_score(game: &Game, info: &SearchInfo) -> String {
+    let score = white_score(game, info);
+    if score >= MATE_BOUND {
+        format!("#{}", (MATE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        format!("#-{}", (MATE + score + 1) / 2)
+    } else {
+        format!("{:+.2}", score as f64 / 100.0)
+    }
+}
+
+/// ex: "850k" or "1.2M"
+fn format_speed(info: &SearchInfo) -> String {
+    let seconds = info.time.as_secs_f64();
+    if seconds <= 0.0 {
+        return "-".to_string();
+    }
+    let nps = info.nodes as f64 / seconds;
+    if nps >= 1_000_000.0 {
+        format!("{:.1}M", nps / 1_000_000.0)
+    } else {
+        format!("{:.0}k", nps / 1000.0)
+    }
+}
+
+/// White's part of the bar grows from white's side of the board, with its chance to win
+pub fn render_eval_bar(rect: Rect, game: &Game, info: Option<&SearchInfo>, flipped: bool) {
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        color_u8!(0x40, 0x3d, 0x39, 0xff),
+    );
+    let share = match info.map(|info| white_score(game, info)) {
+        Some(score) if score >= MATE_BOUND => 1.0,
+        Some(score) if score <= -MATE_BOUND => 0.0,
+        Some(score) => 1.0 / (1.0 + (-score as f32 / 270.0).exp()),
+        None => 0.5,
+    };
+    let white_height = rect.h * share;
+    let y = if flipped {
+        rect.y
+    } else {
+        rect.bottom() - white_height
+    };
+    draw_rectangle(rect.x, y, rect.w, white_height, WHITE);
+    // the middle, where nobody is ahead
+    draw_rectangle(
+        rect.x,
+        rect.y + rect.h / 2.0 - 1.0,
+        rect.w,
+        2.0,
+        color_u8!(0xc8, 0x32, 0x28, 0xc0),
+    );
+}
+
+/// The score, the depth and speed, and below them the best line with move numbers
+pub fn render_analysis(rect: Rect, game: &Game, info: Option<&SearchInfo>) {
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        color_u8!(0x30, 0x2e, 0x2b, 0xff),
+    );
+    let row_height = (rect.w * 0.09).max(16.0);
+    let font_size = row_height * 0.7;
+    let padding = rect.w * 0.03;
+    let grey = color_u8!(0xa0, 0xa0, 0xa0, 0xff);
+
+    let Some(info) = info else {
+        let text = if game.board.get_legal_moves(game.turn).is_empty() {
+            "game over"
+        } else {
+            "thinking..."
+        };
+        draw_text(
+            text,
+            rect.x + padding,
+            rect.y + row_height * 0.75,
+            font_size,
+            grey,
+        );
+        return;
+    };
+
+    draw_text(
+        &format_score(game, info),
+        rect.x + padding,
+        rect.y + row_height,
+        row_height * 1.2,
+        WHITE,
+    );
+    let stats = format!("depth {}  {} nps", info.depth, format_speed(info));
+    let size = measure_text(&stats, None, font_size as u16, 1.0);
+    draw_text(
+        &stats,
+        rect.right() - size.width - padding,
+        rect.y + row_height,
+        font_size,
+        grey,
+    );
+
+    // the line, a move with its number stays together when the line wraps
+    let mut words = Vec::new();
+    let mut fullmove = game.fullmove;
+    let mut turn = game.turn;
+    for (index, san) in line_to_san(&game.board, game.turn, &info.pv)
+        .into_iter()
+        .enumerate()
+    {
+        words.push(if turn {
+            format!("{}. {}", fullmove, san)
+        } else if index == 0 {
+            format!("{}... {}", fullmove, san)
+        } else {
+            san
+        });
+        if !turn {
+            fullmove += 1;
+        }
+        turn = !turn;
+    }
+    let space = measure_text(" ", None, font_size as u16, 1.0).width;
+    let mut x = rect.x + padding;
+    let mut y = rect.y + row_height * 2.0;
+    for word in words {
+        let width = measure_text(&word, None, font_size as u16, 1.0).width;
+        if x > rect.x + padding && x + width > rect.right() - padding {
+            x = rect.x + padding;
+            y += row_height;
+        }
+        if y + row_height > rect.bottom() {
+            break;
+        }
+        draw_text(&word, x, y + row_height * 0.75, font_size, WHITE);
+        x += width + space;
+    }
+}
@@ -0,0 +1,150 @@
+// Purpose: Slides the pieces from where they were drawn to where they are now, so a move of the
+// bot or going back through the game doesn't make them jump.
+//
+// The animation is found by comparing the two boards: a piece that left a square goes to the
+// nearest square a piece of its kind arrived on. That covers the rook of castling and going
+// back or forward many moves at once. Pieces that went away fade out. It only changes what is
+// drawn, the game and the inputs never wait for it.
+
+use std::time::{Duration, Instant};
+
+use macroquad::{
+    color::Color,
+    math::vec2,
+    prelude::WHITE,
+    texture::{draw_texture_ex, DrawTextureParams},
+};
+
+use crate::core::{board::Board, PieceType};
+
+use super::{layout::Layout, renderer::PIECE_SIZE, textures::Textures};
+
+/// How long a move takes when nothing else is asked for
+pub const DEFAULT_DURATION: Duration = Duration::from_millis(200);
+
+/// column and row
+type Square = (u32, u32);
+
+pub struct Animation {
+    /// a piece and the squares it slides from and to
+    slides: Vec<(PieceType, Square, Square)>,
+    /// pieces that are gone and the squares they fade out on
+    fades: Vec<(PieceType, Square)>,
+    start: Instant,
+    duration: Duration,
+}
+
+impl Animation {
+    /// The animation from `before` to `after`, None when no piece changed
+    pub fn new(before: &Board, after: &Board, duration: Duration) -> Option<Animation> {
+        let mut left = Vec::new();
+        let mut arrived = Vec::new();
+        for column in 0..8 {
+            for row in 0..8 {
+                let old = before.get_piece_pos(column, row);
+                let new = after.get_piece_pos(column, row);
+                if old == new {
+                    continue;
+                }
+                if let Some(piece) = old {
+                    left.push((piece, (column, row)));
+                }
+                if let Some(piece) = new {
+                    arrived.push((piece, (column, row)));
+                }
+            }
+        }
+        if left.is_empty() && arrived.is_empty() {
+            return None;
+        }
+
+        // the closest pairs first, pieces that only appear, like a new queen, are just there
+        let mut slides = Vec::new();
+        loop {
+            let closest = left
+                .iter()
+                .enumerate()
+                .flat_map(|(i, (piece, from))| {
+                    arrived
+                        .iter()
+                        .enumerate()
+                        .filter(move |(_, (other, _))| other == piece)
+                        .map(move |(j, (_, to))| (distance(*from, *to), i, j))
+                })
+                .min();
+            let Some((_, i, j)) = closest else {
+                break;
+            };
+            let (piece, from) = left.swap_remove(i);
+            let (_, to) = arrived.swap_remove(j);
+            slides.push((piece, from, to));
+        }
+        Some(Animation {
+            slides,
+            fades: left,
+            start: Instant::now(),
+            duration,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.start.elapsed() >= self.duration
+    }
+
+    /// How far the animation is, from 0 to 1, slow at the start and the end
+    fn progress(&self) -> f32 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        let t = (self.start.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.0);
+        // cubic ease in and out
+        if t < 0.5 {
+            4.0 * t * t * t
+        } else {
+            1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+        }
+    }
+
+    /// A piece is sliding to this square, it isn't drawn on it yet
+    pub fn is_sliding_to(&self, column: u32, row: u32) -> bool {
+        self.slides.iter().any(|(_, _, to)| *to == (column, row))
+    }
+
+    /// The fading pieces and the sliding ones over them
+    pub fn render(&self, layout: &Layout, textures: &Textures) {
+        let progress = self.progress();
+        for (piece, (column, row)) in &self.fades {
+            let rect = layout.inner_rect(*column, *row, PIECE_SIZE);
+            draw_texture_ex(
+                textures.get_texture(*piece),
+                rect.x,
+                rect.y,
+                Color::new(1.0, 1.0, 1.0, 1.0 - progress),
+                DrawTextureParams {
+                    dest_size: Some(vec2(rect.w, rect.h)),
+                    ..Default::default()
+                },
+            );
+        }
+        for (piece, from, to) in &self.slides {
+            let from = layout.inner_rect(from.0, from.1, PIECE_SIZE);
+            let to = layout.inner_rect(to.0, to.1, PIECE_SIZE);
+            let point = from.point().lerp(to.point(), progress);
+            draw_texture_ex(
+                textures.get_texture(*piece),
+                point.x,
+                point.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(to.w, to.h)),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
+
+/// Squares between two squares, counting diagonal steps as one
+fn distance(from: Square, to: Square) -> u32 {
+    from.0.abs_diff(to.0).max(from.1.abs_diff(to.1))
+}
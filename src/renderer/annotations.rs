@@ -0,0 +1,146 @@
+// Purpose: Marks drawn on the board with the right mouse button, to think or to teach.
+//
+// Dragging with the right button draws an arrow, a right-click colours the square. Shift, Alt
+// or both pick red, blue or yellow instead of green, drawing the same mark again removes it.
+// The next left click on the board clears everything. The engine draws its arrows the same way.
+
+use macroquad::{
+    color::Color,
+    color_u8,
+    input::{
+        is_key_down, is_mouse_button_pressed, is_mouse_button_released, mouse_position, KeyCode,
+        MouseButton,
+    },
+    math::Vec2,
+    shapes::{draw_line, draw_rectangle, draw_triangle},
+};
+
+use crate::core::Move;
+
+use super::layout::Layout;
+
+pub const GREEN: Color = color_u8!(0x15, 0x78, 0x1b, 0xa0);
+pub const RED: Color = color_u8!(0x88, 0x20, 0x20, 0xa0);
+pub const BLUE: Color = color_u8!(0x00, 0x30, 0x88, 0xa0);
+pub const YELLOW: Color = color_u8!(0xe6, 0x8f, 0x00, 0xa0);
+
+pub struct Annotations {
+    arrows: Vec<(Move, Color)>,
+    squares: Vec<((u32, u32), Color)>,
+    /// the square the right button was pressed on
+    drag_start: Option<(u32, u32)>,
+}
+
+impl Default for Annotations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Annotations {
+    pub fn new() -> Annotations {
+        Annotations {
+            arrows: Vec::new(),
+            squares: Vec::new(),
+            drag_start: None,
+        }
+    }
+
+    /// The colour the modifier keys held down pick
+    fn color() -> Color {
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let alt = is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt);
+        match (shift, alt) {
+            (false, false) => GREEN,
+            (true, false) => RED,
+            (false, true) => BLUE,
+            (true, true) => YELLOW,
+        }
+    }
+
+    /// Call every frame. A right-click that only cancels something, like a selected piece,
+    /// shouldn't mark anything: then `can_mark` is false.
+    pub fn update(&mut self, layout: &Layout, can_mark: bool) {
+        let square = layout.square_at(mouse_position().into());
+        if is_mouse_button_pressed(MouseButton::Left) && square.is_some() {
+            self.clear();
+        }
+        if is_mouse_button_pressed(MouseButton::Right) {
+            self.drag_start = square.filter(|_| can_mark);
+        }
+        if is_mouse_button_released(MouseButton::Right) {
+            let (Some(start), Some(end)) = (self.drag_start.take(), square) else {
+                return;
+            };
+            let color = Self::color();
+            if start == end {
+                toggle(&mut self.squares, start, color);
+            } else {
+                let arrow = Move::new(start, end);
+                toggle(&mut self.arrows, arrow, color);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.arrows.clear();
+        self.squares.clear();
+        self.drag_start = None;
+    }
+
+    /// The coloured squares, drawn under the pieces
+    pub fn render_squares(&self, layout: &Layout) {
+        for ((column, row), color) in &self.squares {
+            let square = layout.square_rect(*column, *row);
+            draw_rectangle(square.x, square.y, square.w, square.h, *color);
+        }
+    }
+
+    /// The arrows and the one being drawn, over the pieces
+    pub fn render_arrows(&self, layout: &Layout) {
+        for (arrow, color) in &self.arrows {
+            draw_arrow(layout, arrow, *color);
+        }
+        if let (Some(start), Some(end)) =
+            (self.drag_start, layout.square_at(mouse_position().into()))
+        {
+            if start != end {
+                let arrow = Move::new(start, end);
+                draw_arrow(layout, &arrow, Self::color());
+            }
+        }
+    }
+}
+
+/// Adds a mark, or takes it away when it is there in the same colour already
+fn toggle<T: PartialEq>(marks: &mut Vec<(T, Color)>, mark: T, color: Color) {
+    match marks.iter().position(|(other, _)| *other == mark) {
+        Some(index) if marks[index].1 == color => {
+            marks.remove(index);
+        }
+        Some(index) => marks[index].1 = color,
+        None => marks.push((mark, color)),
+    }
+}
+
+/// An arrow from the middle of the square a move comes from to the one it goes to
+pub fn draw_arrow(layout: &Layout, r#move: &Move, color: Color) {
+    let square_size = layout.square_size();
+    let from = layout.square_center(r#move.column_from, r#move.row_from);
+    let to = layout.square_center(r#move.column_to, r#move.row_to);
+    let direction = (to - from).normalize_or_zero();
+    let side = Vec2::new(-direction.y, direction.x);
+
+    // the point stops a bit before the middle of the square, the line where the head starts
+    let tip = to - direction * square_size * 0.1;
+    let head = tip - direction * square_size * 0.4;
+    let start = from + direction * square_size * 0.2;
+    draw_line(start.x, start.y, head.x, head.y, square_size * 0.2, color);
+    let half_width = square_size * 0.25;
+    draw_triangle(
+        tip,
+        head + side * half_width,
+        head - side * half_width,
+        color,
+    );
+}
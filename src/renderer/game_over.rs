@@ -0,0 +1,125 @@
+// Purpose: The box over the board when the game is over: who won and why, and the buttons
+// for what to do next.
+
+use macroquad::{
+    color::Color,
+    color_u8,
+    input::{is_mouse_button_pressed, mouse_position, MouseButton},
+    math::Rect,
+    prelude::WHITE,
+    shapes::draw_rectangle,
+    text::{draw_text, measure_text},
+};
+
+use crate::core::game::{GameResult, Termination};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameOverAction {
+    /// the same sides again with the colours swapped
+    Rematch,
+    NewGame,
+    CopyPgn,
+    /// go through the game from the start with the engine's analysis
+    Analyse,
+}
+
+const BUTTONS: [(GameOverAction, &str); 4] = [
+    (GameOverAction::Rematch, "Rematch"),
+    (GameOverAction::NewGame, "New game"),
+    (GameOverAction::CopyPgn, "Copy PGN"),
+    (GameOverAction::Analyse, "Analyse game"),
+];
+
+/// The box in the middle of the board
+fn overlay_rect(board: Rect) -> Rect {
+    let width = board.w * 0.6;
+    let height = board.h * 0.6;
+    Rect::new(
+        board.x + (board.w - width) / 2.0,
+        board.y + (board.h - height) / 2.0,
+        width,
+        height,
+    )
+}
+
+/// The buttons below each other in the lower part of the box
+fn button_rects(board: Rect) -> impl Iterator<Item = (GameOverAction, &'static str, Rect)> {
+    let overlay = overlay_rect(board);
+    let height = overlay.h * 0.12;
+    let gap = overlay.h * 0.04;
+    let width = overlay.w * 0.7;
+    let x = overlay.x + (overlay.w - width) / 2.0;
+    let top = overlay.bottom() - 4.0 * (height + gap);
+    BUTTONS
+        .into_iter()
+        .enumerate()
+        .map(move |(index, (action, label))| {
+            let y = top + index as f32 * (height + gap);
+            (action, label, Rect::new(x, y, width, height))
+        })
+}
+
+/// The button clicked this frame
+pub fn update(board: Rect) -> Option<GameOverAction> {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return None;
+    }
+    let mouse = mouse_position().into();
+    button_rects(board)
+        .find(|(_, _, rect)| rect.contains(mouse))
+        .map(|(action, _, _)| action)
+}
+
+/// ex: "White wins" over "by checkmate"
+pub fn render(board: Rect, result: GameResult, termination: Termination) {
+    let overlay = overlay_rect(board);
+    draw_rectangle(
+        overlay.x,
+        overlay.y,
+        overlay.w,
+        overlay.h,
+        color_u8!(0x30, 0x2e, 0x2b, 0xe8),
+    );
+
+    let title = match result {
+        GameResult::WhiteWins => "White wins",
+        GameResult::BlackWins => "Black wins",
+        GameResult::Draw => "Draw",
+    };
+    let reason = format!("by {}", termination.describe());
+    let title_size = overlay.h * 0.13;
+    let reason_size = overlay.h * 0.08;
+    for (text, size, y) in [
+        (title, title_size, overlay.y + overlay.h * 0.17),
+        (reason.as_str(), reason_size, overlay.y + overlay.h * 0.29),
+    ] {
+        let width = measure_text(text, None, size as u16, 1.0).width;
+        draw_text(text, overlay.center().x - width / 2.0, y, size, WHITE);
+    }
+
+    let mouse = mouse_position().into();
+    for (_, label, rect) in button_rects(board) {
+        let color = if rect.contains(mouse) {
+            color_u8!(0x5a, 0x7a, 0xa8, 0xff)
+        } else {
+            color_u8!(0x4a, 0x47, 0x43, 0xff)
+        };
+        draw_rectangle(rect.x, rect.y, rect.w, rect.h, color);
+        let size = rect.h * 0.6;
+        let text = measure_text(label, None, size as u16, 1.0);
+        draw_text(
+            label,
+            rect.center().x - text.width / 2.0,
+            rect.y + (rect.h + text.offset_y) / 2.0,
+            size,
+            WHITE,
+        );
+    }
+}
+
+/// Puts text on the clipboard of the system
+pub fn copy_to_clipboard(text: &str) {
+    // macroquad only reaches the clipboard through its miniquad context, like its own ui does
+    let gl = unsafe { macroquad::window::get_internal_gl() };
+    gl.quad_context.clipboard_set(text);
+}
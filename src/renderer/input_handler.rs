@@ -1,18 +1,30 @@
 use crate::core::game::Game;
-use crate::core::Move;
-use crate::core::{board::Board, PieceType};
+use crate::core::{board::Board, utils::bitboard_to_coordinates, PieceType};
+use crate::core::{Move, Promotion};
 use macroquad::input::{
     is_mouse_button_pressed, is_mouse_button_released, mouse_position, MouseButton,
 };
-use macroquad::math::vec2;
-use macroquad::window::{screen_height, screen_width};
-use std::cmp::min;
 
-use super::renderer::BOARD_SIZE;
+use super::layout::Layout;
 
+// A move is made by dragging a piece to its square, or by clicking the piece and then the
+// square. The selected piece stays selected between the two clicks, clicking it again,
+// clicking next to its moves or right-clicking deselects it.
+//
+// While the opponent thinks the same inputs queue premoves. They are played on the board as
+// it would be after them, to any square the piece could reach if the way was clear, and are
+// only checked against the rules once it is our turn. Right-clicking throws them all away.
 pub struct InputHandler {
     picked_piece: Option<PieceType>,
     picked_piece_pos: Option<u64>,
+    /// the left button is held down since it picked the piece
+    dragging: bool,
+    /// the press that started the drag was on the piece that was selected already
+    was_selected: bool,
+    /// moves to play as soon as it is our turn, the first one first
+    premoves: Vec<Move>,
+    /// the inputs of the last frame were premoves
+    premoving: bool,
 }
 
 impl Default for InputHandler {
@@ -26,60 +38,165 @@ impl InputHandler {
         InputHandler {
             picked_piece: None,
             picked_piece_pos: None,
+            dragging: false,
+            was_selected: false,
+            premoves: Vec::new(),
+            premoving: false,
         }
     }
 
-    pub fn handle_input(&mut self, game: &Game) -> Option<Move> {
-        let mut move_made = None;
-        if is_mouse_button_pressed(MouseButton::Left) {
-            let (x, y) = mouse_position();
-            let board_size = min(screen_width() as u32, screen_height() as u32) as f32 * BOARD_SIZE;
-            let board_pos = vec2(
-                screen_width() / 2.0 - board_size / 4.0,
-                screen_height() / 2.0 - board_size / 2.0,
-            );
-            let board_pos = vec2(board_pos.x, board_pos.y);
-
-            let column = ((x - board_pos.x) / (board_size / 8.0)) as u32;
-            let row = ((y - board_pos.y) / (board_size / 8.0)) as u32;
-            let pos = 1 << (column + row * 8);
-
-            if let Some(piece) = game.board.get_piece(pos) {
-                self.picked_piece = Some(piece);
-                self.picked_piece_pos = Some(pos);
+    /// A move of the side to move
+    pub fn handle_input(&mut self, game: &Game, layout: &Layout) -> Option<Move> {
+        self.premoving = false;
+        self.handle_mouse(&game.board, game.turn(), layout)
+    }
+
+    /// Queues premoves of the side that waits for its turn
+    pub fn handle_premove_input(&mut self, game: &Game, layout: &Layout) {
+        self.premoving = true;
+        let board = self.premove_board(&game.board);
+        if let Some(r#move) = self.handle_mouse(&board, !game.turn(), layout) {
+            self.premoves.push(r#move);
+        }
+    }
+
+    fn handle_mouse(&mut self, board: &Board, color: bool, layout: &Layout) -> Option<Move> {
+        // the position changed under the selection, like going back through the game
+        if let Some(pos) = self.picked_piece_pos {
+            let piece = board.get_piece(pos);
+            if piece != self.picked_piece || piece.map(|p| p.color()) != Some(color) {
+                self.deselect();
+            }
+        }
+        let square = layout
+            .square_at(mouse_position().into())
+            .map(|(column, row)| 1u64 << (column + row * 8));
+        if is_mouse_button_pressed(MouseButton::Right) {
+            self.deselect();
+            self.premoves.clear();
+        } else if is_mouse_button_pressed(MouseButton::Left) {
+            let Some(pos) = square else {
+                self.deselect();
+                return None;
+            };
+            // the second click of click-to-move
+            if let Some(r#move) = self.move_to(board, color, pos) {
+                self.deselect();
+                return Some(r#move);
+            }
+            match board.get_piece(pos) {
+                Some(piece) if piece.color() == color => {
+                    self.was_selected = self.picked_piece_pos == Some(pos);
+                    self.picked_piece = Some(piece);
+                    self.picked_piece_pos = Some(pos);
+                    self.dragging = true;
+                }
+                _ => self.deselect(),
             }
-        } else if is_mouse_button_released(MouseButton::Left) {
-            let (x, y) = mouse_position();
-            let board_size = min(screen_width() as u32, screen_height() as u32) as f32 * BOARD_SIZE;
-            let board_pos = vec2(
-                screen_width() / 2.0 - board_size / 4.0,
-                screen_height() / 2.0 - board_size / 2.0,
-            );
-            let board_pos = vec2(board_pos.x, board_pos.y);
-
-            let column_to = ((x - board_pos.x) / (board_size / 8.0)) as u32;
-            let row_to = ((y - board_pos.y) / (board_size / 8.0)) as u32;
-
-            let pos = 1 << (column_to + row_to * 8);
-
-            if self.picked_piece.is_some() {
-                if let Some(piece_pos) = self.picked_piece_pos {
-                    let column_from = piece_pos.trailing_zeros() % 8;
-                    let row_from = piece_pos.trailing_zeros() / 8;
-                    if game.board.is_legal_move(piece_pos, pos, game.turn()) {
-                        move_made = Some(Move {
-                            column_from,
-                            row_from,
-                            column_to,
-                            row_to,
-                        });
+        } else if is_mouse_button_released(MouseButton::Left) && self.dragging {
+            self.dragging = false;
+            match square {
+                // a click on the piece, it stays selected unless it was already
+                Some(pos) if Some(pos) == self.picked_piece_pos && self.was_selected => {
+                    self.deselect()
+                }
+                Some(pos) if Some(pos) == self.picked_piece_pos => {}
+                Some(pos) => {
+                    if let Some(r#move) = self.move_to(board, color, pos) {
+                        self.deselect();
+                        return Some(r#move);
                     }
                 }
+                None => {}
+            }
+        }
+        None
+    }
+
+    /// The move of the selected piece to `pos`, when it is legal, or could be for a premove
+    fn move_to(&self, board: &Board, color: bool, pos: u64) -> Option<Move> {
+        let piece_pos = self.picked_piece_pos?;
+        let allowed = if self.premoving {
+            premove_targets(board, piece_pos) & pos != 0
+        } else {
+            board.is_legal_move(piece_pos, pos, color)
+        };
+        if !allowed {
+            return None;
+        }
+        let r#move = Move::new(
+            bitboard_to_coordinates(piece_pos),
+            bitboard_to_coordinates(pos),
+        );
+        // pawns reaching the last row become a queen
+        let is_pawn = matches!(
+            board.get_piece(piece_pos),
+            Some(PieceType::WhitePawn | PieceType::BlackPawn)
+        );
+        if is_pawn && (r#move.row_to == 0 || r#move.row_to == 7) {
+            return Some(r#move.with_promotion(Promotion::Queen));
+        }
+        Some(r#move)
+    }
+
+    fn deselect(&mut self) {
+        self.picked_piece = None;
+        self.picked_piece_pos = None;
+        self.dragging = false;
+        self.was_selected = false;
+    }
+
+    /// The squares the selected piece can go to, on the board as it is shown
+    pub fn targets(&self, game: &Game) -> u64 {
+        let Some(piece_pos) = self.picked_piece_pos else {
+            return 0;
+        };
+        if self.premoving {
+            premove_targets(&self.premove_board(&game.board), piece_pos)
+        } else {
+            let (column, row) = bitboard_to_coordinates(piece_pos);
+            game.board.get_allowed_moves(column, row, game.turn())
+        }
+    }
+
+    /// A right-click would cancel a selection or premoves
+    pub fn has_pending(&self) -> bool {
+        self.picked_piece_pos.is_some() || !self.premoves.is_empty()
+    }
+
+    pub fn premoves(&self) -> &[Move] {
+        &self.premoves
+    }
+
+    /// The board with the premoves played on it
+    pub fn premove_board(&self, board: &Board) -> Board {
+        let mut board = board.clone();
+        for r#move in &self.premoves {
+            if board
+                .get_piece_pos(r#move.column_from, r#move.row_from)
+                .is_some()
+            {
+                board.move_piece(r#move);
             }
-            self.picked_piece = None;
-            self.picked_piece_pos = None;
         }
-        move_made
+        board
+    }
+
+    /// The first premove when it is legal now, otherwise none of the premoves are played
+    pub fn take_premove(&mut self, game: &Game) -> Option<Move> {
+        if self.premoves.is_empty() {
+            return None;
+        }
+        let r#move = self.premoves.remove(0);
+        let from = 1 << (r#move.column_from + r#move.row_from * 8);
+        let to = 1 << (r#move.column_to + r#move.row_to * 8);
+        let own_piece = game.board.get_piece(from).map(|p| p.color()) == Some(game.turn());
+        if own_piece && game.board.is_legal_move(from, to, game.turn()) {
+            Some(r#move)
+        } else {
+            self.premoves.clear();
+            None
+        }
     }
 
     pub fn get_picked_piece(&self) -> Option<PieceType> {
@@ -89,4 +206,90 @@ impl InputHandler {
     pub fn get_picked_piece_pos(&self) -> Option<u64> {
         self.picked_piece_pos
     }
+
+    /// The picked piece follows the mouse
+    pub fn is_dragging(&self) -> bool {
+        self.dragging
+    }
+}
+
+/// The squares the piece on `pos` could go to if nothing was in the way: pawns may always
+/// capture, the king may castle while it and the rook are on their squares
+fn premove_targets(board: &Board, pos: u64) -> u64 {
+    let Some(piece) = board.get_piece(pos) else {
+        return 0;
+    };
+    let (column, row) = bitboard_to_coordinates(pos);
+    let (column, row) = (column as i32, row as i32);
+    let square = |c: i32, r: i32| {
+        if (0..8).contains(&c) && (0..8).contains(&r) {
+            1u64 << (c + r * 8)
+        } else {
+            0
+        }
+    };
+    let lines = |directions: &[(i32, i32)]| {
+        let mut targets = 0;
+        for (dc, dr) in directions {
+            for step in 1..8 {
+                targets |= square(column + dc * step, row + dr * step);
+            }
+        }
+        targets
+    };
+    const STRAIGHT: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    const DIAGONAL: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+    match piece {
+        PieceType::WhitePawn | PieceType::BlackPawn => {
+            // white goes up the board, to row 0
+            let (forward, start) = if piece.color() { (-1, 6) } else { (1, 1) };
+            let mut targets = square(column, row + forward)
+                | square(column - 1, row + forward)
+                | square(column + 1, row + forward);
+            if row == start {
+                targets |= square(column, row + 2 * forward);
+            }
+            targets
+        }
+        PieceType::WhiteKnight | PieceType::BlackKnight => [
+            (1, 2),
+            (2, 1),
+            (2, -1),
+            (1, -2),
+            (-1, -2),
+            (-2, -1),
+            (-2, 1),
+            (-1, 2),
+        ]
+        .iter()
+        .fold(0, |targets, (dc, dr)| {
+            targets | square(column + dc, row + dr)
+        }),
+        PieceType::WhiteBishop | PieceType::BlackBishop => lines(&DIAGONAL),
+        PieceType::WhiteRook | PieceType::BlackRook => lines(&STRAIGHT),
+        PieceType::WhiteQueen | PieceType::BlackQueen => lines(&STRAIGHT) | lines(&DIAGONAL),
+        PieceType::WhiteKing | PieceType::BlackKing => {
+            let mut targets = STRAIGHT
+                .iter()
+                .chain(DIAGONAL.iter())
+                .fold(0, |targets, (dc, dr)| {
+                    targets | square(column + dc, row + dr)
+                });
+            let (home, rook) = if piece.color() {
+                (7, PieceType::WhiteRook)
+            } else {
+                (0, PieceType::BlackRook)
+            };
+            if (column, row) == (4, home) {
+                if board.get_piece_pos(7, home as u32) == Some(rook) {
+                    targets |= square(6, home);
+                }
+                if board.get_piece_pos(0, home as u32) == Some(rook) {
+                    targets |= square(2, home);
+                }
+            }
+            targets
+        }
+    }
 }
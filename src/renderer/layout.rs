@@ -0,0 +1,126 @@
+// Purpose: Where everything goes on the screen: the board, its squares and the panels next to it.
+// Rendering and the mouse both use the same layout, so what is drawn is what gets clicked.
+//
+// In a wide window the board is in the middle with a panel on either side, in a tall window
+// the board is at the top with the two panels below it. The strip of the eval bar is at the
+// side of the left panel that faces the right panel.
+
+use macroquad::math::{Rect, Vec2};
+use macroquad::window::{screen_height, screen_width};
+
+/// part of the short side of the window the board may take
+pub const BOARD_SIZE: f32 = 0.9;
+/// the space between the board, the panels and the edges, as part of the board
+const MARGIN: f32 = 0.03;
+/// the width of the eval bar, as part of the board
+const EVAL_BAR: f32 = 0.04;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    pub board: Rect,
+    pub left_panel: Rect,
+    pub right_panel: Rect,
+    pub eval_bar: Rect,
+    /// black at the bottom of the board
+    flipped: bool,
+}
+
+impl Layout {
+    pub fn new(width: f32, height: f32, flipped: bool) -> Layout {
+        let wide = width >= height;
+        // leave at least a third of the long side for the panels
+        let size = if wide {
+            (height * BOARD_SIZE).min(width * 0.6)
+        } else {
+            (width * BOARD_SIZE).min(height * 0.6)
+        };
+        let margin = size * MARGIN;
+        let bar_width = size * EVAL_BAR;
+        let (board, left, right) = if wide {
+            let board = Rect::new((width - size) / 2.0, (height - size) / 2.0, size, size);
+            let panel_width = board.x - 2.0 * margin;
+            let left = Rect::new(margin, board.y, panel_width, size);
+            let right = Rect::new(board.right() + margin, board.y, panel_width, size);
+            (board, left, right)
+        } else {
+            let board = Rect::new((width - size) / 2.0, margin, size, size);
+            let top = board.bottom() + margin;
+            let panel_width = (width - 3.0 * margin) / 2.0;
+            let panel_height = height - top - margin;
+            let left = Rect::new(margin, top, panel_width, panel_height);
+            let right = Rect::new(2.0 * margin + panel_width, top, panel_width, panel_height);
+            (board, left, right)
+        };
+        // the eval bar takes its strip off the left panel
+        let left_width = (left.w - bar_width - margin).max(0.0);
+        Layout {
+            board,
+            left_panel: Rect::new(left.x, left.y, left_width, left.h),
+            right_panel: right,
+            eval_bar: Rect::new(left.right() - bar_width, left.y, bar_width, left.h),
+            flipped,
+        }
+    }
+
+    /// The layout of the window as it is now
+    pub fn current(flipped: bool) -> Layout {
+        Layout::new(screen_width(), screen_height(), flipped)
+    }
+
+    pub fn is_flipped(&self) -> bool {
+        self.flipped
+    }
+
+    pub fn square_size(&self) -> f32 {
+        self.board.w / 8.0
+    }
+
+    pub fn margin(&self) -> f32 {
+        self.board.w * MARGIN
+    }
+
+    /// The column and row on the screen of a square of the board, 0 is left and top
+    pub fn screen_square(&self, column: u32, row: u32) -> (u32, u32) {
+        if self.flipped {
+            (7 - column, 7 - row)
+        } else {
+            (column, row)
+        }
+    }
+
+    /// Where a square of the board is drawn
+    pub fn square_rect(&self, column: u32, row: u32) -> Rect {
+        let (column, row) = self.screen_square(column, row);
+        let size = self.square_size();
+        Rect::new(
+            self.board.x + size * column as f32,
+            self.board.y + size * row as f32,
+            size,
+            size,
+        )
+    }
+
+    pub fn square_center(&self, column: u32, row: u32) -> Vec2 {
+        self.square_rect(column, row).center()
+    }
+
+    /// The square of the board at this point of the screen, None next to the board
+    pub fn square_at(&self, point: Vec2) -> Option<(u32, u32)> {
+        if !self.board.contains(point) {
+            return None;
+        }
+        let size = self.square_size();
+        let column = (((point.x - self.board.x) / size) as u32).min(7);
+        let row = (((point.y - self.board.y) / size) as u32).min(7);
+        // flipping twice is not flipping
+        Some(self.screen_square(column, row))
+    }
+
+    /// A rect of `size` times the square, in the middle of the square
+    pub fn inner_rect(&self, column: u32, row: u32, size: f32) -> Rect {
+        let square = self.square_rect(column, row);
+        let inner = square.w * size;
+        let offset = (square.w - inner) / 2.0;
+        Rect::new(square.x + offset, square.y + offset, inner, inner)
+    }
+}
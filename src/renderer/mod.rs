@@ -1,3 +1,9 @@
+pub mod analysis_panel;
+pub mod animation;
+pub mod annotations;
+pub mod game_over;
 pub mod input_handler;
+pub mod layout;
+pub mod move_list;
 pub mod renderer;
 pub mod textures;
@@ -0,0 +1,150 @@
+// Purpose: The panel with the moves of the game, two moves per row with their move number.
+// Clicking a move shows the position after it, the mouse wheel scrolls through the moves.
+
+use macroquad::{
+    color::Color,
+    color_u8,
+    input::{is_mouse_button_pressed, mouse_position, mouse_wheel, MouseButton},
+    math::Rect,
+    prelude::WHITE,
+    shapes::draw_rectangle,
+    text::draw_text,
+};
+
+use crate::core::game_record::GameRecord;
+
+pub struct MoveList {
+    /// rows scrolled out of sight at the top
+    scroll: usize,
+    /// the current move of the record the last frame, to scroll to it when it changes
+    shown_current: usize,
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList {
+            scroll: 0,
+            shown_current: 0,
+        }
+    }
+
+    fn row_height(rect: &Rect) -> f32 {
+        (rect.w * 0.09).max(16.0)
+    }
+
+    fn visible_rows(rect: &Rect) -> usize {
+        (rect.h / Self::row_height(rect)) as usize
+    }
+
+    /// The row and column of a move, the first row starts with "1..." when black moved first
+    fn cell(record: &GameRecord, index: usize) -> (usize, usize) {
+        let offset = if record.start().turn { 0 } else { 1 };
+        ((index + offset) / 2, (index + offset) % 2)
+    }
+
+    /// Scrolls and handles clicks, returns the position to show when a move was clicked
+    pub fn update(&mut self, record: &GameRecord, rect: Rect) -> Option<usize> {
+        let rows = record
+            .moves()
+            .len()
+            .checked_sub(1)
+            .map_or(0, |last| Self::cell(record, last).0 + 1);
+        let visible_rows = Self::visible_rows(&rect).max(1);
+
+        // keep the current move in sight when it changes
+        if record.current() != self.shown_current {
+            self.shown_current = record.current();
+            if let Some(index) = record.current().checked_sub(1) {
+                let row = Self::cell(record, index).0;
+                if row < self.scroll {
+                    self.scroll = row;
+                } else if row >= self.scroll + visible_rows {
+                    self.scroll = row + 1 - visible_rows;
+                }
+            }
+        }
+
+        let mouse = mouse_position().into();
+        if rect.contains(mouse) {
+            let wheel = mouse_wheel().1;
+            if wheel > 0.0 {
+                self.scroll = self.scroll.saturating_sub(1);
+            } else if wheel < 0.0 {
+                self.scroll += 1;
+            }
+        }
+        self.scroll = self.scroll.min(rows.saturating_sub(visible_rows));
+
+        if !is_mouse_button_pressed(MouseButton::Left) || !rect.contains(mouse) {
+            return None;
+        }
+        let row = ((mouse.y - rect.y) / Self::row_height(&rect)) as usize + self.scroll;
+        let column = if mouse.x < rect.x + rect.w * 0.55 {
+            0
+        } else {
+            1
+        };
+        (0..record.moves().len())
+            .find(|index| Self::cell(record, *index) == (row, column))
+            .map(|index| index + 1)
+    }
+
+    pub fn render(&self, record: &GameRecord, rect: Rect) {
+        draw_rectangle(
+            rect.x,
+            rect.y,
+            rect.w,
+            rect.h,
+            color_u8!(0x30, 0x2e, 0x2b, 0xff),
+        );
+        let row_height = Self::row_height(&rect);
+        let font_size = row_height * 0.7;
+        let visible_rows = Self::visible_rows(&rect);
+        let columns = [rect.x + rect.w * 0.2, rect.x + rect.w * 0.6];
+
+        for (index, san) in record.san().iter().enumerate() {
+            let (row, column) = Self::cell(record, index);
+            if row < self.scroll || row >= self.scroll + visible_rows {
+                continue;
+            }
+            let y = rect.y + (row - self.scroll) as f32 * row_height;
+            let (number, white) = record.move_number(index);
+            if white || index == 0 {
+                let number = if white {
+                    format!("{}.", number)
+                } else {
+                    format!("{}...", number)
+                };
+                draw_text(
+                    &number,
+                    rect.x + rect.w * 0.03,
+                    y + row_height * 0.75,
+                    font_size,
+                    color_u8!(0xa0, 0xa0, 0xa0, 0xff),
+                );
+            }
+            if index + 1 == record.current() {
+                draw_rectangle(
+                    columns[column] - rect.w * 0.02,
+                    y,
+                    rect.w * 0.38,
+                    row_height,
+                    color_u8!(0x5a, 0x7a, 0xa8, 0xff),
+                );
+            }
+            draw_text(
+                san,
+                columns[column],
+                y + row_height * 0.75,
+                font_size,
+                WHITE,
+            );
+        }
+    }
+}
@@ -1,101 +1,377 @@
-use std::cmp::min;
-
 use macroquad::{
     color::Color,
     color_u8,
+    math::Rect,
     prelude::{mouse_position, vec2, DARKGRAY, WHITE},
     shapes::{draw_circle, draw_circle_lines, draw_rectangle},
-    text::draw_text,
+    text::{draw_text, measure_text},
     texture::{draw_texture_ex, DrawTextureParams},
-    window::{clear_background, screen_height, screen_width},
+    window::clear_background,
 };
 
-use crate::core::{board::Board, game::Game, utils::bitboard_to_coordinates, Move, PieceType};
+use std::time::{Duration, Instant};
+
+use crate::core::{
+    board::Board,
+    clock::Clock,
+    game::{Game, GameResult, Termination},
+    game_record::GameRecord,
+    utils::bitboard_to_coordinates,
+    Move,
+};
+use crate::engine::analysis::Analysis;
 
-use super::{input_handler::InputHandler, textures::Textures};
+use super::{
+    analysis_panel,
+    animation::{self, Animation},
+    annotations::{self, Annotations},
+    game_over::{self, GameOverAction},
+    input_handler::InputHandler,
+    layout::Layout,
+    move_list::MoveList,
+    textures::Textures,
+};
 
 pub const PIECE_SIZE: f32 = 0.9;
-pub const BOARD_SIZE: f32 = 0.9;
+/// How long a status message stays on the screen, it fades out in the last part
+const STATUS_DURATION: Duration = Duration::from_millis(2500);
 
 pub struct Renderer {
     textures: Textures,
     input_handler: InputHandler,
-    board_size: f32,
-    last_move: Option<Move>,
+    layout: Layout,
+    move_list: MoveList,
+    annotations: Annotations,
+    /// the engine's best move and the move it threatens after it
+    hint: Option<(Move, Option<Move>)>,
+    /// black at the bottom of the screen
+    flipped: bool,
+    /// the analysis panel takes the top of the right panel
+    analysis_shown: bool,
+    /// the board as it was drawn the last frame, to animate what changed
+    shown: Option<Board>,
+    animation: Option<Animation>,
+    animation_duration: Duration,
+    /// the next change is a piece dropped where it goes, it doesn't slide there
+    snap: bool,
+    /// the last status message and when it was shown, ex: "Hints on"
+    status: Option<(String, Instant)>,
 }
 
 impl Renderer {
     pub async fn new() -> Self {
         let textures = Textures::new().await;
         let input_handler = InputHandler::new();
-        let board_size = min(screen_width() as u32, screen_height() as u32) as f32 * BOARD_SIZE;
         Self {
             textures,
             input_handler,
-            board_size,
-            last_move: None,
+            layout: Layout::current(false),
+            move_list: MoveList::new(),
+            annotations: Annotations::new(),
+            hint: None,
+            flipped: false,
+            analysis_shown: false,
+            shown: None,
+            animation: None,
+            animation_duration: animation::DEFAULT_DURATION,
+            snap: false,
+            status: None,
         }
     }
 
+    pub fn is_flipped(&self) -> bool {
+        self.flipped
+    }
+
+    /// Draws the board with black at the bottom, or white when false
+    pub fn set_flipped(&mut self, flipped: bool) {
+        self.flipped = flipped;
+        self.layout = Layout::current(flipped);
+    }
+
+    /// How long a piece takes to slide to its square, zero turns the animations off
+    pub fn set_animation_duration(&mut self, duration: Duration) {
+        self.animation_duration = duration;
+    }
+
     pub fn user_inputs(&mut self, game: &Game) -> Option<Move> {
-        if let Some(move_made) = self.input_handler.handle_input(game) {
-            // println!("Game: {}", game.exportPosition());
-            println!("Move  : {:?}", move_made);
-            self.last_move = Some(move_made);
+        self.layout = Layout::current(self.flipped);
+        let was_dragging = self.input_handler.is_dragging();
+        if let Some(move_made) = self.input_handler.handle_input(game, &self.layout) {
+            self.snap = was_dragging;
             return Some(move_made);
         }
         None
     }
 
-    pub fn render(&mut self, game: &Game) {
-        let position = &game.board;
-        self.board_size = min(screen_width() as u32, screen_height() as u32) as f32 * BOARD_SIZE;
+    /// Premoves while the other side thinks
+    pub fn premove_inputs(&mut self, game: &Game) {
+        self.layout = Layout::current(self.flipped);
+        let was_dragging = self.input_handler.is_dragging();
+        let premoves = self.input_handler.premoves().len();
+        self.input_handler.handle_premove_input(game, &self.layout);
+        if self.input_handler.premoves().len() > premoves {
+            self.snap = was_dragging;
+        }
+    }
+
+    /// The next premove once it is our turn, None when there is none or it isn't legal
+    pub fn take_premove(&mut self, game: &Game) -> Option<Move> {
+        self.input_handler.take_premove(game)
+    }
+
+    /// The button of the game over box clicked this frame, call it while the box is shown
+    pub fn game_over_inputs(&mut self) -> Option<GameOverAction> {
+        self.layout = Layout::current(self.flipped);
+        game_over::update(self.layout.board)
+    }
+
+    /// Arrows and coloured squares drawn with the right mouse button, call it before the
+    /// moves are handled: a right-click that cancels a selection or premoves marks nothing
+    pub fn annotation_inputs(&mut self) {
+        self.layout = Layout::current(self.flipped);
+        let can_mark = !self.input_handler.has_pending();
+        self.annotations.update(&self.layout, can_mark);
+    }
+
+    /// Shows a short message under the side to move for a moment, ex: "Pondering on"
+    pub fn show_status(&mut self, text: impl Into<String>) {
+        self.status = Some((text.into(), Instant::now()));
+    }
+
+    /// The engine's arrows, drawn in blue for its best move and red for its threat
+    pub fn set_hint(&mut self, hint: Option<(Move, Option<Move>)>) {
+        self.hint = hint;
+    }
+
+    /// Clicks and scrolling in the move list, returns the position to show when a move was
+    /// clicked, see `GameRecord::go_to`
+    pub fn move_list_inputs(&mut self, record: &GameRecord) -> Option<usize> {
+        self.move_list.update(record, self.move_list_rect())
+    }
+
+    /// The right panel is the move list, below the analysis when it is shown
+    fn move_list_rect(&self) -> Rect {
+        let panel = self.layout.right_panel;
+        if self.analysis_shown {
+            let top = panel.h * 0.3;
+            Rect::new(panel.x, panel.y + top, panel.w, panel.h - top)
+        } else {
+            panel
+        }
+    }
+
+    fn analysis_rect(&self) -> Rect {
+        let panel = self.layout.right_panel;
+        let margin = self.layout.margin();
+        Rect::new(panel.x, panel.y, panel.w, panel.h * 0.3 - margin)
+    }
+
+    pub fn render(
+        &mut self,
+        record: &GameRecord,
+        clock: Option<&Clock>,
+        analysis: Option<&Analysis>,
+        game_over: Option<(GameResult, Termination)>,
+    ) {
+        let game = record.game();
+        // the pieces stand where the premoves put them
+        let position = self.input_handler.premove_board(&game.board);
+        self.layout = Layout::current(self.flipped);
+        self.animate(&position);
 
         clear_background(DARKGRAY);
 
         self.render_board();
-        self.light_up_previous_move();
-        self.render_pieces(position);
-        self.light_up_moves(game);
-        self.text(game);
+        self.light_up_previous_move(record.last_move());
+        self.light_up_premoves();
+        self.light_up_selection();
+        self.annotations.render_squares(&self.layout);
+        self.render_coordinates();
+        self.render_pieces(&position);
+        self.render_hint();
+        self.annotations.render_arrows(&self.layout);
+        self.light_up_moves(game, &position);
+        self.analysis_shown = analysis.is_some();
+        if let Some(analysis) = analysis {
+            let info = analysis.info();
+            analysis_panel::render_eval_bar(
+                self.layout.eval_bar,
+                game,
+                info.as_ref(),
+                self.flipped,
+            );
+            analysis_panel::render_analysis(self.analysis_rect(), game, info.as_ref());
+        }
+        self.move_list.render(record, self.move_list_rect());
+        if let Some(clock) = clock {
+            self.render_clocks(clock);
+        }
+        self.text(game, game_over.is_some());
+        self.render_status();
+        if let Some((result, termination)) = game_over {
+            game_over::render(self.layout.board, result, termination);
+        }
+    }
+
+    /// Starts animating when the board changed since the last frame
+    fn animate(&mut self, position: &Board) {
+        if let Some(shown) = &self.shown {
+            if let Some(animation) = Animation::new(shown, position, self.animation_duration) {
+                self.animation = Some(animation).filter(|_| !self.snap);
+            }
+        }
+        if self.animation.as_ref().is_some_and(|a| a.is_finished()) {
+            self.animation = None;
+        }
+        self.shown = Some(position.clone());
+        self.snap = false;
+    }
+
+    /// The status message in the left panel, fading out before it goes away
+    fn render_status(&mut self) {
+        let Some((text, shown)) = &self.status else {
+            return;
+        };
+        let elapsed = shown.elapsed();
+        if elapsed >= STATUS_DURATION {
+            self.status = None;
+            return;
+        }
+        let fade = STATUS_DURATION / 3;
+        let alpha = (STATUS_DURATION - elapsed).as_secs_f32() / fade.as_secs_f32();
+        let panel = self.layout.left_panel;
+        let font_size = self.layout.square_size() * 0.35;
+        draw_text(
+            text,
+            panel.x,
+            panel.y + panel.h / 2.0 + font_size * 1.5,
+            font_size,
+            Color::new(1.0, 1.0, 1.0, alpha.min(1.0)),
+        );
+    }
+
+    /// The clock of the side at the top of the board at the top of the left panel,
+    /// the other one at the bottom. A clock that runs low turns red.
+    fn render_clocks(&self, clock: &Clock) {
+        let panel = self.layout.left_panel;
+        let height = self.layout.square_size() * 0.6;
+        let low_time = (clock.settings().base / 10).min(Duration::from_secs(20));
+        for (color, y) in [
+            (self.flipped, panel.y),
+            (!self.flipped, panel.bottom() - height),
+        ] {
+            let remaining = clock.remaining(color);
+            let background = if remaining < low_time {
+                color_u8!(0xb0, 0x30, 0x28, 0xff)
+            } else if clock.running() == Some(color) {
+                color_u8!(0x5a, 0x7a, 0xa8, 0xff)
+            } else {
+                color_u8!(0x30, 0x2e, 0x2b, 0xff)
+            };
+            draw_rectangle(panel.x, y, panel.w, height, background);
+            let text = format_clock(remaining);
+            let size = measure_text(&text, None, (height * 0.8) as u16, 1.0);
+            draw_text(
+                &text,
+                panel.right() - size.width - height * 0.2,
+                y + (height + size.offset_y) / 2.0,
+                height * 0.8,
+                WHITE,
+            );
+        }
+    }
+
+    fn render_hint(&self) {
+        if let Some((best_move, threat)) = self.hint {
+            if let Some(threat) = threat {
+                annotations::draw_arrow(&self.layout, &threat, annotations::RED);
+            }
+            annotations::draw_arrow(&self.layout, &best_move, annotations::BLUE);
+        }
     }
 
     fn render_board(&self) {
+        let board = self.layout.board;
         draw_texture_ex(
             self.textures.get_board(),
-            screen_width() / 2.0 - self.board_size / 4.0,
-            screen_height() / 2.0 - self.board_size / 2.0,
+            board.x,
+            board.y,
             WHITE,
             DrawTextureParams {
-                dest_size: Some(vec2(self.board_size, self.board_size)),
+                dest_size: Some(vec2(board.w, board.h)),
                 ..Default::default()
             },
         );
     }
 
+    /// a-h along the bottom edge and 1-8 along the left edge of the board
+    fn render_coordinates(&self) {
+        let square_size = self.layout.square_size();
+        let font_size = square_size * 0.25;
+        let padding = square_size * 0.05;
+        // the colour of the other squares, a8 and h1 are light
+        let color = |column: u32, row: u32| {
+            if (column + row).is_multiple_of(2) {
+                color_u8!(0x18, 0x16, 0x12, 0xa0)
+            } else {
+                color_u8!(0xff, 0xff, 0xff, 0xc0)
+            }
+        };
+        for i in 0..8 {
+            // the squares in the bottom row and the left column of the screen
+            let (column, row) = self.layout.screen_square(i, 7);
+            let square = self.layout.square_rect(column, row);
+            let file = ((b'a' + column as u8) as char).to_string();
+            let size = measure_text(&file, None, font_size as u16, 1.0);
+            draw_text(
+                &file,
+                square.right() - size.width - padding,
+                square.bottom() - padding,
+                font_size,
+                color(i, 7),
+            );
+
+            let (column, row) = self.layout.screen_square(0, i);
+            let square = self.layout.square_rect(column, row);
+            let rank = (8 - row).to_string();
+            let size = measure_text(&rank, None, font_size as u16, 1.0);
+            draw_text(
+                &rank,
+                square.x + padding,
+                square.y + padding + size.offset_y,
+                font_size,
+                color(0, i),
+            );
+        }
+    }
+
     fn render_pieces(&self, position: &Board) {
+        let piece_size = self.layout.square_size() * PIECE_SIZE;
         for column in 0..8 {
             for row in 0..8 {
                 if let Some(piece) = position.get_piece_pos(column, row) {
                     let texture = self.textures.get_texture(piece);
 
-                    if self.input_handler.get_picked_piece_pos() == Some(1 << (column + row * 8)) {
+                    let sliding = self
+                        .animation
+                        .as_ref()
+                        .is_some_and(|a| a.is_sliding_to(column, row));
+                    if sliding
+                        || self.input_handler.is_dragging()
+                            && self.input_handler.get_picked_piece_pos()
+                                == Some(1 << (column + row * 8))
+                    {
                         continue;
                     } else {
+                        let rect = self.layout.inner_rect(column, row, PIECE_SIZE);
                         draw_texture_ex(
                             texture,
-                            screen_width() / 2.0 - self.board_size / 4.0
-                                + self.board_size / 8.0 * column as f32
-                                + self.board_size / 8.0 * (1.0 - PIECE_SIZE) / 2.0,
-                            screen_height() / 2.0 - self.board_size / 2.0
-                                + self.board_size / 8.0 * row as f32
-                                + self.board_size / 8.0 * (1.0 - PIECE_SIZE) / 2.0,
+                            rect.x,
+                            rect.y,
                             WHITE,
                             DrawTextureParams {
-                                dest_size: Some(vec2(
-                                    self.board_size / 8.0 * PIECE_SIZE,
-                                    self.board_size / 8.0 * PIECE_SIZE,
-                                )),
+                                dest_size: Some(vec2(rect.w, rect.h)),
                                 ..Default::default()
                             },
                         );
@@ -103,56 +379,53 @@ impl Renderer {
                 }
             }
         }
+        if let Some(animation) = &self.animation {
+            animation.render(&self.layout, &self.textures);
+        }
         // draw picked piece
-        if let Some(piece_pos) = self.input_handler.get_picked_piece_pos() {
-            let (column, row) = bitboard_to_coordinates(piece_pos);
-            let piece = position.get_piece_pos(column, row).unwrap();
+        if let (true, Some(piece)) = (
+            self.input_handler.is_dragging(),
+            self.input_handler.get_picked_piece(),
+        ) {
             let texture = self.textures.get_texture(piece);
 
             draw_texture_ex(
                 texture,
-                mouse_position().0 - self.board_size / 8.0 * PIECE_SIZE / 2.0,
-                mouse_position().1 - self.board_size / 8.0 * PIECE_SIZE / 2.0,
+                mouse_position().0 - piece_size / 2.0,
+                mouse_position().1 - piece_size / 2.0,
                 WHITE,
                 DrawTextureParams {
-                    dest_size: Some(vec2(
-                        self.board_size / 8.0 * PIECE_SIZE,
-                        self.board_size / 8.0 * PIECE_SIZE,
-                    )),
+                    dest_size: Some(vec2(piece_size, piece_size)),
                     ..Default::default()
                 },
             );
         }
     }
 
-    fn light_up_moves(&self, game: &Game) {
+    fn light_up_moves(&self, game: &Game, position: &Board) {
         // light up allowed moves
-        if let Some(piece_pos) = self.input_handler.get_picked_piece_pos() {
-            let (column, row) = bitboard_to_coordinates(piece_pos);
-            let allowed_moves = game.board.get_allowed_moves(column, row, game.turn());
+        if self.input_handler.get_picked_piece_pos().is_some() {
+            let allowed_moves = self.input_handler.targets(game);
+            let square_size = self.layout.square_size();
 
             for column in 0..8 {
                 for row in 0..8 {
                     if allowed_moves & (1 << (column + row * 8)) != 0 {
-                        let board_pos = vec2(
-                            screen_width() / 2.0 - self.board_size / 4.0,
-                            screen_height() / 2.0 - self.board_size / 2.0,
-                        );
-                        let board_pos = vec2(board_pos.x, board_pos.y);
+                        let center = self.layout.square_center(column, row);
 
-                        if game.board.get_piece_pos(column, row).is_some() {
+                        if position.get_piece_pos(column, row).is_some() {
                             draw_circle_lines(
-                                board_pos.x + self.board_size / 8.0 * (column as f32 + 0.5),
-                                board_pos.y + self.board_size / 8.0 * (row as f32 + 0.5),
-                                self.board_size / 8.0 * 0.45,
+                                center.x,
+                                center.y,
+                                square_size * 0.45,
                                 5.0,
                                 color_u8!(0x18, 0x16, 0x12, 0x30),
                             );
                         } else {
                             draw_circle(
-                                board_pos.x + self.board_size / 8.0 * (column as f32 + 0.5),
-                                board_pos.y + self.board_size / 8.0 * (row as f32 + 0.5),
-                                self.board_size / 8.0 * 0.2,
+                                center.x,
+                                center.y,
+                                square_size * 0.2,
                                 color_u8!(0x18, 0x16, 0x12, 0x30),
                             );
                         }
@@ -162,61 +435,102 @@ impl Renderer {
         }
     }
 
-    fn light_up_previous_move(&self) {
-        if let Some(last_move) = self.last_move {
-            let board_pos = vec2(
-                screen_width() / 2.0 - self.board_size / 4.0,
-                screen_height() / 2.0 - self.board_size / 2.0,
-            );
-            let board_pos = vec2(board_pos.x, board_pos.y);
-
+    /// The square of the selected piece, for click-to-move
+    fn light_up_selection(&self) {
+        if let Some(piece_pos) = self.input_handler.get_picked_piece_pos() {
+            let (column, row) = bitboard_to_coordinates(piece_pos);
+            let square = self.layout.square_rect(column, row);
             draw_rectangle(
-                board_pos.x + self.board_size / 8.0 * (last_move.column_from as f32),
-                board_pos.y + self.board_size / 8.0 * (last_move.row_from as f32),
-                self.board_size / 8.0,
-                self.board_size / 8.0,
-                // yellow ish
-                color_u8!(0xff, 0xff, 0x20, 0x40),
+                square.x,
+                square.y,
+                square.w,
+                square.h,
+                color_u8!(0x14, 0x55, 0x1e, 0x80),
             );
+        }
+    }
 
-            draw_rectangle(
-                board_pos.x + self.board_size / 8.0 * (last_move.column_to as f32),
-                board_pos.y + self.board_size / 8.0 * (last_move.row_to as f32),
-                self.board_size / 8.0,
-                self.board_size / 8.0,
-                color_u8!(0xff, 0xff, 0x30, 0x40),
-            );
+    /// The squares of the queued premoves, in red
+    fn light_up_premoves(&self) {
+        for premove in self.input_handler.premoves() {
+            for (column, row) in [
+                (premove.column_from, premove.row_from),
+                (premove.column_to, premove.row_to),
+            ] {
+                let square = self.layout.square_rect(column, row);
+                draw_rectangle(
+                    square.x,
+                    square.y,
+                    square.w,
+                    square.h,
+                    color_u8!(0xc8, 0x32, 0x28, 0x60),
+                );
+            }
         }
     }
 
-    fn text(&self, game: &Game) {
-        let board_pos = vec2(
-            screen_width() / 2.0 - self.board_size / 4.0,
-            screen_height() / 2.0 - self.board_size / 2.0,
-        );
-        let board_pos = vec2(board_pos.x, board_pos.y);
+    fn light_up_previous_move(&self, last_move: Option<Move>) {
+        if let Some(last_move) = last_move {
+            let squares = [
+                (last_move.column_from, last_move.row_from),
+                (last_move.column_to, last_move.row_to),
+            ];
+            // yellow ish
+            let colors = [
+                color_u8!(0xff, 0xff, 0x20, 0x40),
+                color_u8!(0xff, 0xff, 0x30, 0x40),
+            ];
+            for ((column, row), color) in squares.into_iter().zip(colors) {
+                let square = self.layout.square_rect(column, row);
+                draw_rectangle(square.x, square.y, square.w, square.h, color);
+            }
+        }
+    }
 
-        let text = format!("{} to move", if game.turn() { "White" } else { "Black" });
+    /// Whose move it is, unless the game is over, and the material of both sides
+    fn text(&self, game: &Game, game_over: bool) {
+        let board = self.layout.board;
+        let panel = self.layout.left_panel;
 
-        draw_text(&text, 120., board_pos.y + 100.0, 50.0, WHITE);
+        if !game_over {
+            let text = format!("{} to move", if game.turn() { "White" } else { "Black" });
+            draw_text(&text, panel.x, panel.y + panel.h / 2.0, 50.0, WHITE);
+        }
 
-        let text = format!(
+        let white = format!(
             "{}{}",
             if game.points >= 0 { "+" } else { "-" },
             game.points / 100
         );
-        draw_text(&text, board_pos.x, board_pos.y - 10.0, 30.0, WHITE);
-        let text = format!(
+        let black = format!(
             "{}{}",
             if game.points <= 0 { "+" } else { "-" },
             game.points / 100
         );
-        draw_text(
-            &text,
-            board_pos.x,
-            board_pos.y + self.board_size + 20.0,
-            30.0,
-            WHITE,
-        );
+        // black's text above the board and white's below it, unless the board is flipped
+        let (top, bottom) = if self.flipped {
+            (white, black)
+        } else {
+            (black, white)
+        };
+        draw_text(&top, board.x, board.y - 10.0, 30.0, WHITE);
+        draw_text(&bottom, board.x, board.bottom() + 20.0, 30.0, WHITE);
+    }
+}
+
+/// The time on a clock, ex: "1:05:00", "4:59" or "0:09.3" in the last ten seconds
+fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else if seconds >= 10 {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        format!("0:{:02}.{}", seconds, time.subsec_millis() / 100)
     }
 }